@@ -0,0 +1,177 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar::{self, instructions::{load_current_index_checked, load_instruction_at_checked}},
+};
+
+/// Number of members the allowlist of each role holds.
+pub const MAX_ROLE_MEMBERS: usize = 8;
+
+/// Roles the authority hands out on top of the delegate, who may do what all of them may.
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Clone, Copy)]
+pub enum Role {
+    /// May push elements.
+    Pusher,
+    /// May pop and remove elements.
+    Popper,
+    /// May extend the data accounts and grant and revoke the pusher and popper roles.
+    Admin,
+}
+
+/// Allowlists of the roles as the metas store them, empty slots hold the default pubkey.
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug, Default)]
+pub struct Roles {
+    pub pushers: [Pubkey; MAX_ROLE_MEMBERS],
+    pub poppers: [Pubkey; MAX_ROLE_MEMBERS],
+    pub admins: [Pubkey; MAX_ROLE_MEMBERS],
+}
+
+impl Roles {
+    pub fn members(&self, role: Role) -> &[Pubkey; MAX_ROLE_MEMBERS] {
+        match role {
+            Role::Pusher => &self.pushers,
+            Role::Popper => &self.poppers,
+            Role::Admin => &self.admins,
+        }
+    }
+
+    fn members_mut(&mut self, role: Role) -> &mut [Pubkey; MAX_ROLE_MEMBERS] {
+        match role {
+            Role::Pusher => &mut self.pushers,
+            Role::Popper => &mut self.poppers,
+            Role::Admin => &mut self.admins,
+        }
+    }
+
+    pub fn has_role(&self, key: &Pubkey, role: Role) -> bool {
+        *key != Pubkey::default() && self.members(role).contains(key)
+    }
+
+    /// Adds `member` to the allowlist of `role`. Returns false if the allowlist is full.
+    pub fn grant(&mut self, role: Role, member: Pubkey) -> bool {
+        if self.has_role(&member, role) {
+            return true;
+        }
+        match self.members_mut(role).iter_mut().find(|slot| **slot == Pubkey::default()) {
+            Some(slot) => {
+                *slot = member;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn revoke(&mut self, role: Role, member: &Pubkey) {
+        for slot in self.members_mut(role).iter_mut() {
+            if slot == member {
+                *slot = Pubkey::default();
+            }
+        }
+    }
+}
+
+/// The fields of a meta that decide who may change the structure.
+pub trait Access {
+    /// The program's own `Unauthorized` error, which the checks fail with.
+    fn unauthorized() -> ProgramError;
+    fn authority(&self) -> &Pubkey;
+    fn delegate(&self) -> &Pubkey;
+    fn roles(&self) -> &Roles;
+    /// Whether only calls from other programs may change the structure, see `check_cpi_only`.
+    fn cpi_only(&self) -> bool;
+}
+
+/// Checks that `signer` signed the transaction and is the authority itself.
+pub fn check_authority<M: Access>(meta: &M, signer: &AccountInfo) -> ProgramResult {
+    if !signer.is_signer || signer.key != meta.authority() {
+        msg!("Missing authority signature");
+        return Err(M::unauthorized());
+    }
+    Ok(())
+}
+
+/// Checks that `signer` signed the transaction and is the authority, the delegate stored in the
+/// meta or a member of `role`.
+pub fn check_role<M: Access>(meta: &M, signer: &AccountInfo, role: Role) -> ProgramResult {
+    let is_delegate = *meta.delegate() != Pubkey::default() && signer.key == meta.delegate();
+    if !signer.is_signer || (signer.key != meta.authority() && !is_delegate && !meta.roles().has_role(signer.key, role)) {
+        msg!("Missing authority signature");
+        return Err(M::unauthorized());
+    }
+    Ok(())
+}
+
+/// Checks that `signer` signed the transaction and may grant and revoke `role`: the authority
+/// manages every role, admins the pusher and popper roles.
+pub fn check_role_manager<M: Access>(meta: &M, signer: &AccountInfo, role: Role) -> ProgramResult {
+    let is_admin = role != Role::Admin && meta.roles().has_role(signer.key, Role::Admin);
+    if !signer.is_signer || (signer.key != meta.authority() && !is_admin) {
+        msg!("Missing authority signature");
+        return Err(M::unauthorized());
+    }
+    Ok(())
+}
+
+/// Rejects top-level calls to a `cpi_only` structure, which only other programs may change. Such
+/// calls pass the instructions sysvar, the current top-level instruction in it is the caller's.
+pub fn check_cpi_only<M: Access>(meta: &M, instructions_sysvar: Option<&AccountInfo>, program_id: &Pubkey) -> ProgramResult {
+    if !meta.cpi_only() {
+        return Ok(());
+    }
+    let instructions_sysvar = instructions_sysvar.ok_or(ProgramError::NotEnoughAccountKeys)?;
+    if *instructions_sysvar.key != sysvar::instructions::id() {
+        msg!("Expected the instructions sysvar");
+        return Err(ProgramError::InvalidArgument);
+    }
+    let current_index = load_current_index_checked(instructions_sysvar)?;
+    let current_instruction = load_instruction_at_checked(current_index as usize, instructions_sysvar)?;
+    if current_instruction.program_id == *program_id {
+        msg!("Only calls from other programs are accepted");
+        return Err(M::unauthorized());
+    }
+    Ok(())
+}
+
+/// Splits off the instructions sysvar that calls to a `cpi_only` structure pass after the data
+/// accounts and runs `check_cpi_only` on it.
+pub fn check_cpi_only_accounts<M: Access>(meta: &M, data_accounts: &mut Vec<&AccountInfo>, program_id: &Pubkey) -> ProgramResult {
+    let instructions_sysvar = if meta.cpi_only() { data_accounts.pop() } else { None };
+    check_cpi_only(meta, instructions_sysvar, program_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grants_and_revokes_roles() {
+        let mut roles = Roles::default();
+        let members: Vec<Pubkey> = (1..=MAX_ROLE_MEMBERS as u8).map(|i| Pubkey::new_from_array([i; 32])).collect();
+        for member in members.iter() {
+            assert!(roles.grant(Role::Pusher, *member));
+        }
+        assert!(roles.grant(Role::Pusher, members[0]));
+        assert!(!roles.grant(Role::Pusher, Pubkey::new_from_array([255; 32])));
+        assert!(roles.has_role(&members[3], Role::Pusher));
+        assert!(!roles.has_role(&members[3], Role::Popper));
+        assert!(!roles.has_role(&Pubkey::default(), Role::Admin));
+
+        roles.revoke(Role::Pusher, &members[3]);
+        assert!(!roles.has_role(&members[3], Role::Pusher));
+        assert!(roles.grant(Role::Pusher, Pubkey::new_from_array([255; 32])));
+    }
+
+    #[test]
+    fn serializes_as_the_three_allowlists() {
+        let mut roles = Roles::default();
+        roles.grant(Role::Admin, Pubkey::new_from_array([1; 32]));
+        let data = roles.try_to_vec().unwrap();
+        assert_eq!(data.len(), 3 * MAX_ROLE_MEMBERS * 32);
+        assert_eq!(data[2 * MAX_ROLE_MEMBERS * 32], 1);
+    }
+}
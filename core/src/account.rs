@@ -0,0 +1,95 @@
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_instruction,
+    system_program,
+    sysvar::rent::Rent,
+};
+
+/// Creates the PDA `account` with `space` bytes, owned by `program_id`, with `payer` paying the
+/// rent. Anyone can send lamports to the address beforehand, which makes `create_account` fail, so
+/// an address that already holds lamports is topped up, allocated and assigned instead.
+pub fn create_pda_account<'a>(
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    space: u64,
+    seeds: &[&[u8]],
+    rent: &Rent,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    let required_lamports = rent.minimum_balance(space as usize);
+    if account.lamports() == 0 {
+        return invoke_signed(
+            &system_instruction::create_account(payer.key, account.key, required_lamports, space, program_id),
+            &[payer.clone(), account.clone(), system_program.clone()],
+            &[seeds],
+        );
+    }
+
+    top_up(payer, system_program, account, space, rent)?;
+    invoke_signed(
+        &system_instruction::allocate(account.key, space),
+        &[account.clone(), system_program.clone()],
+        &[seeds],
+    )?;
+    invoke_signed(
+        &system_instruction::assign(account.key, program_id),
+        &[account.clone(), system_program.clone()],
+        &[seeds],
+    )
+}
+
+/// Grows `account`, which the calling program owns, to `space` bytes, with `payer` topping up the
+/// rent the larger account needs.
+pub fn grow_account<'a>(
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    space: u64,
+    rent: &Rent,
+) -> ProgramResult {
+    top_up(payer, system_program, account, space, rent)?;
+    account.realloc(space as usize, true)
+}
+
+/// Transfers what `account` lacks of the rent exemption of `space` bytes from `payer`.
+fn top_up<'a>(
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    account: &AccountInfo<'a>,
+    space: u64,
+    rent: &Rent,
+) -> ProgramResult {
+    let required_lamports = rent.minimum_balance(space as usize).saturating_sub(account.lamports());
+    if required_lamports > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, account.key, required_lamports),
+            &[payer.clone(), account.clone(), system_program.clone()],
+        )?;
+    }
+    Ok(())
+}
+
+/// Moves all lamports of `account` to `destination`, shrinks its data to nothing and hands it back
+/// to the system program. A closed meta then fails the meta checks of the programs for the rest of
+/// the transaction, so the structure cannot be used again. If someone refunds the address in the
+/// same transaction it stays an empty system account, which `Initialize` or `Extend` can create
+/// again.
+pub fn close_account(account: &AccountInfo, destination: &AccountInfo) -> ProgramResult {
+    let mut destination_lamports = destination.lamports.borrow_mut();
+    let mut account_lamports = account.lamports.borrow_mut();
+
+    **destination_lamports = destination_lamports
+        .checked_add(**account_lamports)
+        .ok_or(ProgramError::InvalidArgument)?;
+    **account_lamports = 0;
+
+    account.realloc(0, false)?;
+    account.assign(&system_program::id());
+
+    Ok(())
+}
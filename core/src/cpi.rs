@@ -0,0 +1,92 @@
+//! Plumbing the `cpi` modules of the programs share to call a program from another on-chain
+//! program.
+
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program::{get_return_data, invoke_signed},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    sysvar,
+};
+
+use crate::return_data::unpack_elements;
+
+/// Accounts of a data structure and the seeds the calling program signs for `authority` with.
+pub struct CpiContext<'a, 'info> {
+    pub program: AccountInfo<'info>,
+    pub authority: AccountInfo<'info>,
+    pub meta: AccountInfo<'info>,
+    /// Data accounts the calls pass, each with its page index, see `find_data_account_addresses` of
+    /// the program.
+    pub data_accounts: Vec<(u32, AccountInfo<'info>)>,
+    pub signer_seeds: &'a [&'a [&'a [u8]]],
+    pub instructions_sysvar: Option<AccountInfo<'info>>,
+}
+
+impl<'a, 'info> CpiContext<'a, 'info> {
+    pub fn new(
+        program: AccountInfo<'info>,
+        authority: AccountInfo<'info>,
+        meta: AccountInfo<'info>,
+        data_accounts: Vec<(u32, AccountInfo<'info>)>,
+    ) -> Self {
+        Self { program, authority, meta, data_accounts, signer_seeds: &[], instructions_sysvar: None }
+    }
+
+    pub fn with_signer(mut self, signer_seeds: &'a [&'a [&'a [u8]]]) -> Self {
+        self.signer_seeds = signer_seeds;
+        self
+    }
+
+    /// Passes the instructions sysvar with every call that changes the structure, as a `cpi_only`
+    /// structure requires.
+    pub fn with_instructions_sysvar(mut self, instructions_sysvar: AccountInfo<'info>) -> Self {
+        self.instructions_sysvar = Some(instructions_sysvar);
+        self
+    }
+
+    /// Page indices and keys of the data accounts, as the instruction builders take them.
+    pub fn data_account_keys(&self) -> Vec<(u32, Pubkey)> {
+        self.data_accounts.iter().map(|(page, account)| (*page, *account.key)).collect()
+    }
+}
+
+/// Appends the instructions sysvar, which calls that change a `cpi_only` structure pass last.
+pub fn with_instructions_sysvar(mut instruction: Instruction) -> Instruction {
+    instruction.accounts.push(AccountMeta::new_readonly(sysvar::instructions::id(), false));
+    instruction
+}
+
+/// Invokes the program with the context's accounts and any `extra_accounts`, and the instructions
+/// sysvar last if the context has it.
+pub fn invoke<'info>(ctx: &CpiContext<'_, 'info>, instruction: &Instruction, extra_accounts: &[AccountInfo<'info>]) -> ProgramResult {
+    let mut account_infos = vec![ctx.authority.clone(), ctx.meta.clone()];
+    account_infos.extend(ctx.data_accounts.iter().map(|(_, account)| account.clone()));
+    account_infos.extend(extra_accounts.iter().cloned());
+    match &ctx.instructions_sysvar {
+        Some(instructions_sysvar) => {
+            account_infos.push(instructions_sysvar.clone());
+            invoke_signed(&with_instructions_sysvar(instruction.clone()), &account_infos, ctx.signer_seeds)
+        }
+        None => invoke_signed(instruction, &account_infos, ctx.signer_seeds),
+    }
+}
+
+/// Invokes a read, which takes no instructions sysvar even on a `cpi_only` structure.
+pub fn invoke_read(ctx: &CpiContext, instruction: &Instruction) -> ProgramResult {
+    let mut account_infos = vec![ctx.meta.clone()];
+    account_infos.extend(ctx.data_accounts.iter().map(|(_, account)| account.clone()));
+    invoke_signed(instruction, &account_infos, ctx.signer_seeds)
+}
+
+/// Decodes the elements of `element_size` bytes the program just returned, only as many as fit in
+/// the return data, see `pack_elements`.
+pub fn read_elements(ctx: &CpiContext, element_size: u64) -> Result<Vec<Vec<u8>>, ProgramError> {
+    let (program_id, data) = get_return_data().ok_or(ProgramError::InvalidAccountData)?;
+    if program_id != *ctx.program.key {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    unpack_elements(&data, element_size)
+}
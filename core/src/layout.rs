@@ -0,0 +1,52 @@
+/// Reads the raw meta `data` of a layout version `1..=meta_lens.len() - 1` as the data of the
+/// current one, the last version in `meta_lens`, and returns it with the version it was written in.
+/// Metas start with `discriminator` and their version byte, `meta_lens` holds their length in every
+/// version. Fields are only ever appended, so an older meta reads as the current one with the new
+/// fields zeroed. Returns `None` for anything else.
+pub fn read_appended_meta(data: &[u8], discriminator: &[u8; 8], meta_lens: &[u64]) -> Option<(Vec<u8>, u8)> {
+    if data.len() < 9 || data[..8] != discriminator[..] {
+        return None;
+    }
+    let version = data[8];
+    let (&current_len, _) = meta_lens.split_last()?;
+    if version == 0 || meta_lens.get(version as usize) != Some(&(data.len() as u64)) {
+        return None;
+    }
+    let mut current = data.to_vec();
+    current.resize(current_len as usize, 0);
+    Some((current, version))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DISCRIMINATOR: [u8; 8] = *b"testmeta";
+    const META_LENS: [u64; 3] = [4, 10, 12];
+
+    #[test]
+    fn zero_extends_older_metas() {
+        let mut data = DISCRIMINATOR.to_vec();
+        data.push(1);
+        data.push(7);
+        let (current, version) = read_appended_meta(&data, &DISCRIMINATOR, &META_LENS).unwrap();
+        assert_eq!(version, 1);
+        assert_eq!(current.len(), 12);
+        assert_eq!(&current[..10], &data[..]);
+        assert_eq!(&current[10..], &[0, 0]);
+    }
+
+    #[test]
+    fn rejects_unknown_layouts() {
+        let mut data = DISCRIMINATOR.to_vec();
+        data.extend_from_slice(&[2, 0, 0, 0]);
+        assert!(read_appended_meta(&data, &DISCRIMINATOR, &META_LENS).is_some());
+        assert!(read_appended_meta(&data[..11], &DISCRIMINATOR, &META_LENS).is_none());
+        assert!(read_appended_meta(&data, b"othermet", &META_LENS).is_none());
+
+        data[8] = 3;
+        assert!(read_appended_meta(&data, &DISCRIMINATOR, &META_LENS).is_none());
+        data[8] = 0;
+        assert!(read_appended_meta(&data, &DISCRIMINATOR, &META_LENS).is_none());
+    }
+}
@@ -0,0 +1,8 @@
+pub mod access;
+pub mod account;
+pub mod cpi;
+pub mod layout;
+pub mod paged_storage;
+pub mod return_data;
+
+pub use paged_storage::PagedStorage;
@@ -0,0 +1,324 @@
+use std::{cell::RefMut, convert::TryFrom};
+
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+};
+
+/// Fixed-size elements laid out back to back over a list of data accounts ("pages").
+/// Element `index` lives in page `index / elements_per_page` at byte
+/// `header_len + (index % elements_per_page) * element_size` of that page, after the header every
+/// page starts with. Only the pages an instruction touches need to be passed, each with its page
+/// index; touching an element on a page that was not passed fails with `NotEnoughAccountKeys`.
+///
+/// The data of every page stays mutably borrowed for as long as the storage lives. Reading
+/// through it does not write anything, so read-only accounts can back a storage that is only
+/// read from.
+pub struct PagedStorage<'a, 'info> {
+    page_indices: Vec<u32>,
+    pages: Vec<RefMut<'a, &'info mut [u8]>>,
+    element_size: usize,
+    elements_per_page: u64,
+    header_len: usize,
+}
+
+impl<'a, 'info> PagedStorage<'a, 'info> {
+    /// Backs the storage with `accounts`, the pages at the increasing page indices `page_indices`.
+    pub fn new(
+        page_indices: &[u32],
+        accounts: &[&'a AccountInfo<'info>],
+        element_size: u64,
+        elements_per_page: u64,
+        header_len: u64,
+    ) -> Result<Self, ProgramError> {
+        if element_size == 0 || elements_per_page == 0 || page_indices.len() != accounts.len() {
+            return Err(ProgramError::InvalidArgument);
+        }
+        if page_indices.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let mut pages = Vec::with_capacity(accounts.len());
+        for account in accounts.iter() {
+            pages.push(account.try_borrow_mut_data()?);
+        }
+        Ok(Self { page_indices: page_indices.to_vec(), pages, element_size: element_size as usize, elements_per_page, header_len: header_len as usize })
+    }
+
+    pub fn element_size(&self) -> usize {
+        self.element_size
+    }
+
+    /// Position in `pages` of the page holding element `index` and the byte offset of the element.
+    fn locate(&self, index: u64) -> Result<(usize, usize), ProgramError> {
+        let page_index = u32::try_from(index / self.elements_per_page).map_err(|_| ProgramError::AccountDataTooSmall)?;
+        let page = self.page_indices.binary_search(&page_index).map_err(|_| ProgramError::NotEnoughAccountKeys)?;
+        let offset = self.header_len + (index % self.elements_per_page) as usize * self.element_size;
+        if offset + self.element_size > self.pages[page].len() {
+            return Err(ProgramError::AccountDataTooSmall);
+        }
+        Ok((page, offset))
+    }
+
+    /// Borrows element `index` in place.
+    pub fn element(&self, index: u64) -> Result<&[u8], ProgramError> {
+        let (page, offset) = self.locate(index)?;
+        Ok(&self.pages[page][offset..offset + self.element_size])
+    }
+
+    pub fn read(&self, index: u64) -> Result<Vec<u8>, ProgramError> {
+        Ok(self.element(index)?.to_vec())
+    }
+
+    /// Reads `count` elements starting at `start`.
+    pub fn read_range(&self, start: u64, count: u64) -> Result<Vec<Vec<u8>>, ProgramError> {
+        let mut elements = Vec::with_capacity(count as usize);
+        for i in 0..count {
+            elements.push(self.read(start + i)?);
+        }
+        Ok(elements)
+    }
+
+    pub fn write(&mut self, index: u64, element: &[u8]) -> ProgramResult {
+        if element.len() != self.element_size {
+            return Err(ProgramError::InvalidArgument);
+        }
+        let (page, offset) = self.locate(index)?;
+        self.pages[page][offset..offset + self.element_size].copy_from_slice(element);
+        Ok(())
+    }
+
+    /// Writes the elements packed back to back in `data`, the first one at `start`.
+    pub fn write_range(&mut self, start: u64, data: &[u8]) -> ProgramResult {
+        if data.len() % self.element_size != 0 {
+            return Err(ProgramError::InvalidArgument);
+        }
+        for (i, element) in data.chunks(self.element_size).enumerate() {
+            self.write(start + i as u64, element)?;
+        }
+        Ok(())
+    }
+
+    /// Copies `count` elements from `src` to `dst`. Overlapping ranges are handled like `memmove`.
+    /// The elements move in runs that stay on one source and one destination page, one slice
+    /// copy per run.
+    pub fn copy_range(&mut self, src: u64, dst: u64, count: u64) -> ProgramResult {
+        if src == dst {
+            return Ok(());
+        }
+        let mut copied = 0;
+        while copied < count {
+            let remaining = count - copied;
+            // front to back when moving left and back to front when moving right, so that no
+            // element is overwritten before it is copied
+            let (offset, run) = if dst < src {
+                let run = remaining
+                    .min(self.elements_per_page - (src + copied) % self.elements_per_page)
+                    .min(self.elements_per_page - (dst + copied) % self.elements_per_page);
+                (copied, run)
+            } else {
+                let run = remaining
+                    .min((src + remaining - 1) % self.elements_per_page + 1)
+                    .min((dst + remaining - 1) % self.elements_per_page + 1);
+                (remaining - run, run)
+            };
+            self.copy_run(src + offset, dst + offset, run)?;
+            copied += run;
+        }
+        Ok(())
+    }
+
+    /// Copies `count` elements from `src` to `dst`, each range on a single page.
+    fn copy_run(&mut self, src: u64, dst: u64, count: u64) -> ProgramResult {
+        let (src_page, src_offset) = self.locate(src)?;
+        self.locate(src + count - 1)?;
+        let (dst_page, dst_offset) = self.locate(dst)?;
+        self.locate(dst + count - 1)?;
+        let len = count as usize * self.element_size;
+        if src_page == dst_page {
+            self.pages[src_page].copy_within(src_offset..src_offset + len, dst_offset);
+        } else if src_page < dst_page {
+            let (head, tail) = self.pages.split_at_mut(dst_page);
+            tail[0][dst_offset..dst_offset + len].copy_from_slice(&head[src_page][src_offset..src_offset + len]);
+        } else {
+            let (head, tail) = self.pages.split_at_mut(src_page);
+            head[dst_page][dst_offset..dst_offset + len].copy_from_slice(&tail[0][src_offset..src_offset + len]);
+        }
+        Ok(())
+    }
+
+    /// Reverses the order of the `count` elements starting at `start`.
+    pub fn reverse(&mut self, start: u64, count: u64) -> ProgramResult {
+        for i in 0..count / 2 {
+            self.swap(start + i, start + count - 1 - i)?;
+        }
+        Ok(())
+    }
+
+    /// Rotates the `count` elements starting at `start` left by `mid` places in place, so the
+    /// element at `start + mid` ends up at `start`.
+    pub fn rotate_left(&mut self, start: u64, count: u64, mid: u64) -> ProgramResult {
+        if mid > count {
+            return Err(ProgramError::InvalidArgument);
+        }
+        self.reverse(start, mid)?;
+        self.reverse(start + mid, count - mid)?;
+        self.reverse(start, count)
+    }
+
+    pub fn swap(&mut self, a: u64, b: u64) -> ProgramResult {
+        if a == b {
+            return Ok(());
+        }
+        let element_a = self.read(a)?;
+        let element_b = self.read(b)?;
+        self.write(a, &element_b)?;
+        self.write(b, &element_a)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::pubkey::Pubkey;
+
+    const ELEMENT_SIZE: u64 = 2;
+    const ELEMENTS_PER_PAGE: u64 = 3;
+    const HEADER_LEN: u64 = 1;
+    const PAGE_LEN: usize = (HEADER_LEN + ELEMENTS_PER_PAGE * ELEMENT_SIZE) as usize;
+
+    fn accounts<'a>(key: &'a Pubkey, lamports: &'a mut [u64], data: &'a mut [Vec<u8>]) -> Vec<AccountInfo<'a>> {
+        lamports
+            .iter_mut()
+            .zip(data.iter_mut())
+            .map(|(lamports, data)| AccountInfo::new(key, false, true, lamports, data, key, false, 0))
+            .collect()
+    }
+
+    /// Fills `count` elements with `[i, !i]`, so each one can be told apart by its first byte.
+    fn fill(storage: &mut PagedStorage, count: u64) {
+        for i in 0..count {
+            storage.write(i, &[i as u8, !(i as u8)]).unwrap();
+        }
+    }
+
+    fn first_bytes(storage: &PagedStorage, count: u64) -> Vec<u8> {
+        (0..count).map(|i| storage.element(i).unwrap()[0]).collect()
+    }
+
+    #[test]
+    fn locates_elements_across_pages() {
+        let key = Pubkey::new_unique();
+        let mut lamports = vec![0; 3];
+        let mut data = vec![vec![0xff; PAGE_LEN]; 3];
+        let accounts = accounts(&key, &mut lamports, &mut data);
+        let pages: Vec<&AccountInfo> = accounts.iter().collect();
+
+        let mut storage = PagedStorage::new(&[0, 1, 2], &pages, ELEMENT_SIZE, ELEMENTS_PER_PAGE, HEADER_LEN).unwrap();
+        fill(&mut storage, 9);
+        assert_eq!(storage.read(2).unwrap(), vec![2, !2]);
+        assert_eq!(storage.read(3).unwrap(), vec![3, !3]);
+        assert_eq!(storage.read_range(2, 3).unwrap(), vec![vec![2, !2], vec![3, !3], vec![4, !4]]);
+        drop(storage);
+
+        // the header of every page is left alone, element 3 is the first one of the second page
+        assert_eq!(*accounts[0].data.borrow(), [0xff, 0, !0, 1, !1, 2, !2]);
+        assert_eq!(*accounts[1].data.borrow(), [0xff, 3, !3, 4, !4, 5, !5]);
+        assert_eq!(*accounts[2].data.borrow(), [0xff, 6, !6, 7, !7, 8, !8]);
+    }
+
+    #[test]
+    fn rejects_out_of_range_elements() {
+        let key = Pubkey::new_unique();
+        let mut lamports = vec![0; 2];
+        // the second page only has room for one element
+        let mut data = vec![vec![0; PAGE_LEN], vec![0; (HEADER_LEN + ELEMENT_SIZE) as usize]];
+        let accounts = accounts(&key, &mut lamports, &mut data);
+        let pages: Vec<&AccountInfo> = accounts.iter().collect();
+
+        let mut storage = PagedStorage::new(&[0, 1], &pages, ELEMENT_SIZE, ELEMENTS_PER_PAGE, HEADER_LEN).unwrap();
+        assert!(storage.read(3).is_ok());
+        assert_eq!(storage.read(4), Err(ProgramError::AccountDataTooSmall));
+        assert_eq!(storage.read(6), Err(ProgramError::NotEnoughAccountKeys));
+        assert_eq!(storage.write(6, &[0, 0]), Err(ProgramError::NotEnoughAccountKeys));
+        assert_eq!(storage.copy_range(0, 3, 2), Err(ProgramError::AccountDataTooSmall));
+        assert_eq!(storage.write(0, &[0]), Err(ProgramError::InvalidArgument));
+    }
+
+    #[test]
+    fn copies_overlapping_ranges() {
+        let key = Pubkey::new_unique();
+        let mut lamports = vec![0; 3];
+        let mut data = vec![vec![0; PAGE_LEN]; 3];
+        let accounts = accounts(&key, &mut lamports, &mut data);
+        let pages: Vec<&AccountInfo> = accounts.iter().collect();
+
+        let mut storage = PagedStorage::new(&[0, 1, 2], &pages, ELEMENT_SIZE, ELEMENTS_PER_PAGE, HEADER_LEN).unwrap();
+        fill(&mut storage, 9);
+        // right, across a page boundary
+        storage.copy_range(1, 3, 5).unwrap();
+        assert_eq!(first_bytes(&storage, 9), vec![0, 1, 2, 1, 2, 3, 4, 5, 8]);
+        assert_eq!(storage.read(3).unwrap(), vec![1, !1]);
+
+        fill(&mut storage, 9);
+        // right, within a page
+        storage.copy_range(6, 7, 2).unwrap();
+        assert_eq!(first_bytes(&storage, 9), vec![0, 1, 2, 3, 4, 5, 6, 6, 7]);
+
+        fill(&mut storage, 9);
+        // left, across a page boundary
+        storage.copy_range(4, 2, 5).unwrap();
+        assert_eq!(first_bytes(&storage, 9), vec![0, 1, 4, 5, 6, 7, 8, 7, 8]);
+        assert_eq!(storage.read(2).unwrap(), vec![4, !4]);
+    }
+
+    #[test]
+    fn rotates_and_swaps_in_place() {
+        let key = Pubkey::new_unique();
+        let mut lamports = vec![0; 3];
+        let mut data = vec![vec![0; PAGE_LEN]; 3];
+        let accounts = accounts(&key, &mut lamports, &mut data);
+        let pages: Vec<&AccountInfo> = accounts.iter().collect();
+
+        let mut storage = PagedStorage::new(&[0, 1, 2], &pages, ELEMENT_SIZE, ELEMENTS_PER_PAGE, HEADER_LEN).unwrap();
+        fill(&mut storage, 9);
+        storage.rotate_left(1, 7, 3).unwrap();
+        assert_eq!(first_bytes(&storage, 9), vec![0, 4, 5, 6, 7, 1, 2, 3, 8]);
+        storage.rotate_left(0, 9, 0).unwrap();
+        storage.rotate_left(0, 9, 9).unwrap();
+        assert_eq!(first_bytes(&storage, 9), vec![0, 4, 5, 6, 7, 1, 2, 3, 8]);
+        assert_eq!(storage.rotate_left(0, 3, 4), Err(ProgramError::InvalidArgument));
+
+        storage.swap(0, 8).unwrap();
+        storage.swap(4, 4).unwrap();
+        assert_eq!(first_bytes(&storage, 9), vec![8, 4, 5, 6, 7, 1, 2, 3, 0]);
+        assert_eq!(storage.read(0).unwrap(), vec![8, !8]);
+        assert_eq!(storage.read(8).unwrap(), vec![0, !0]);
+    }
+
+    #[test]
+    fn locates_elements_on_passed_pages_only() {
+        let key = Pubkey::new_unique();
+        let mut lamports = vec![0; 2];
+        let mut data = vec![vec![0; PAGE_LEN]; 2];
+        let accounts = accounts(&key, &mut lamports, &mut data);
+        let pages: Vec<&AccountInfo> = accounts.iter().collect();
+
+        let mut storage = PagedStorage::new(&[1, 4], &pages, ELEMENT_SIZE, ELEMENTS_PER_PAGE, HEADER_LEN).unwrap();
+        storage.write(3, &[3, !3]).unwrap();
+        storage.write(14, &[14, !14]).unwrap();
+        storage.copy_range(3, 12, 1).unwrap();
+        assert_eq!(storage.read(12).unwrap(), vec![3, !3]);
+        assert_eq!(storage.read(0), Err(ProgramError::NotEnoughAccountKeys));
+        assert_eq!(storage.read(6), Err(ProgramError::NotEnoughAccountKeys));
+        assert_eq!(storage.read(15), Err(ProgramError::NotEnoughAccountKeys));
+        drop(storage);
+
+        assert_eq!(*accounts[0].data.borrow(), [0, 3, !3, 0, 0, 0, 0]);
+        assert_eq!(*accounts[1].data.borrow(), [0, 3, !3, 0, 0, 14, !14]);
+
+        assert!(PagedStorage::new(&[4, 1], &pages, ELEMENT_SIZE, ELEMENTS_PER_PAGE, HEADER_LEN).is_err());
+        assert!(PagedStorage::new(&[1, 1], &pages, ELEMENT_SIZE, ELEMENTS_PER_PAGE, HEADER_LEN).is_err());
+        assert!(PagedStorage::new(&[1], &pages, ELEMENT_SIZE, ELEMENTS_PER_PAGE, HEADER_LEN).is_err());
+    }
+}
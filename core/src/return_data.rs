@@ -0,0 +1,69 @@
+use std::convert::TryInto;
+
+use solana_program::{
+    msg,
+    program::{set_return_data, MAX_RETURN_DATA},
+    program_error::ProgramError,
+};
+
+/// Encodes the elements an instruction read or removed as its return data:
+/// `[count: u64 LE][count * element_size bytes of raw elements]`. Only the leading elements that
+/// fit in `MAX_RETURN_DATA` bytes are encoded and `count` says how many, so an instruction that
+/// removes more than it can return still succeeds. An element larger than `MAX_RETURN_DATA - 8`
+/// bytes never fits and comes back as a count of 0.
+pub fn pack_elements(elements: &[Vec<u8>]) -> Vec<u8> {
+    let mut count = 0u64;
+    let mut bytes = Vec::new();
+    for element in elements.iter() {
+        if 8 + bytes.len() + element.len() > MAX_RETURN_DATA {
+            break;
+        }
+        bytes.extend_from_slice(element);
+        count += 1;
+    }
+    let mut data = count.to_le_bytes().to_vec();
+    data.extend(bytes);
+    data
+}
+
+/// Hands the elements read by an instruction to the caller, see `pack_elements` for the layout.
+pub fn set_elements_return_data(elements: &[Vec<u8>]) {
+    let data = pack_elements(elements);
+    if data[..8] != (elements.len() as u64).to_le_bytes() {
+        msg!("Only the first {} of {} elements fit in the return data", u64::from_le_bytes(data[..8].try_into().unwrap()), elements.len());
+    }
+    set_return_data(&data);
+}
+
+/// Decodes return data written by `pack_elements`.
+pub fn unpack_elements(data: &[u8], element_size: u64) -> Result<Vec<Vec<u8>>, ProgramError> {
+    if data.len() < 8 || element_size == 0 {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    let (count, bytes) = data.split_at(8);
+    let count = u64::from_le_bytes(count.try_into().unwrap());
+    if count.checked_mul(element_size) != Some(bytes.len() as u64) {
+        return Err(ProgramError::InvalidAccountData);
+    }
+    Ok(bytes.chunks(element_size as usize).map(|element| element.to_vec()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_the_elements_that_fit() {
+        let elements = vec![vec![1; 8], vec![2; 8]];
+        let data = pack_elements(&elements);
+        assert_eq!(data.len(), 24);
+        assert_eq!(unpack_elements(&data, 8).unwrap(), elements);
+
+        let elements = vec![vec![3; 500]; 3];
+        let data = pack_elements(&elements);
+        assert_eq!(unpack_elements(&data, 500).unwrap(), elements[..2].to_vec());
+
+        let data = pack_elements(&[vec![4; MAX_RETURN_DATA]]);
+        assert_eq!(unpack_elements(&data, MAX_RETURN_DATA as u64).unwrap(), Vec::<Vec<u8>>::new());
+    }
+}
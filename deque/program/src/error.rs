@@ -12,6 +12,24 @@ pub enum DequeError {
     Overflow,
     #[error("IndexOutofBounds")]
     IndexOutofBounds,
+    #[error("Unauthorized")]
+    Unauthorized,
+    #[error("Invalid Meta Account")]
+    InvalidMetaAccount,
+    #[error("Invalid Data Account")]
+    InvalidDataAccount,
+    #[error("Return Data Too Large")]
+    ReturnDataTooLarge,
+    #[error("Invalid Parameters")]
+    InvalidParameters,
+    #[error("Not Provisioned")]
+    NotProvisioned,
+    #[error("Already Initialized")]
+    AlreadyInitialized,
+    #[error("Role List Full")]
+    RoleListFull,
+    #[error("Resize Pending")]
+    ResizePending,
 }
 
 impl From<DequeError> for ProgramError {
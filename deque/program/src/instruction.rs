@@ -1,61 +1,390 @@
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
-    entrypoint::ProgramResult,
+    entrypoint::{ProgramResult, MAX_PERMITTED_DATA_INCREASE},
+    instruction::{AccountMeta, Instruction as SolInstruction},
     msg,
-    program::{invoke, invoke_signed},
+    program::invoke,
     program_error::ProgramError,
     pubkey::Pubkey,
-    sysvar::{rent::Rent, Sysvar}
+    system_program,
+    sysvar::{self, rent::Rent, Sysvar}
 };
 use borsh::{BorshSerialize, BorshDeserialize};
-use std::{cmp::min};
+use std::{cmp::{max, min}};
 
-use crate::{error::DequeError, state::{MAX_ACCOUNT_SIZE, DEQUE_META_LEN, DequeMeta}};
+use solana_data_structures_core::{
+    access::{check_authority, check_cpi_only, check_cpi_only_accounts, check_role, check_role_manager, Roles},
+    account::{close_account, create_pda_account, grow_account},
+    PagedStorage,
+};
+pub use solana_data_structures_core::cpi::with_instructions_sysvar;
+
+use crate::{error::DequeError, state::{DATA_HEADER, DATA_HEADER_LEN, LAYOUT_VERSION, LAZY_ACCOUNT_SIZE, MAX_ACCOUNT_SIZE, MAX_RESIZE_MOVE_BYTES, DEQUE_META_DISCRIMINATOR, DEQUE_META_LEN, DequeMeta, DataHeader, Role}};
 
-#[derive(BorshDeserialize, PartialEq, Debug)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
 pub struct InitializeParams{
     pub max_length: u64,
     pub element_size: u64,
+    pub lazy: bool,
+    pub id: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+pub struct PushParams{
+    pub data: Vec<u8>,
 }
 
-#[derive(BorshDeserialize, PartialEq, Debug)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+pub struct SetParams{
+    pub index: u64,
+    pub data: Vec<u8>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
 pub struct PopParams{
     pub num_elements: u64,
 }
 
-#[derive(BorshDeserialize, PartialEq, Debug)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
 pub struct GetParams{
     pub start: u64,
     pub end: u64,
 }
 
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+pub struct ResizeParams{
+    pub max_length: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+pub struct SetDelegateParams{
+    pub delegate: Pubkey,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+pub struct SetAuthorityParams{
+    pub new_authority: Pubkey,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+pub struct RoleParams{
+    pub role: Role,
+    pub member: Pubkey,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+pub struct SetCpiOnlyParams{
+    pub cpi_only: bool,
+}
+
+/// Instructions of the deque program, serialized with Borsh: the variant index is the leading tag
+/// byte and the parameters follow it.
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
 pub enum Instruction {
-    Initialize,
-    PushFront,
-    PushBack,
-    PopFront,
-    PopBack,
-    Get,
-    Remove,
+    Initialize(InitializeParams),
+    PushFront(PushParams),
+    PushBack(PushParams),
+    PopFront(PopParams),
+    PopBack(PopParams),
+    Get(GetParams),
+    Remove(GetParams),
     Delete,
+    SetDelegate(SetDelegateParams),
+    Extend,
+    Resize(ResizeParams),
+    Shrink(ResizeParams),
+    Clear,
+    Migrate,
+    SetAuthority(SetAuthorityParams),
+    AcceptAuthority,
+    GrantRole(RoleParams),
+    RevokeRole(RoleParams),
+    SetCpiOnly(SetCpiOnlyParams),
+    Set(SetParams),
 }
 
 impl Instruction {
-    /// Unpacks a byte buffer into a [EscrowInstruction](enum.EscrowInstruction.html).
-    pub fn unpack(tag: &u8) -> Result<Self, ProgramError> {
-
-        Ok(match tag {
-            0 => Self::Initialize,
-            1 => Self::PushFront,
-            2 => Self::PushBack,
-            3 => Self::PopFront,
-            4 => Self::PopBack,
-            5 => Self::Get,
-            6 => Self::Remove,
-            7 => Self::Delete,
-            _ => return Err(ProgramError::InvalidInstructionData),
-        })
-    }   
+    /// Reads the instruction and the page indices of the data accounts passed with it, a Borsh
+    /// `Vec<u32>` after the instruction. Instructions without data accounts may leave it out.
+    pub fn unpack(input: &[u8]) -> Result<(Self, Vec<u32>), ProgramError> {
+        let mut input = input;
+        let instruction = Self::deserialize(&mut input).map_err(|_| ProgramError::InvalidInstructionData)?;
+        if input.is_empty(){
+            return Ok((instruction, Vec::new()));
+        }
+        let pages = Vec::<u32>::try_from_slice(input).map_err(|_| ProgramError::InvalidInstructionData)?;
+        Ok((instruction, pages))
+    }
+
+    pub fn pack(&self) -> Vec<u8> {
+        self.try_to_vec().unwrap()
+    }
+
+    /// Packs the instruction followed by the page indices of the data accounts passed with it.
+    pub fn pack_with_pages(&self, pages: &[u32]) -> Vec<u8> {
+        let mut data = self.pack();
+        data.extend(pages.to_vec().try_to_vec().unwrap());
+        data
+    }
+}
+
+/// Checks that the meta account is owned by this program and has the meta layout.
+fn check_meta_layout(
+    deque_meta_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    if deque_meta_account.owner != program_id || deque_meta_account.data_len() != DEQUE_META_LEN as usize{
+        msg!("Invalid deque meta account");
+        return Err(DequeError::InvalidMetaAccount.into());
+    }
+    Ok(())
+}
+
+/// Checks that the meta account is a deque meta of the current layout that `Initialize` has set up.
+fn check_meta_account(
+    deque_meta_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    check_meta_layout(deque_meta_account, program_id)?;
+    let deque_meta = DequeMeta::try_from_slice(&deque_meta_account.data.borrow())?;
+    if deque_meta.discriminator != DEQUE_META_DISCRIMINATOR || deque_meta.version != LAYOUT_VERSION{
+        msg!("Not a deque meta account of layout version {}", LAYOUT_VERSION);
+        return Err(DequeError::InvalidMetaAccount.into());
+    }
+    if !deque_meta.is_initialized{
+        msg!("Deque meta account is not initialized");
+        return Err(DequeError::InvalidMetaAccount.into());
+    }
+    Ok(())
+}
+
+/// Checks that the data accounts passed are the deque's data accounts at the page indices `pages`,
+/// in that order. The indices increase and each account is the PDA `[meta key, index as u32 LE]`,
+/// either owned by this program and starting with the data header or not created yet. Legacy data
+/// accounts have no header and the PDA `[meta key, index as u8]`.
+fn check_deque_accounts(
+    deque_meta_account: &AccountInfo,
+    deque_meta: &DequeMeta,
+    pages: &[u32],
+    deque_accounts: &[&AccountInfo],
+    program_id: &Pubkey,
+) -> ProgramResult {
+    if deque_accounts.len() < pages.len(){
+        msg!("Not enough accounts");
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    if deque_accounts.len() > pages.len(){
+        msg!("Too many deque accounts");
+        return Err(DequeError::InvalidDataAccount.into());
+    }
+
+    let num_accounts = deque_meta.num_accounts();
+    for (pages_index, (&page, deque_account)) in pages.iter().zip(deque_accounts.iter()).enumerate(){
+        if page as u64 >= num_accounts || (pages_index > 0 && page <= pages[pages_index - 1]){
+            msg!("Invalid page index {}", page);
+            return Err(DequeError::InvalidDataAccount.into());
+        }
+        let (deque_account_key, _) = Pubkey::find_program_address(
+            &[deque_meta_account.key.as_ref(), &deque_meta.data_account_seed(page)],
+            program_id,
+        );
+        // data accounts are created by Initialize, Extend or a push to a lazy deque
+        let uncreated = *deque_account.owner == system_program::id() && deque_account.data_len() == 0;
+        let created = deque_account.owner == program_id && (deque_meta.legacy || has_data_header(deque_account));
+        if (!created && !uncreated) || *deque_account.key != deque_account_key{
+            msg!("Invalid deque account {}", page);
+            return Err(DequeError::InvalidDataAccount.into());
+        }
+    }
+    Ok(())
+}
+
+/// The data account passed for page `page`, see `check_deque_accounts`.
+fn page_account<'b, 'a>(
+    pages: &[u32],
+    deque_accounts: &[&'b AccountInfo<'a>],
+    page: u64,
+) -> Result<&'b AccountInfo<'a>, ProgramError> {
+    let pages_index = pages.iter().position(|&passed| passed as u64 == page).ok_or_else(|| {
+        msg!("Missing deque account {}", page);
+        ProgramError::NotEnoughAccountKeys
+    })?;
+    Ok(deque_accounts[pages_index])
+}
+
+/// Rejects deques that need more data accounts than their seeds can tell apart.
+fn check_num_accounts(
+    deque_meta: &DequeMeta,
+) -> ProgramResult {
+    if deque_meta.num_accounts() > deque_meta.max_num_accounts(){
+        msg!("The deque needs {} data accounts, at most {} can be addressed", deque_meta.num_accounts(), deque_meta.max_num_accounts());
+        return Err(DequeError::InvalidParameters.into());
+    }
+    Ok(())
+}
+
+/// Whether `deque_account` starts with the data header of the current layout.
+fn has_data_header(
+    deque_account: &AccountInfo,
+) -> bool {
+    let data = deque_account.data.borrow();
+    data.len() >= DATA_HEADER_LEN as usize
+        && DataHeader::try_from_slice(&data[..DATA_HEADER_LEN as usize]).map_or(false, |header| header == DATA_HEADER)
+}
+
+/// Rejects deques in the middle of a `Resize`, whose elements may be moving.
+fn check_resize_complete(
+    deque_meta: &DequeMeta,
+) -> ProgramResult {
+    if deque_meta.pending_max_length != 0{
+        msg!("Deque is resizing to {} elements, send Resize until it completes", deque_meta.pending_max_length);
+        return Err(DequeError::ResizePending.into());
+    }
+    Ok(())
+}
+
+/// Rejects deques in the middle of a `Resize` and those whose data accounts `Extend` has not grown
+/// to their full size yet. Lazy deques create and size their data accounts as pushes need them.
+fn check_provisioned(
+    deque_meta: &DequeMeta,
+) -> ProgramResult {
+    check_resize_complete(deque_meta)?;
+    if !deque_meta.lazy && deque_meta.provisioned_bytes < deque_meta.max_bytes{
+        msg!("Deque is not fully provisioned, send Extend first");
+        return Err(DequeError::NotProvisioned.into());
+    }
+    Ok(())
+}
+
+/// Grows the data account at page `page` towards `target` bytes by at most
+/// `MAX_PERMITTED_DATA_INCREASE`, creating it first if it does not exist yet, with `payer` paying
+/// the rent. `target` includes the data header. Returns the number of element bytes added.
+#[allow(clippy::too_many_arguments)]
+fn provision_deque_account<'a>(
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    deque_meta_account: &AccountInfo<'a>,
+    deque_meta: &DequeMeta,
+    deque_account: &AccountInfo<'a>,
+    page: u32,
+    target: u64,
+    program_id: &Pubkey,
+) -> Result<u64, ProgramError> {
+    let current = deque_account.data_len() as u64;
+    if current >= target{
+        return Ok(0);
+    }
+    let space = min(target, current + MAX_PERMITTED_DATA_INCREASE as u64);
+    let rent = Rent::get()?;
+
+    if deque_account.owner != program_id{
+        let (deque_account_key, bump) = Pubkey::find_program_address(
+            &[deque_meta_account.key.as_ref(), &deque_meta.data_account_seed(page)],
+            program_id,
+        );
+        if *deque_account.key != deque_account_key{
+            msg!("Invalid deque account {}", page);
+            return Err(DequeError::InvalidDataAccount.into());
+        }
+        let deque_account_seeds: &[&[u8]] = &[deque_meta_account.key.as_ref(), &deque_meta.data_account_seed(page), &[bump]];
+        create_pda_account(payer, system_program, deque_account, space, deque_account_seeds, &rent, program_id)?;
+        if !deque_meta.legacy{
+            DATA_HEADER.serialize(&mut *deque_account.data.borrow_mut())?;
+        }
+        msg!("Created deque account {}", page);
+        return Ok(space - deque_meta.data_header_len());
+    } else {
+        grow_account(payer, system_program, deque_account, space, &rent)?;
+    }
+
+    Ok(space - current)
+}
+
+/// Creates the data accounts of a lazy deque that hold the elements at `indices` and do not exist
+/// yet, with `payer` paying the rent.
+#[allow(clippy::too_many_arguments)]
+fn provision_elements<'a>(
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    deque_meta_account: &AccountInfo<'a>,
+    deque_meta: &mut DequeMeta,
+    pages: &[u32],
+    deque_accounts: &[&AccountInfo<'a>],
+    indices: impl Iterator<Item = u64>,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    for index in indices{
+        let page = index / deque_meta.max_elements_per_account;
+        let deque_account = page_account(pages, deque_accounts, page)?;
+        let target = deque_meta.data_header_len() + deque_meta.account_bytes(page);
+        deque_meta.provisioned_bytes += provision_deque_account(payer, system_program, deque_meta_account, deque_meta, deque_account, page as u32, target, program_id)?;
+    }
+    Ok(())
+}
+
+/// Rejects moving elements to the `count` elements from `dst` on unless the data accounts passed
+/// hold them, provisioned by `Extend` or the pushes of a lazy deque.
+fn check_range_provisioned(
+    deque_meta: &DequeMeta,
+    pages: &[u32],
+    deque_accounts: &[&AccountInfo],
+    dst: u64,
+    count: u64,
+) -> ProgramResult {
+    let per_account = deque_meta.max_elements_per_account;
+    for page in dst / per_account..=(dst + count - 1) / per_account{
+        let deque_account = page_account(pages, deque_accounts, page)?;
+        let last = min(dst + count, (page + 1) * per_account) - 1;
+        if (deque_account.data_len() as u64) < deque_meta.data_header_len() + (last % per_account + 1) * deque_meta.element_size{
+            msg!("Deque account {} is not provisioned, send Extend first", page);
+            return Err(DequeError::NotProvisioned.into());
+        }
+    }
+    Ok(())
+}
+
+/// Number of the last `count` elements moving from `src` to `dst` that one `Resize` can move: down
+/// from the last one for as long as the data accounts passed hold them on both sides and, unless
+/// the deque is lazy, `Extend` has provisioned their destination, at most `MAX_RESIZE_MOVE_BYTES`.
+fn movable_elements(
+    deque_meta: &DequeMeta,
+    pages: &[u32],
+    deque_accounts: &[&AccountInfo],
+    src: u64,
+    dst: u64,
+    count: u64,
+) -> u64 {
+    let per_account = deque_meta.max_elements_per_account;
+    let max_count = min(count, max(1, MAX_RESIZE_MOVE_BYTES / deque_meta.element_size));
+    let mut movable = 0;
+    while movable < max_count{
+        let (last_src, last_dst) = (src + count - movable - 1, dst + count - movable - 1);
+        let src_passed = pages.iter().any(|&page| page as u64 == last_src / per_account);
+        let dst_provisioned = pages.iter().position(|&page| page as u64 == last_dst / per_account).map_or(false, |pages_index| {
+            let end = deque_meta.data_header_len() + (last_dst % per_account + 1) * deque_meta.element_size;
+            deque_meta.lazy || deque_accounts[pages_index].data_len() as u64 >= end
+        });
+        if !src_passed || !dst_provisioned{
+            break;
+        }
+        // the rest of both pages down to the first element of either
+        movable += min(last_src % per_account, last_dst % per_account) + 1;
+    }
+    min(movable, max_count)
+}
+
+/// Rejects parameters the paging arithmetic cannot handle: zero sizes, elements larger than a
+/// data account and capacities whose byte size overflows.
+fn check_parameters(
+    max_length: u64,
+    element_size: u64,
+) -> ProgramResult {
+    if max_length == 0 || element_size == 0 || element_size > MAX_ACCOUNT_SIZE - DATA_HEADER_LEN{
+        msg!("Invalid max length or element size");
+        return Err(DequeError::InvalidParameters.into());
+    }
+    max_length.checked_mul(element_size).ok_or(DequeError::Overflow)?;
+    Ok(())
 }
 
 pub fn initialize_deque(
@@ -75,6 +404,13 @@ pub fn initialize_deque(
     while account_info_iter.peek().is_some(){
         deque_accounts.push(next_account_info(account_info_iter)?);
     }
+
+    if !auth.is_signer{
+        msg!("Missing authority signature");
+        return Err(DequeError::Unauthorized.into());
+    }
+
+    check_parameters(max_length, element_size)?;
     
     // create deque meta account if it doesn't exist
     if deque_meta_account.data_len() == 0{
@@ -99,15 +435,32 @@ pub fn initialize_deque(
 
     let mut deque_meta = DequeMeta::try_from_slice(&deque_meta_account.data.borrow())?;
 
+    // the meta of a live deque is never overwritten
+    if deque_meta.is_initialized{
+        msg!("Deque already initialized");
+        return Err(DequeError::AlreadyInitialized.into());
+    }
+
+    deque_meta.discriminator = DEQUE_META_DISCRIMINATOR;
+    deque_meta.version = LAYOUT_VERSION;
     deque_meta.max_length = max_length;
     deque_meta.element_size = element_size;
     deque_meta.max_bytes = max_length * element_size;
     deque_meta.start = 0;
     deque_meta.length = 0;
-    deque_meta.max_elements_per_account = MAX_ACCOUNT_SIZE / element_size;
+    deque_meta.max_elements_per_account = (MAX_ACCOUNT_SIZE - DATA_HEADER_LEN) / element_size;
     deque_meta.max_bytes_per_account = deque_meta.max_elements_per_account * element_size;
-
-    deque_meta.serialize(&mut *deque_meta_account.data.borrow_mut())?;
+    deque_meta.authority = *auth.key;
+    deque_meta.delegate = Pubkey::default();
+    deque_meta.pending_authority = Pubkey::default();
+    deque_meta.roles = Roles::default();
+    deque_meta.cpi_only = false;
+    deque_meta.pending_max_length = 0;
+    deque_meta.moved_length = 0;
+    deque_meta.provisioned_bytes = 0;
+    deque_meta.lazy = false;
+    deque_meta.id = 0;
+    deque_meta.is_initialized = true;
 
     let mut size_to_allocate = max_length * element_size;
     let mut deque_accounts_index = 0;
@@ -117,8 +470,9 @@ pub fn initialize_deque(
             return Err(ProgramError::NotEnoughAccountKeys);
         }
 
+        let target = min(size_to_allocate, deque_meta.max_bytes_per_account);
         if deque_accounts[deque_accounts_index].data_len() == 0{
-            let space = min(size_to_allocate, deque_meta.max_bytes_per_account);
+            let space = min(DATA_HEADER_LEN + target, MAX_PERMITTED_DATA_INCREASE as u64);
             let required_lamports = rent.minimum_balance(space as usize);
             invoke(
                 &solana_program::system_instruction::create_account(
@@ -134,12 +488,16 @@ pub fn initialize_deque(
                     system_program.clone(),
                 ]
             )?;
-
-            size_to_allocate -= space;
-            deque_accounts_index += 1;
         }
+
+        DATA_HEADER.serialize(&mut *deque_accounts[deque_accounts_index].data.borrow_mut())?;
+        deque_meta.provisioned_bytes += (deque_accounts[deque_accounts_index].data_len() as u64).saturating_sub(DATA_HEADER_LEN);
+        size_to_allocate -= target;
+        deque_accounts_index += 1;
     }
 
+    deque_meta.serialize(&mut *deque_meta_account.data.borrow_mut())?;
+
     Ok(())
 }
 
@@ -147,9 +505,10 @@ pub fn initialize_deque_signed(
     accounts: &[AccountInfo],
     max_length: u64,
     element_size: u64,
+    lazy: bool,
+    id: u64,
+    pages: &[u32],
     program_id: &Pubkey,
-    meta_seeds: &[&[u8]],
-    deque_bump_seeds: &[u8],
 ) -> ProgramResult {
 
     let account_info_iter = &mut accounts.iter().peekable();
@@ -162,88 +521,122 @@ pub fn initialize_deque_signed(
     while account_info_iter.peek().is_some(){
         deque_accounts.push(next_account_info(account_info_iter)?);
     }
+
+    if !auth.is_signer{
+        msg!("Missing authority signature");
+        return Err(DequeError::Unauthorized.into());
+    }
+
+    check_parameters(max_length, element_size)?;
+    if lazy && element_size > LAZY_ACCOUNT_SIZE - DATA_HEADER_LEN{
+        msg!("Element size larger than a lazy deque data account");
+        return Err(DequeError::InvalidParameters.into());
+    }
+    let account_size = if lazy { LAZY_ACCOUNT_SIZE } else { MAX_ACCOUNT_SIZE };
+
+    // the meta and the data accounts only take the canonical bump, so every set of parameters has
+    // exactly one meta and each index one data account
+    let (deque_meta_key, meta_bump) = find_meta_address(program_id, auth.key, max_length, element_size, id);
+    if *deque_meta_account.key != deque_meta_key{
+        msg!("Deque meta account does not match the authority and parameters");
+        return Err(DequeError::InvalidMetaAccount.into());
+    }
+    let meta_seeds: &[&[u8]] = &[auth.key.as_ref(), &max_length.to_le_bytes(), &element_size.to_le_bytes(), &id.to_le_bytes(), &[meta_bump]];
     
     // create deque meta account if it doesn't exist
     if deque_meta_account.data_len() == 0{
-
         let space = DEQUE_META_LEN;
-        let required_lamports = rent.minimum_balance(space as usize);
-        invoke_signed(
-            &solana_program::system_instruction::create_account(
-                auth.key,
-                deque_meta_account.key,
-                required_lamports,
-                space,
-                program_id,
-            ),
-            &[
-                auth.clone(),
-                deque_meta_account.clone(),
-                system_program.clone(),
-            ],
-            &[meta_seeds]
-        )?;
+        create_pda_account(auth, system_program, deque_meta_account, space, meta_seeds, rent, program_id)?;
     }
 
+    check_meta_layout(deque_meta_account, program_id)?;
+
     msg!("Created deque meta account");
 
     let mut deque_meta = DequeMeta::try_from_slice(&deque_meta_account.data.borrow())?;
 
+    // the meta of a live deque is never overwritten
+    if deque_meta.is_initialized{
+        msg!("Deque already initialized");
+        return Err(DequeError::AlreadyInitialized.into());
+    }
+
+    deque_meta.discriminator = DEQUE_META_DISCRIMINATOR;
+    deque_meta.version = LAYOUT_VERSION;
     deque_meta.max_length = max_length;
     deque_meta.element_size = element_size;
     deque_meta.max_bytes = max_length * element_size;
     deque_meta.start = 0;
     deque_meta.length = 0;
-    deque_meta.max_elements_per_account = MAX_ACCOUNT_SIZE / element_size;
+    deque_meta.max_elements_per_account = (account_size - DATA_HEADER_LEN) / element_size;
     deque_meta.max_bytes_per_account = deque_meta.max_elements_per_account * element_size;
+    deque_meta.authority = *auth.key;
+    deque_meta.delegate = Pubkey::default();
+    deque_meta.pending_authority = Pubkey::default();
+    deque_meta.roles = Roles::default();
+    deque_meta.cpi_only = false;
+    deque_meta.pending_max_length = 0;
+    deque_meta.moved_length = 0;
+    deque_meta.provisioned_bytes = 0;
+    deque_meta.lazy = lazy;
+    deque_meta.id = id;
+    deque_meta.is_initialized = true;
+
+    check_num_accounts(&deque_meta)?;
+    check_deque_accounts(deque_meta_account, &deque_meta, pages, &deque_accounts, program_id)?;
+
+    // the data accounts passed are created here, Extend creates and grows the rest and a lazy
+    // deque creates them when a push first needs them
+    for (&page, &deque_account) in pages.iter().zip(deque_accounts.iter()){
+        let (_, bump) = Pubkey::find_program_address(&[deque_meta_account.key.as_ref(), &page.to_le_bytes()], program_id);
+        let deque_account_seeds: &[&[u8]] = &[deque_meta_account.key.as_ref(), &page.to_le_bytes(), &[bump]];
+
+        // a program may only allocate MAX_PERMITTED_DATA_INCREASE bytes per account in one
+        // instruction, the rest is grown by Extend
+        let space = min(DATA_HEADER_LEN + deque_meta.account_bytes(page as u64), MAX_PERMITTED_DATA_INCREASE as u64);
+        create_pda_account(auth, system_program, deque_account, space, deque_account_seeds, rent, program_id)?;
+        DATA_HEADER.serialize(&mut *deque_account.data.borrow_mut())?;
+        deque_meta.provisioned_bytes += space - DATA_HEADER_LEN;
+
+        msg!("Created deque account {}", page);
+    }
 
     deque_meta.serialize(&mut *deque_meta_account.data.borrow_mut())?;
 
-    let mut size_to_allocate = max_length * element_size;
-    let mut deque_accounts_index = 0;
-    while size_to_allocate > 0 {
-        if deque_accounts_index == deque_accounts.len(){
-            msg!("Not enough accounts");
-            return Err(ProgramError::NotEnoughAccountKeys);
-        }
-
-
-        let space = min(size_to_allocate, deque_meta.max_bytes_per_account);
-        let required_lamports = rent.minimum_balance(space as usize);
-        invoke_signed(
-            &solana_program::system_instruction::create_account(
-                auth.key,
-                deque_accounts[deque_accounts_index].key,
-                required_lamports,
-                space,
-                program_id,
-            ),
-            &[
-                auth.clone(),
-                deque_accounts[deque_accounts_index].clone(),
-                system_program.clone(),
-            ],
-            &[&[deque_meta_account.key.as_ref(), &[deque_accounts_index as u8], 
-              &[deque_bump_seeds[deque_accounts_index]]]],
-        )?;
-
-        size_to_allocate -= space;
+    Ok(())
+}
 
-        msg!("Created deque account {}", deque_accounts_index);
+/// Position in the data accounts of the element `index` places behind the front of the deque.
+fn physical_index(
+    deque_meta: &DequeMeta,
+    index: u64,
+) -> u64 {
+    (deque_meta.start + index) % deque_meta.max_length
+}
 
-        deque_accounts_index += 1;
+/// Reads `count` elements starting `start` places behind the front, following the wrap.
+fn read_range(
+    deque_storage: &PagedStorage,
+    deque_meta: &DequeMeta,
+    start: u64,
+    count: u64,
+) -> Result<Vec<Vec<u8>>, ProgramError> {
+    let mut ret = Vec::with_capacity(count as usize);
+    for i in start..start + count{
+        ret.push(deque_storage.read(physical_index(deque_meta, i))?);
     }
-
-    Ok(())
+    Ok(ret)
 }
 
 pub fn get_meta(
     accounts: &[AccountInfo],
+    program_id: &Pubkey,
 ) -> Result<DequeMeta, ProgramError> {
 
     let account_info_iter = &mut accounts.iter().peekable();
 
     let deque_meta_account = next_account_info(account_info_iter)?;
+    check_meta_account(deque_meta_account, program_id)?;
     let deque_meta = DequeMeta::try_from_slice(&deque_meta_account.data.borrow())?;
 
     Ok(deque_meta)
@@ -252,18 +645,31 @@ pub fn get_meta(
 pub fn push_front(
     accounts: &[AccountInfo],
     data: &[u8],
+    pages: &[u32],
+    program_id: &Pubkey,
 ) -> ProgramResult {
 
     let account_info_iter = &mut accounts.iter().peekable();
 
+    let auth = next_account_info(account_info_iter)?;
     let deque_meta_account = next_account_info(account_info_iter)?;
 
+    check_meta_account(deque_meta_account, program_id)?;
+    let mut deque_meta = DequeMeta::try_from_slice(&deque_meta_account.data.borrow())?;
+
+    // a lazy deque takes the system program after the meta to create data accounts on demand
+    let system_program = if deque_meta.lazy { Some(next_account_info(account_info_iter)?) } else { None };
+
     let mut deque_accounts = Vec::new();
     while account_info_iter.peek().is_some(){
         deque_accounts.push(next_account_info(account_info_iter)?);
     }
 
-    let mut deque_meta = DequeMeta::try_from_slice(&deque_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&deque_meta, &mut deque_accounts, program_id)?;
+
+    check_role(&deque_meta, auth, Role::Pusher)?;
+    check_deque_accounts(deque_meta_account, &deque_meta, pages, &deque_accounts, program_id)?;
+    check_provisioned(&deque_meta)?;
 
     if data.len() % (deque_meta.element_size) as usize != 0{
         msg!("Data length not multiple of element size");
@@ -271,35 +677,22 @@ pub fn push_front(
     }
 
     let num_elements = data.len() as u64 / deque_meta.element_size;
-    if deque_meta.length + num_elements > deque_meta.max_length{
+    if deque_meta.length.checked_add(num_elements).ok_or(DequeError::Overflow)? > deque_meta.max_length{
         msg!("Not enough space");
         return Err(DequeError::InsufficientSpace.into());
     }
-    let start = (deque_meta.start - num_elements + deque_meta.max_length) % deque_meta.max_length;
-
-    let mut cur_byte = start * deque_meta.element_size;
-    let mut deque_accounts_index = (start / deque_meta.max_elements_per_account) as usize;
-    let mut deque_data = deque_accounts[deque_accounts_index].data.borrow_mut();
-    let mut deque_data_index = ((start % deque_meta.max_elements_per_account) * deque_meta.element_size) as usize;
-
-    for data_index in 0..data.len(){
-        deque_data[deque_data_index] = data[data_index];
-        deque_data_index += 1;
-        cur_byte += 1;
-        if cur_byte >= deque_meta.max_bytes{
-            deque_accounts_index = 0;
-            deque_data = deque_accounts[deque_accounts_index].data.borrow_mut();
-            deque_data_index = 0;
-            cur_byte = 0;
-        }
-        else if deque_data_index as u64 >= deque_meta.max_bytes_per_account{
-            deque_accounts_index += 1;
-            deque_data = deque_accounts[deque_accounts_index].data.borrow_mut();
-            deque_data_index = 0;
-        }
+    deque_meta.start = (deque_meta.start + deque_meta.max_length - num_elements) % deque_meta.max_length;
+
+    if let Some(system_program) = system_program{
+        let indices: Vec<u64> = (0..num_elements).map(|i| physical_index(&deque_meta, i)).collect();
+        provision_elements(auth, system_program, deque_meta_account, &mut deque_meta, pages, &deque_accounts, indices.into_iter(), program_id)?;
+    }
+
+    let mut deque_storage = PagedStorage::new(pages, &deque_accounts, deque_meta.element_size, deque_meta.max_elements_per_account, deque_meta.data_header_len())?;
+    for (i, element) in data.chunks(deque_meta.element_size as usize).enumerate(){
+        deque_storage.write(physical_index(&deque_meta, i as u64), element)?;
     }
 
-    deque_meta.start = start;
     deque_meta.length += num_elements;
     deque_meta.serialize(&mut *deque_meta_account.data.borrow_mut())?;
 
@@ -309,18 +702,31 @@ pub fn push_front(
 pub fn push_back(
     accounts: &[AccountInfo],
     data: &[u8],
+    pages: &[u32],
+    program_id: &Pubkey,
 ) -> ProgramResult {
 
     let account_info_iter = &mut accounts.iter().peekable();
 
+    let auth = next_account_info(account_info_iter)?;
     let deque_meta_account = next_account_info(account_info_iter)?;
 
+    check_meta_account(deque_meta_account, program_id)?;
+    let mut deque_meta = DequeMeta::try_from_slice(&deque_meta_account.data.borrow())?;
+
+    // a lazy deque takes the system program after the meta to create data accounts on demand
+    let system_program = if deque_meta.lazy { Some(next_account_info(account_info_iter)?) } else { None };
+
     let mut deque_accounts = Vec::new();
     while account_info_iter.peek().is_some(){
         deque_accounts.push(next_account_info(account_info_iter)?);
     }
 
-    let mut deque_meta = DequeMeta::try_from_slice(&deque_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&deque_meta, &mut deque_accounts, program_id)?;
+
+    check_role(&deque_meta, auth, Role::Pusher)?;
+    check_deque_accounts(deque_meta_account, &deque_meta, pages, &deque_accounts, program_id)?;
+    check_provisioned(&deque_meta)?;
 
     if data.len() % (deque_meta.element_size) as usize != 0{
         msg!("Data length not multiple of element size");
@@ -328,32 +734,18 @@ pub fn push_back(
     }
 
     let num_elements = data.len() as u64 / deque_meta.element_size;
-    if deque_meta.length + num_elements > deque_meta.max_length{
+    if deque_meta.length.checked_add(num_elements).ok_or(DequeError::Overflow)? > deque_meta.max_length{
         msg!("Not enough space");
         return Err(DequeError::InsufficientSpace.into());
     }
-    let start = (deque_meta.start + deque_meta.length) % deque_meta.max_length;
-
-    let mut cur_byte = start * deque_meta.element_size;
-    let mut deque_accounts_index = (start / deque_meta.max_elements_per_account) as usize;
-    let mut deque_data = deque_accounts[deque_accounts_index].data.borrow_mut();
-    let mut deque_data_index = ((start % deque_meta.max_elements_per_account) * deque_meta.element_size) as usize;
-
-    for data_index in 0..data.len(){
-        deque_data[deque_data_index] = data[data_index];
-        deque_data_index += 1;
-        cur_byte += 1;
-        if cur_byte >= deque_meta.max_bytes{
-            deque_accounts_index = 0;
-            deque_data = deque_accounts[deque_accounts_index].data.borrow_mut();
-            deque_data_index = 0;
-            cur_byte = 0;
-        }
-        else if deque_data_index as u64 >= deque_meta.max_bytes_per_account{
-            deque_accounts_index += 1;
-            deque_data = deque_accounts[deque_accounts_index].data.borrow_mut();
-            deque_data_index = 0;
-        }
+    if let Some(system_program) = system_program{
+        let indices: Vec<u64> = (deque_meta.length..deque_meta.length + num_elements).map(|i| physical_index(&deque_meta, i)).collect();
+        provision_elements(auth, system_program, deque_meta_account, &mut deque_meta, pages, &deque_accounts, indices.into_iter(), program_id)?;
+    }
+
+    let mut deque_storage = PagedStorage::new(pages, &deque_accounts, deque_meta.element_size, deque_meta.max_elements_per_account, deque_meta.data_header_len())?;
+    for (i, element) in data.chunks(deque_meta.element_size as usize).enumerate(){
+        deque_storage.write(physical_index(&deque_meta, deque_meta.length + i as u64), element)?;
     }
 
     deque_meta.length += num_elements;
@@ -365,9 +757,12 @@ pub fn push_back(
 pub fn pop_slice_front(
     accounts: &[AccountInfo],
     num_elements: u64,
+    pages: &[u32],
+    program_id: &Pubkey,
 ) -> Result<Vec<Vec<u8>>, ProgramError> {
     let account_info_iter = &mut accounts.iter().peekable();
 
+    let auth = next_account_info(account_info_iter)?;
     let deque_meta_account = next_account_info(account_info_iter)?;
 
     let mut deque_accounts = Vec::new();
@@ -375,42 +770,25 @@ pub fn pop_slice_front(
         deque_accounts.push(next_account_info(account_info_iter)?);
     }
 
+    check_meta_account(deque_meta_account, program_id)?;
     let mut deque_meta = DequeMeta::try_from_slice(&deque_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&deque_meta, &mut deque_accounts, program_id)?;
+    check_role(&deque_meta, auth, Role::Popper)?;
+    check_deque_accounts(deque_meta_account, &deque_meta, pages, &deque_accounts, program_id)?;
+    check_provisioned(&deque_meta)?;
 
     if deque_meta.length < num_elements{
         msg!("Not enough elements to pop");
         return Err(DequeError::PopFromEmpty.into());
     }
 
-    let mut ret = Vec::with_capacity(num_elements as usize);
-
     let new_length = deque_meta.length - num_elements;
-    let start = deque_meta.start;
-
-    let mut cur_byte = start * deque_meta.element_size;
-    let mut deque_accounts_index = (start / deque_meta.max_elements_per_account) as usize;
-    let mut deque_data = deque_accounts[deque_accounts_index].data.borrow_mut();
-    let mut deque_data_index = ((start % deque_meta.max_elements_per_account) * deque_meta.element_size) as usize;
-
-    for _x in 0..num_elements{
-        ret.push(deque_data[deque_data_index..(deque_data_index + deque_meta.element_size as usize)].to_vec());
-        deque_data_index += deque_meta.element_size as usize;
-        cur_byte += deque_meta.element_size;
-        if cur_byte >= deque_meta.max_bytes{
-            deque_accounts_index = 0;
-            deque_data = deque_accounts[deque_accounts_index].data.borrow_mut();
-            deque_data_index = 0;
-            cur_byte = 0;
-        }
-        else if deque_data_index as u64 >= deque_meta.max_bytes_per_account{
-            deque_accounts_index += 1;
-            deque_data = deque_accounts[deque_accounts_index].data.borrow_mut();
-            deque_data_index = 0;
-        }
-    }
 
+    let deque_storage = PagedStorage::new(pages, &deque_accounts, deque_meta.element_size, deque_meta.max_elements_per_account, deque_meta.data_header_len())?;
+    let ret = read_range(&deque_storage, &deque_meta, 0, num_elements)?;
+
+    deque_meta.start = physical_index(&deque_meta, num_elements);
     deque_meta.length = new_length;
-    deque_meta.start = (deque_meta.start + num_elements) % deque_meta.max_length;
     deque_meta.serialize(&mut *deque_meta_account.data.borrow_mut())?;
 
     Ok(ret)
@@ -419,10 +797,13 @@ pub fn pop_slice_front(
 pub fn pop_slice_back(
     accounts: &[AccountInfo],
     num_elements: u64,
+    pages: &[u32],
+    program_id: &Pubkey,
 ) -> Result<Vec<Vec<u8>>, ProgramError> {
 
     let account_info_iter = &mut accounts.iter().peekable();
 
+    let auth = next_account_info(account_info_iter)?;
     let deque_meta_account = next_account_info(account_info_iter)?;
 
     let mut deque_accounts = Vec::new();
@@ -430,39 +811,22 @@ pub fn pop_slice_back(
         deque_accounts.push(next_account_info(account_info_iter)?);
     }
 
+    check_meta_account(deque_meta_account, program_id)?;
     let mut deque_meta = DequeMeta::try_from_slice(&deque_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&deque_meta, &mut deque_accounts, program_id)?;
+    check_role(&deque_meta, auth, Role::Popper)?;
+    check_deque_accounts(deque_meta_account, &deque_meta, pages, &deque_accounts, program_id)?;
+    check_provisioned(&deque_meta)?;
 
     if deque_meta.length < num_elements{
         msg!("Not enough elements to pop");
         return Err(DequeError::PopFromEmpty.into());
     }
 
-    let mut ret = Vec::with_capacity(num_elements as usize);
-
     let new_length = deque_meta.length - num_elements;
-    let start = (deque_meta.start + new_length + 1) % deque_meta.max_length;
-
-    let mut cur_byte = start * deque_meta.element_size;
-    let mut deque_accounts_index = (start / deque_meta.max_elements_per_account) as usize;
-    let mut deque_data = deque_accounts[deque_accounts_index].data.borrow_mut();
-    let mut deque_data_index = ((start % deque_meta.max_elements_per_account) * deque_meta.element_size) as usize;
-
-    for _x in 0..num_elements{
-        ret.push(deque_data[deque_data_index..(deque_data_index + deque_meta.element_size as usize)].to_vec());
-        deque_data_index += deque_meta.element_size as usize;
-        cur_byte += deque_meta.element_size;
-        if cur_byte >= deque_meta.max_bytes{
-            deque_accounts_index = 0;
-            deque_data = deque_accounts[deque_accounts_index].data.borrow_mut();
-            deque_data_index = 0;
-            cur_byte = 0;
-        }
-        else if deque_data_index as u64 >= deque_meta.max_bytes_per_account{
-            deque_accounts_index += 1;
-            deque_data = deque_accounts[deque_accounts_index].data.borrow_mut();
-            deque_data_index = 0;
-        }
-    }
+
+    let deque_storage = PagedStorage::new(pages, &deque_accounts, deque_meta.element_size, deque_meta.max_elements_per_account, deque_meta.data_header_len())?;
+    let ret = read_range(&deque_storage, &deque_meta, new_length, num_elements)?;
 
     deque_meta.length = new_length;
     deque_meta.serialize(&mut *deque_meta_account.data.borrow_mut())?;
@@ -472,23 +836,31 @@ pub fn pop_slice_back(
 
 pub fn pop_front(
     accounts: &[AccountInfo],
+    pages: &[u32],
+    program_id: &Pubkey,
 ) -> Result<Vec<u8>, ProgramError> {
 
-    Ok((pop_slice_front(
+    pop_slice_front(
         accounts,
-        1
-    )?).pop().ok_or(ProgramError::InvalidArgument)?)
+        1,
+        pages,
+        program_id,
+    )?.pop().ok_or(ProgramError::InvalidArgument)
 
 }
 
 pub fn pop_back(
     accounts: &[AccountInfo],
+    pages: &[u32],
+    program_id: &Pubkey,
 ) -> Result<Vec<u8>, ProgramError> {
 
-    Ok((pop_slice_back(
+    pop_slice_back(
         accounts,
-        1
-    )?).pop().ok_or(ProgramError::InvalidArgument)?)
+        1,
+        pages,
+        program_id,
+    )?.pop().ok_or(ProgramError::InvalidArgument)
 
 }
 
@@ -496,6 +868,8 @@ pub fn slice(
     accounts: &[AccountInfo],
     start: u64,
     end: u64,
+    pages: &[u32],
+    program_id: &Pubkey,
 ) -> Result<Vec<Vec<u8>>, ProgramError> {
 
     let account_info_iter = &mut accounts.iter().peekable();
@@ -507,62 +881,46 @@ pub fn slice(
         deque_accounts.push(next_account_info(account_info_iter)?);
     }
 
+    check_meta_account(deque_meta_account, program_id)?;
     let deque_meta = DequeMeta::try_from_slice(&deque_meta_account.data.borrow())?;
+    check_deque_accounts(deque_meta_account, &deque_meta, pages, &deque_accounts, program_id)?;
+    check_provisioned(&deque_meta)?;
 
-    if start >= deque_meta.length || end >= deque_meta.length || start > end {
+    if start > end || end > deque_meta.length {
         msg!("Index Out of Bounds");
         return Err(DequeError::IndexOutofBounds.into());
     }
 
-    let num_elements = end - start;
-
-    let mut ret = Vec::with_capacity(num_elements as usize);
-
-    let start = (deque_meta.start + start) % deque_meta.max_length;
-    let mut cur_byte = start * deque_meta.element_size;
-    let mut deque_accounts_index = (start / deque_meta.max_elements_per_account) as usize;
-    let mut deque_data = deque_accounts[deque_accounts_index].data.borrow_mut();
-    let mut deque_data_index = ((start % deque_meta.max_elements_per_account) * deque_meta.element_size) as usize;
-
-    for _x in 0..num_elements{
-        ret.push(deque_data[deque_data_index..(deque_data_index + deque_meta.element_size as usize)].to_vec());
-        deque_data_index += deque_meta.element_size as usize;
-        cur_byte += deque_meta.element_size;
-        if cur_byte >= deque_meta.max_bytes{
-            deque_accounts_index = 0;
-            deque_data = deque_accounts[deque_accounts_index].data.borrow_mut();
-            deque_data_index = 0;
-            cur_byte = 0;
-        }
-        else if deque_data_index as u64 >= deque_meta.max_bytes_per_account{
-            deque_accounts_index += 1;
-            deque_data = deque_accounts[deque_accounts_index].data.borrow_mut();
-            deque_data_index = 0;
-        }
-    }
-
-    Ok(ret)
+    let deque_storage = PagedStorage::new(pages, &deque_accounts, deque_meta.element_size, deque_meta.max_elements_per_account, deque_meta.data_header_len())?;
+    read_range(&deque_storage, &deque_meta, start, end - start)
 }
 
 pub fn get(
     accounts: &[AccountInfo],
     index: u64,
+    pages: &[u32],
+    program_id: &Pubkey,
 ) -> Result<Vec<u8>, ProgramError> {
-    Ok((slice(
+    slice(
         accounts,
         index,
-        index + 1,
-    )?).pop().ok_or(ProgramError::InvalidArgument)?)
+        index.checked_add(1).ok_or(DequeError::IndexOutofBounds)?,
+        pages,
+        program_id,
+    )?.pop().ok_or(ProgramError::InvalidArgument)
 }
 
 pub fn remove_slice(
     accounts: &[AccountInfo],
     start: u64,
     end: u64,
+    pages: &[u32],
+    program_id: &Pubkey,
 ) -> Result<Vec<Vec<u8>>, ProgramError> {
 
     let account_info_iter = &mut accounts.iter().peekable();
 
+    let auth = next_account_info(account_info_iter)?;
     let deque_meta_account = next_account_info(account_info_iter)?;
 
     let mut deque_accounts = Vec::new();
@@ -570,80 +928,26 @@ pub fn remove_slice(
         deque_accounts.push(next_account_info(account_info_iter)?);
     }
 
+    check_meta_account(deque_meta_account, program_id)?;
     let mut deque_meta = DequeMeta::try_from_slice(&deque_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&deque_meta, &mut deque_accounts, program_id)?;
+    check_role(&deque_meta, auth, Role::Popper)?;
+    check_deque_accounts(deque_meta_account, &deque_meta, pages, &deque_accounts, program_id)?;
+    check_provisioned(&deque_meta)?;
 
-    if start >= deque_meta.length || end >= deque_meta.length || start > end {
+    if start > end || end > deque_meta.length {
         msg!("Index Out of Bounds");
         return Err(DequeError::IndexOutofBounds.into());
     }
 
-    let mut deque_account_refs = Vec::with_capacity(deque_accounts.len() as usize);
-    for i in 0..deque_accounts.len(){
-        deque_account_refs.push(deque_accounts[i].data.borrow_mut());
-    }
-
     let num_elements = end - start;
-
-    let mut ret = Vec::with_capacity(num_elements as usize);
-
-    let bytes_to_shift = (deque_meta.length - end) * deque_meta.element_size; // number of bytes to shift forward
-
-    let start = (deque_meta.start + start) % deque_meta.max_length;
-    let end = (deque_meta.start + end) % deque_meta.max_length;
-
-    let mut cur_byte = start * deque_meta.element_size;
-    let mut deque_accounts_index = (start / deque_meta.max_elements_per_account) as usize;
-    let mut deque_data_index = ((start % deque_meta.max_elements_per_account) * deque_meta.element_size) as usize;
-    for _x in 0..num_elements{
-        ret.push(deque_account_refs[deque_accounts_index][deque_data_index..(deque_data_index + deque_meta.element_size as usize)].to_vec());
-        deque_data_index += deque_meta.element_size as usize;
-        cur_byte += deque_meta.element_size;
-        if cur_byte >= deque_meta.max_bytes{
-            deque_accounts_index = 0;
-            deque_data_index = 0;
-            cur_byte = 0;
-        }
-        else if deque_data_index as u64 >= deque_meta.max_bytes_per_account{
-            deque_accounts_index += 1;
-            deque_data_index = 0;
-        }
-    }
-
     let new_length = deque_meta.length - num_elements;
 
-    let mut cur_byte_a = start * deque_meta.element_size;
-    let mut deque_accounts_index_a = (start / deque_meta.max_elements_per_account) as usize;
-    let mut deque_data_index_a = ((start % deque_meta.max_elements_per_account) * deque_meta.element_size) as usize;
-    let mut cur_byte_b = end * deque_meta.element_size;
-    let mut deque_accounts_index_b = (end / deque_meta.max_elements_per_account) as usize;
-    let mut deque_data_index_b = ((end % deque_meta.max_elements_per_account) * deque_meta.element_size) as usize;
-
-    for _x in 0..bytes_to_shift{
-        deque_account_refs[deque_accounts_index_a][deque_data_index_a] = deque_account_refs[deque_accounts_index_b][deque_data_index_b];
-
-        deque_data_index_a += 1;
-        cur_byte_a += 1;
-        if cur_byte_a >= deque_meta.max_bytes{
-            deque_accounts_index_a = 0;
-            deque_data_index_a = 0;
-            cur_byte_a = 0;
-        }
-        else if deque_data_index_a as u64 >= deque_meta.max_bytes_per_account{
-            deque_accounts_index_a += 1;
-            deque_data_index_a = 0;
-        }
-        
-        deque_data_index_b += 1;
-        cur_byte_b += 1;
-        if cur_byte_b >= deque_meta.max_bytes{
-            deque_accounts_index_b = 0;
-            deque_data_index_b = 0;
-            cur_byte_b = 0;
-        }
-        else if deque_data_index_b as u64 >= deque_meta.max_bytes_per_account{
-            deque_accounts_index_b += 1;
-            deque_data_index_b = 0;
-        }
+    let mut deque_storage = PagedStorage::new(pages, &deque_accounts, deque_meta.element_size, deque_meta.max_elements_per_account, deque_meta.data_header_len())?;
+    let ret = read_range(&deque_storage, &deque_meta, start, num_elements)?;
+    // shift the elements after the removed range forward, one at a time since either range may wrap
+    for i in 0..(deque_meta.length - end){
+        deque_storage.copy_range(physical_index(&deque_meta, end + i), physical_index(&deque_meta, start + i), 1)?;
     }
 
     deque_meta.length = new_length;
@@ -655,41 +959,1238 @@ pub fn remove_slice(
 pub fn remove(
     accounts: &[AccountInfo],
     index: u64,
+    pages: &[u32],
+    program_id: &Pubkey,
 ) -> Result<Vec<u8>, ProgramError> {
-    Ok((remove_slice(
+    remove_slice(
         accounts,
         index,
-        index + 1,
-    )?).pop().ok_or(ProgramError::InvalidArgument)?)
+        index.checked_add(1).ok_or(DequeError::IndexOutofBounds)?,
+        pages,
+        program_id,
+    )?.pop().ok_or(ProgramError::InvalidArgument)
 }
 
-pub fn delete(
+/// Overwrites the elements from `index` places behind the front on with the elements packed in
+/// `data`, following the wrap. The length does not change.
+pub fn set(
     accounts: &[AccountInfo],
-) -> ProgramResult{
+    index: u64,
+    data: &[u8],
+    pages: &[u32],
+    program_id: &Pubkey,
+) -> ProgramResult {
 
     let account_info_iter = &mut accounts.iter().peekable();
+
     let auth = next_account_info(account_info_iter)?;
     let deque_meta_account = next_account_info(account_info_iter)?;
+
     let mut deque_accounts = Vec::new();
     while account_info_iter.peek().is_some(){
         deque_accounts.push(next_account_info(account_info_iter)?);
     }
 
-    let mut auth_lamports = auth.lamports.borrow_mut();
-    let mut deque_meta_lamports = deque_meta_account.lamports.borrow_mut();
+    check_meta_account(deque_meta_account, program_id)?;
+    let deque_meta = DequeMeta::try_from_slice(&deque_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&deque_meta, &mut deque_accounts, program_id)?;
+    check_role(&deque_meta, auth, Role::Pusher)?;
+    check_deque_accounts(deque_meta_account, &deque_meta, pages, &deque_accounts, program_id)?;
+    check_provisioned(&deque_meta)?;
+
+    if data.len() % (deque_meta.element_size) as usize != 0{
+        msg!("Data length not multiple of element size");
+        return Err(ProgramError::InvalidArgument);
+    }
 
-    **auth_lamports = auth_lamports.
-        checked_add(**deque_meta_lamports)
-        .ok_or(DequeError::Overflow)?;
-    **deque_meta_lamports = 0;
+    let num_elements = data.len() as u64 / deque_meta.element_size;
+    if index.checked_add(num_elements).ok_or(DequeError::Overflow)? > deque_meta.length{
+        msg!("Index Out of Bounds");
+        return Err(DequeError::IndexOutofBounds.into());
+    }
 
-    for i in 0..deque_accounts.len(){
-        let mut account_lamports = deque_accounts[i].lamports.borrow_mut();
-        **auth_lamports = auth_lamports
-                .checked_add(**account_lamports)
-                .ok_or(DequeError::Overflow)?;
-        **account_lamports = 0;
+    let mut deque_storage = PagedStorage::new(pages, &deque_accounts, deque_meta.element_size, deque_meta.max_elements_per_account, deque_meta.data_header_len())?;
+    // one element at a time since the range may wrap
+    for (i, element) in data.chunks(deque_meta.element_size as usize).enumerate(){
+        deque_storage.write(physical_index(&deque_meta, index + i as u64), element)?;
     }
 
     Ok(())
 }
+
+/// Grows the data accounts passed towards their full size by up to `MAX_PERMITTED_DATA_INCREASE`
+/// bytes, the most a program may allocate per account in one instruction, with the signer paying
+/// the rent. Large deques need several `Extend` instructions after `Initialize` before they can be
+/// used. Data accounts that do not exist yet are created.
+pub fn extend(
+    accounts: &[AccountInfo],
+    pages: &[u32],
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let deque_meta_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let mut deque_accounts = Vec::new();
+    while account_info_iter.peek().is_some(){
+        deque_accounts.push(next_account_info(account_info_iter)?);
+    }
+
+    check_meta_account(deque_meta_account, program_id)?;
+    let mut deque_meta = DequeMeta::try_from_slice(&deque_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&deque_meta, &mut deque_accounts, program_id)?;
+    check_role(&deque_meta, auth, Role::Admin)?;
+    check_deque_accounts(deque_meta_account, &deque_meta, pages, &deque_accounts, program_id)?;
+
+    for (&page, &deque_account) in pages.iter().zip(deque_accounts.iter()){
+        let target = deque_meta.data_header_len() + deque_meta.account_bytes(page as u64);
+        deque_meta.provisioned_bytes += provision_deque_account(auth, system_program, deque_meta_account, &deque_meta, deque_account, page, target, program_id)?;
+    }
+
+    deque_meta.serialize(&mut *deque_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Grows the deque to `max_length` elements in place, in steps. The first `Resize` records the new
+/// capacity as pending and lets `Extend` provision the data accounts towards it; the deque takes
+/// no pushes, pops or writes until the resize completes. A ring that wraps has to move the shorter
+/// of its parts into the new space first: each `Resize` moves as many of its elements as the data
+/// accounts passed hold on both sides, from the last one down, once `Extend` has provisioned their
+/// destination. The `Resize` that moves the last of them, or the first one if nothing has to move,
+/// sets the new capacity. Lazy deques create the data accounts the elements move to. The meta keeps
+/// the address derived from the max length it was created with.
+pub fn resize(
+    accounts: &[AccountInfo],
+    max_length: u64,
+    pages: &[u32],
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let deque_meta_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let mut deque_accounts = Vec::new();
+    while account_info_iter.peek().is_some(){
+        deque_accounts.push(next_account_info(account_info_iter)?);
+    }
+
+    check_meta_account(deque_meta_account, program_id)?;
+    let mut deque_meta = DequeMeta::try_from_slice(&deque_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&deque_meta, &mut deque_accounts, program_id)?;
+
+    // only the authority itself may resize the deque
+    check_authority(&deque_meta, auth)?;
+
+    if deque_meta.pending_max_length == 0{
+        check_provisioned(&deque_meta)?;
+        if max_length < deque_meta.max_length{
+            msg!("Resize cannot shrink the deque");
+            return Err(DequeError::InvalidParameters.into());
+        }
+        check_parameters(max_length, deque_meta.element_size)?;
+
+        // the ring keeps its old capacity until its elements have moved, the data accounts and
+        // `Extend` already follow the new one
+        deque_meta.pending_max_length = max_length;
+        deque_meta.moved_length = 0;
+        deque_meta.max_bytes = max_length * deque_meta.element_size;
+        check_num_accounts(&deque_meta)?;
+    } else if max_length != deque_meta.pending_max_length{
+        msg!("Deque is resizing to {} elements", deque_meta.pending_max_length);
+        return Err(DequeError::ResizePending.into());
+    }
+    check_deque_accounts(deque_meta_account, &deque_meta, pages, &deque_accounts, program_id)?;
+
+    // a ring that wraps around the end of the old capacity would come apart under the new one. Like
+    // `VecDeque` growing, only the shorter of its two parts moves: the back part at the start of the
+    // buffer into the new space after the old capacity, or the front part at the end of the old
+    // capacity to the end of the new one. Neither overwrites elements of the ring, which stays
+    // readable under the old capacity until the last of them has moved
+    let old_max_length = deque_meta.max_length;
+    let front_len = old_max_length - deque_meta.start;
+    let (src, dst, count) = if deque_meta.length <= front_len{
+        (0, 0, 0)
+    } else if deque_meta.length - front_len <= min(front_len, max_length - old_max_length){
+        (0, old_max_length, deque_meta.length - front_len)
+    } else {
+        (deque_meta.start, max_length - front_len, front_len)
+    };
+
+    // a `Clear` in between leaves nothing to move
+    let remaining = count.saturating_sub(deque_meta.moved_length);
+    let movable = movable_elements(&deque_meta, pages, &deque_accounts, src, dst, remaining);
+    if movable > 0{
+        let (src, dst) = (src + remaining - movable, dst + remaining - movable);
+        if deque_meta.lazy{
+            let per_account = deque_meta.max_elements_per_account;
+            let indices = (dst / per_account..=(dst + movable - 1) / per_account).map(|page| max(page * per_account, dst));
+            provision_elements(auth, system_program, deque_meta_account, &mut deque_meta, pages, &deque_accounts, indices, program_id)?;
+        }
+        let mut deque_storage = PagedStorage::new(pages, &deque_accounts, deque_meta.element_size, deque_meta.max_elements_per_account, deque_meta.data_header_len())?;
+        deque_storage.copy_range(src, dst, movable)?;
+        deque_meta.moved_length += movable;
+    }
+
+    if movable == remaining{
+        // the ring starts where its front part moved to
+        if count > 0 && src == deque_meta.start{
+            deque_meta.start = dst;
+        }
+        deque_meta.max_length = max_length;
+        deque_meta.pending_max_length = 0;
+        deque_meta.moved_length = 0;
+        msg!("Resized the deque to {} elements", max_length);
+    } else {
+        let per_account = deque_meta.max_elements_per_account;
+        let (next_src, next_dst) = (src + remaining - movable - 1, dst + remaining - movable - 1);
+        msg!("Moved {} of {} elements, send Resize with deque accounts {} and {} provisioned to move the next ones", count - remaining + movable, count, next_src / per_account, next_dst / per_account);
+    }
+
+    deque_meta.serialize(&mut *deque_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Shrinks the deque to `max_length` elements, which must still hold its elements; shrinking to the
+/// current length fits it to its contents, an empty deque keeps room for one element. Data accounts past the new capacity are closed, the
+/// last one kept is truncated, and the freed rent goes to the authority. Takes the data accounts
+/// from the last one kept on and those the elements move between.
+pub fn shrink(
+    accounts: &[AccountInfo],
+    max_length: u64,
+    pages: &[u32],
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let deque_meta_account = next_account_info(account_info_iter)?;
+    let mut deque_accounts = Vec::new();
+    while account_info_iter.peek().is_some(){
+        deque_accounts.push(next_account_info(account_info_iter)?);
+    }
+
+    check_meta_account(deque_meta_account, program_id)?;
+    let mut deque_meta = DequeMeta::try_from_slice(&deque_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&deque_meta, &mut deque_accounts, program_id)?;
+
+    // only the authority itself may shrink the deque
+    check_authority(&deque_meta, auth)?;
+    check_deque_accounts(deque_meta_account, &deque_meta, pages, &deque_accounts, program_id)?;
+    check_resize_complete(&deque_meta)?;
+
+    // the paging arithmetic needs a capacity of at least one element
+    let max_length = max(max_length, 1);
+    if max_length > deque_meta.max_length || max_length < deque_meta.length{
+        msg!("Shrink cannot grow the deque or drop elements");
+        return Err(DequeError::InvalidParameters.into());
+    }
+    check_parameters(max_length, deque_meta.element_size)?;
+
+    // the elements have to fit the ring of the smaller capacity, only the live ones move: the front
+    // part of a wrapped ring to the end of the new capacity, right after the back part it leaves in
+    // place, or a ring that runs past the new capacity to the start of the buffer
+    if deque_meta.length == 0{
+        deque_meta.start = 0;
+    }
+    let front_len = min(deque_meta.length, deque_meta.max_length - deque_meta.start);
+    let moved = if deque_meta.length > front_len{
+        Some((deque_meta.start, max_length - front_len, front_len))
+    } else if deque_meta.start + deque_meta.length > max_length{
+        Some((deque_meta.start, 0, deque_meta.length))
+    } else {
+        None
+    };
+    if let Some((src, dst, count)) = moved{
+        // the destination may lie in data accounts a lazy deque has not created yet
+        check_range_provisioned(&deque_meta, pages, &deque_accounts, dst, count)?;
+        let mut deque_storage = PagedStorage::new(pages, &deque_accounts, deque_meta.element_size, deque_meta.max_elements_per_account, deque_meta.data_header_len())?;
+        deque_storage.copy_range(src, dst, count)?;
+        deque_meta.start = dst;
+    }
+
+    let old_num_accounts = deque_meta.num_accounts();
+    deque_meta.max_length = max_length;
+    deque_meta.max_bytes = max_length * deque_meta.element_size;
+    let num_accounts = deque_meta.num_accounts();
+    if !(num_accounts - 1..old_num_accounts).all(|page| pages.contains(&(page as u32))){
+        msg!("Shrink takes the deque accounts from {} to {}", num_accounts - 1, old_num_accounts - 1);
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+
+    let rent = Rent::get()?;
+    for (&page, &deque_account) in pages.iter().zip(deque_accounts.iter()){
+        let provisioned = (deque_account.data_len() as u64).saturating_sub(deque_meta.data_header_len());
+        if page as u64 >= num_accounts{
+            // uncreated data accounts have nothing to close
+            if deque_account.owner == program_id{
+                close_account(deque_account, auth)?;
+            }
+            deque_meta.provisioned_bytes = deque_meta.provisioned_bytes.saturating_sub(provisioned);
+            continue;
+        }
+        let size = deque_meta.data_header_len() + deque_meta.account_bytes(page as u64);
+        if (deque_account.data_len() as u64) > size{
+            deque_account.realloc(size as usize, false)?;
+
+            let refund = deque_account.lamports().saturating_sub(rent.minimum_balance(size as usize));
+            let auth_lamports = auth.lamports().checked_add(refund).ok_or(DequeError::Overflow)?;
+            **deque_account.try_borrow_mut_lamports()? -= refund;
+            **auth.try_borrow_mut_lamports()? = auth_lamports;
+            deque_meta.provisioned_bytes = deque_meta.provisioned_bytes.saturating_sub(provisioned - deque_meta.account_bytes(page as u64));
+        }
+    }
+
+    deque_meta.serialize(&mut *deque_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+pub fn delete(
+    accounts: &[AccountInfo],
+    pages: &[u32],
+    program_id: &Pubkey,
+) -> ProgramResult{
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let destination = next_account_info(account_info_iter)?;
+    let deque_meta_account = next_account_info(account_info_iter)?;
+    let mut deque_accounts = Vec::new();
+    while account_info_iter.peek().is_some(){
+        deque_accounts.push(next_account_info(account_info_iter)?);
+    }
+
+    check_meta_account(deque_meta_account, program_id)?;
+    let deque_meta = DequeMeta::try_from_slice(&deque_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&deque_meta, &mut deque_accounts, program_id)?;
+    check_deque_accounts(deque_meta_account, &deque_meta, pages, &deque_accounts, program_id)?;
+    // the data accounts cannot be closed without the meta, deques with more of them than fit in
+    // a transaction are shrunk first
+    if pages.len() as u64 != deque_meta.num_accounts(){
+        msg!("Delete takes every deque account");
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+
+    // only the authority itself may close the deque
+    check_authority(&deque_meta, auth)?;
+
+    if destination.key == deque_meta_account.key || deque_accounts.iter().any(|deque_account| deque_account.key == destination.key){
+        msg!("Destination cannot be one of the deque accounts");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    close_account(deque_meta_account, destination)?;
+    for deque_account in deque_accounts.iter(){
+        // uncreated data accounts have nothing to close
+        if deque_account.owner == program_id{
+            close_account(deque_account, destination)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn set_delegate(
+    accounts: &[AccountInfo],
+    delegate: Pubkey,
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let deque_meta_account = next_account_info(account_info_iter)?;
+
+    check_meta_account(deque_meta_account, program_id)?;
+    let mut deque_meta = DequeMeta::try_from_slice(&deque_meta_account.data.borrow())?;
+    check_cpi_only(&deque_meta, account_info_iter.next(), program_id)?;
+
+    // only the authority itself may change the delegate
+    check_authority(&deque_meta, auth)?;
+
+    deque_meta.delegate = delegate;
+    deque_meta.serialize(&mut *deque_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Empties the deque. The data accounts keep their contents until elements are pushed over them.
+pub fn clear(
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let deque_meta_account = next_account_info(account_info_iter)?;
+
+    check_meta_account(deque_meta_account, program_id)?;
+    let mut deque_meta = DequeMeta::try_from_slice(&deque_meta_account.data.borrow())?;
+    check_cpi_only(&deque_meta, account_info_iter.next(), program_id)?;
+
+    // only the authority itself may clear the deque
+    check_authority(&deque_meta, auth)?;
+
+    deque_meta.start = 0;
+    deque_meta.length = 0;
+    deque_meta.serialize(&mut *deque_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Reads the meta `Migrate` rewrites and checks that `auth` may rewrite it. A version 0 meta comes
+/// back with `auth` as its authority and fully provisioned.
+fn read_meta_to_migrate(
+    auth: &AccountInfo,
+    deque_meta_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> Result<(DequeMeta, u8), ProgramError> {
+    if deque_meta_account.owner != program_id{
+        msg!("Invalid deque meta account");
+        return Err(DequeError::InvalidMetaAccount.into());
+    }
+    let (mut deque_meta, version) = DequeMeta::from_any_version(&deque_meta_account.data.borrow())?;
+    if version == LAYOUT_VERSION{
+        msg!("Deque meta is already at layout version {}", LAYOUT_VERSION);
+        return Ok((deque_meta, version));
+    }
+
+    if version == 0{
+        if !auth.is_signer{
+            msg!("Missing authority signature");
+            return Err(DequeError::Unauthorized.into());
+        }
+        // a version 0 meta is told apart by its length alone, so it has to be at the PDA the first
+        // release derived from its authority and sizes, which also makes the signer that authority
+        let (deque_meta_key, _) = Pubkey::find_program_address(
+            &[auth.key.as_ref(), &deque_meta.max_length.to_le_bytes(), &deque_meta.element_size.to_le_bytes()],
+            program_id,
+        );
+        if *deque_meta_account.key != deque_meta_key{
+            msg!("Deque meta account is not the version 0 meta of {}", auth.key);
+            return Err(DequeError::InvalidMetaAccount.into());
+        }
+        if deque_meta.max_bytes_per_account == 0{
+            msg!("Deque meta was never initialized");
+            return Err(DequeError::InvalidMetaAccount.into());
+        }
+        deque_meta.authority = *auth.key;
+        // the first release created every data account at its full size in Initialize
+        deque_meta.provisioned_bytes = deque_meta.max_bytes;
+        msg!("Set the authority to {}, the data accounts keep layout version 0", auth.key);
+    } else {
+        check_authority(&deque_meta, auth)?;
+    }
+
+    Ok((deque_meta, version))
+}
+
+/// Rewrites a meta written in an earlier layout version in the current one, growing the meta
+/// account with `authority` paying the rent, and returns the version it was written in. Fields
+/// that version did not have start zeroed. Version 0 metas predate authorities, so the signer
+/// becomes the authority and has to be the one the `[authority, max_length, element_size]` meta
+/// PDA was derived from; their data accounts keep their layout and the `[meta key, index as u8]`
+/// PDAs.
+pub fn migrate(
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+) -> Result<u8, ProgramError> {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let deque_meta_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    let (deque_meta, version) = read_meta_to_migrate(auth, deque_meta_account, program_id)?;
+    if version == LAYOUT_VERSION{
+        return Ok(version);
+    }
+
+    msg!("Grew the deque meta from {} to {} bytes", deque_meta_account.data_len(), DEQUE_META_LEN);
+    grow_account(auth, system_program, deque_meta_account, DEQUE_META_LEN, &Rent::get()?)?;
+    deque_meta.serialize(&mut *deque_meta_account.data.borrow_mut())?;
+
+    Ok(version)
+}
+
+/// Proposes `new_authority` as the authority of the deque. It takes over, along with the rent refunds,
+/// once it signs `AcceptAuthority`. Proposing the default pubkey withdraws the proposal.
+pub fn set_authority(
+    accounts: &[AccountInfo],
+    new_authority: Pubkey,
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let deque_meta_account = next_account_info(account_info_iter)?;
+
+    check_meta_account(deque_meta_account, program_id)?;
+    let mut deque_meta = DequeMeta::try_from_slice(&deque_meta_account.data.borrow())?;
+    check_cpi_only(&deque_meta, account_info_iter.next(), program_id)?;
+
+    // only the authority itself may hand the deque over
+    check_authority(&deque_meta, auth)?;
+
+    deque_meta.pending_authority = new_authority;
+    deque_meta.serialize(&mut *deque_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Makes the proposed authority, which must sign, the authority of the deque. The delegate of the
+/// previous authority is cleared.
+pub fn accept_authority(
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let new_auth = next_account_info(account_info_iter)?;
+    let deque_meta_account = next_account_info(account_info_iter)?;
+
+    check_meta_account(deque_meta_account, program_id)?;
+    let mut deque_meta = DequeMeta::try_from_slice(&deque_meta_account.data.borrow())?;
+    check_cpi_only(&deque_meta, account_info_iter.next(), program_id)?;
+
+    if deque_meta.pending_authority == Pubkey::default() || !new_auth.is_signer || *new_auth.key != deque_meta.pending_authority{
+        msg!("Missing signature of the proposed authority");
+        return Err(DequeError::Unauthorized.into());
+    }
+
+    deque_meta.authority = deque_meta.pending_authority;
+    deque_meta.pending_authority = Pubkey::default();
+    deque_meta.delegate = Pubkey::default();
+    deque_meta.serialize(&mut *deque_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Adds `member` to the allowlist of `role`.
+pub fn grant_role(
+    accounts: &[AccountInfo],
+    role: Role,
+    member: Pubkey,
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let deque_meta_account = next_account_info(account_info_iter)?;
+
+    check_meta_account(deque_meta_account, program_id)?;
+    let mut deque_meta = DequeMeta::try_from_slice(&deque_meta_account.data.borrow())?;
+    check_cpi_only(&deque_meta, account_info_iter.next(), program_id)?;
+    check_role_manager(&deque_meta, auth, role)?;
+
+    if member == Pubkey::default(){
+        msg!("Cannot grant a role to the default pubkey");
+        return Err(DequeError::InvalidParameters.into());
+    }
+    if !deque_meta.roles.grant(role, member){
+        msg!("The {:?} allowlist is full", role);
+        return Err(DequeError::RoleListFull.into());
+    }
+    deque_meta.serialize(&mut *deque_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Removes `member` from the allowlist of `role`, if it is on it.
+pub fn revoke_role(
+    accounts: &[AccountInfo],
+    role: Role,
+    member: Pubkey,
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let deque_meta_account = next_account_info(account_info_iter)?;
+
+    check_meta_account(deque_meta_account, program_id)?;
+    let mut deque_meta = DequeMeta::try_from_slice(&deque_meta_account.data.borrow())?;
+    check_cpi_only(&deque_meta, account_info_iter.next(), program_id)?;
+    check_role_manager(&deque_meta, auth, role)?;
+
+    deque_meta.roles.revoke(role, &member);
+    deque_meta.serialize(&mut *deque_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Sets whether only other programs may change the deque, e.g. the program whose PDA is the
+/// authority. Reads stay open to everyone. Once set, clearing it takes a call from another program
+/// as well.
+pub fn set_cpi_only(
+    accounts: &[AccountInfo],
+    cpi_only: bool,
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let deque_meta_account = next_account_info(account_info_iter)?;
+
+    check_meta_account(deque_meta_account, program_id)?;
+    let mut deque_meta = DequeMeta::try_from_slice(&deque_meta_account.data.borrow())?;
+    check_cpi_only(&deque_meta, account_info_iter.next(), program_id)?;
+
+    // only the authority itself may restrict the callers
+    check_authority(&deque_meta, auth)?;
+
+    deque_meta.cpi_only = cpi_only;
+    deque_meta.serialize(&mut *deque_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Address and bump of the meta account of the deque `authority` creates with these parameters.
+/// `id` tells apart the deques of one authority that share their sizes.
+pub fn find_meta_address(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    max_length: u64,
+    element_size: u64,
+    id: u64,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[authority.as_ref(), &max_length.to_le_bytes(), &element_size.to_le_bytes(), &id.to_le_bytes()], program_id)
+}
+
+/// Page indices and addresses of the data accounts at `pages` of the deque with meta account `meta`,
+/// as the builders take them. The seed of each one is its page index as a little-endian u32.
+pub fn find_data_account_addresses(
+    program_id: &Pubkey,
+    meta: &Pubkey,
+    pages: &[u32],
+) -> Vec<(u32, Pubkey)> {
+    pages.iter().map(|&page| (page, Pubkey::find_program_address(&[meta.as_ref(), &page.to_le_bytes()], program_id).0)).collect()
+}
+
+/// Number of data accounts backing `max_length` elements of `element_size` bytes, in a lazy
+/// deque if `lazy`.
+pub fn num_data_accounts(
+    max_length: u64,
+    element_size: u64,
+    lazy: bool,
+) -> u64 {
+    let account_size = if lazy { LAZY_ACCOUNT_SIZE } else { MAX_ACCOUNT_SIZE };
+    let max_bytes_per_account = ((account_size - DATA_HEADER_LEN) / element_size) * element_size;
+    (max_length * element_size + max_bytes_per_account - 1) / max_bytes_per_account
+}
+
+/// Creates the deque with its meta and the data accounts at `pages`, paid for by `authority`; `Extend`
+/// creates the others. A `lazy` deque creates its data accounts as pushes need them instead, with the
+/// pusher paying.
+pub fn build_initialize(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    max_length: u64,
+    element_size: u64,
+    lazy: bool,
+    id: u64,
+    pages: &[u32],
+) -> SolInstruction {
+    let (meta, _) = find_meta_address(program_id, authority, max_length, element_size, id);
+    let data_accounts = find_data_account_addresses(program_id, &meta, pages);
+
+    let mut account_metas = vec![
+        AccountMeta::new(*authority, true),
+        AccountMeta::new(meta, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+    account_metas.extend(data_accounts.iter().map(|(_, key)| AccountMeta::new(*key, false)));
+
+    let instruction = Instruction::Initialize(InitializeParams{
+        max_length,
+        element_size,
+        lazy,
+        id,
+    });
+    SolInstruction::new_with_bytes(*program_id, &instruction.pack_with_pages(pages), account_metas)
+}
+
+/// Page indices of `data_accounts`, which the instruction data carries after the instruction.
+fn page_indices(
+    data_accounts: &[(u32, Pubkey)],
+) -> Vec<u32> {
+    data_accounts.iter().map(|(page, _)| *page).collect()
+}
+
+fn mutating_account_metas(
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+) -> Vec<AccountMeta> {
+    let mut account_metas = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    account_metas.extend(data_accounts.iter().map(|(_, key)| AccountMeta::new(*key, false)));
+    account_metas
+}
+
+/// Like `mutating_account_metas` for pushes to a lazy deque, which may create data accounts with the
+/// authority paying.
+fn lazy_push_account_metas(
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+) -> Vec<AccountMeta> {
+    let mut account_metas = vec![
+        AccountMeta::new(*authority, true),
+        AccountMeta::new(*meta, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    account_metas.extend(data_accounts.iter().map(|(_, key)| AccountMeta::new(*key, false)));
+    account_metas
+}
+
+fn read_only_account_metas(
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+) -> Vec<AccountMeta> {
+    let mut account_metas = vec![AccountMeta::new_readonly(*meta, false)];
+    account_metas.extend(data_accounts.iter().map(|(_, key)| AccountMeta::new_readonly(*key, false)));
+    account_metas
+}
+
+/// Pushes the elements in `data` onto the front.
+pub fn build_push_front(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    data: &[u8],
+) -> SolInstruction {
+    let instruction_data = Instruction::PushFront(PushParams{ data: data.to_vec() }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, mutating_account_metas(authority, meta, data_accounts))
+}
+
+/// `build_push_front` for a lazy deque, creating data accounts as needed with `authority` paying.
+pub fn build_push_front_lazy(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    data: &[u8],
+) -> SolInstruction {
+    let instruction_data = Instruction::PushFront(PushParams{ data: data.to_vec() }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, lazy_push_account_metas(authority, meta, data_accounts))
+}
+
+/// Pushes the elements in `data` onto the back.
+pub fn build_push_back(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    data: &[u8],
+) -> SolInstruction {
+    let instruction_data = Instruction::PushBack(PushParams{ data: data.to_vec() }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, mutating_account_metas(authority, meta, data_accounts))
+}
+
+/// `build_push_back` for a lazy deque, creating data accounts as needed with `authority` paying.
+pub fn build_push_back_lazy(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    data: &[u8],
+) -> SolInstruction {
+    let instruction_data = Instruction::PushBack(PushParams{ data: data.to_vec() }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, lazy_push_account_metas(authority, meta, data_accounts))
+}
+
+/// Pops `num_elements` elements from the front.
+pub fn build_pop_front(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    num_elements: u64,
+) -> SolInstruction {
+    let instruction_data = Instruction::PopFront(PopParams{ num_elements }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, mutating_account_metas(authority, meta, data_accounts))
+}
+
+/// Pops `num_elements` elements from the back.
+pub fn build_pop_back(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    num_elements: u64,
+) -> SolInstruction {
+    let instruction_data = Instruction::PopBack(PopParams{ num_elements }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, mutating_account_metas(authority, meta, data_accounts))
+}
+
+/// Reads the elements `start..end`. Read-only, the authority does not sign.
+pub fn build_get(
+    program_id: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    start: u64,
+    end: u64,
+) -> SolInstruction {
+    let instruction_data = Instruction::Get(GetParams{ start, end }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, read_only_account_metas(meta, data_accounts))
+}
+
+/// Removes the elements `start..end`.
+pub fn build_remove(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    start: u64,
+    end: u64,
+) -> SolInstruction {
+    let instruction_data = Instruction::Remove(GetParams{ start, end }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, mutating_account_metas(authority, meta, data_accounts))
+}
+
+/// Overwrites the elements from `index` on with the elements packed in `data`.
+pub fn build_set(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    index: u64,
+    data: &[u8],
+) -> SolInstruction {
+    let instruction_data = Instruction::Set(SetParams{ index, data: data.to_vec() }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, mutating_account_metas(authority, meta, data_accounts))
+}
+
+/// Closes the deque and moves its lamports to `destination`. `data_accounts` lists every data account.
+pub fn build_delete(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    destination: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+) -> SolInstruction {
+    let mut account_metas = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*destination, false),
+        AccountMeta::new(*meta, false),
+    ];
+    account_metas.extend(data_accounts.iter().map(|(_, key)| AccountMeta::new(*key, false)));
+    SolInstruction::new_with_bytes(*program_id, &Instruction::Delete.pack_with_pages(&page_indices(data_accounts)), account_metas)
+}
+
+pub fn build_set_delegate(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    delegate: &Pubkey,
+) -> SolInstruction {
+    let instruction_data = Instruction::SetDelegate(SetDelegateParams{ delegate: *delegate }).pack();
+    let account_metas = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, account_metas)
+}
+
+/// Grows `data_accounts` by up to `MAX_PERMITTED_DATA_INCREASE` bytes each, creating the ones that
+/// do not exist yet, paid by `authority`.
+pub fn build_extend(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+) -> SolInstruction {
+    let mut account_metas = vec![
+        AccountMeta::new(*authority, true),
+        AccountMeta::new(*meta, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    account_metas.extend(data_accounts.iter().map(|(_, key)| AccountMeta::new(*key, false)));
+    SolInstruction::new_with_bytes(*program_id, &Instruction::Extend.pack_with_pages(&page_indices(data_accounts)), account_metas)
+}
+
+/// Grows the deque to `max_length` elements, one step of it: send `build_resize` once, `build_extend`
+/// until the grown deque is provisioned and `build_resize` again until a wrapped ring has moved.
+/// `data_accounts` lists the data accounts the elements move between, see `resize` and
+/// `find_data_account_addresses`.
+pub fn build_resize(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    max_length: u64,
+) -> SolInstruction {
+    let mut account_metas = vec![
+        AccountMeta::new(*authority, true),
+        AccountMeta::new(*meta, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    account_metas.extend(data_accounts.iter().map(|(_, key)| AccountMeta::new(*key, false)));
+    let instruction_data = Instruction::Resize(ResizeParams{ max_length }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, account_metas)
+}
+
+/// Shrinks the deque to `max_length` elements and refunds the freed rent to `authority`.
+/// `data_accounts` lists the data accounts from the last one kept on and those the elements move
+/// between, the ones past the new capacity are closed.
+pub fn build_shrink(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    max_length: u64,
+) -> SolInstruction {
+    let mut account_metas = vec![
+        AccountMeta::new(*authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    account_metas.extend(data_accounts.iter().map(|(_, key)| AccountMeta::new(*key, false)));
+    let instruction_data = Instruction::Shrink(ResizeParams{ max_length }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, account_metas)
+}
+
+/// Empties the deque.
+pub fn build_clear(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+) -> SolInstruction {
+    let account_metas = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    SolInstruction::new_with_bytes(*program_id, &Instruction::Clear.pack(), account_metas)
+}
+
+/// Rewrites the meta in the current layout, `authority` paying for the larger meta account.
+pub fn build_migrate(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+) -> SolInstruction {
+    let account_metas = vec![
+        AccountMeta::new(*authority, true),
+        AccountMeta::new(*meta, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    SolInstruction::new_with_bytes(*program_id, &Instruction::Migrate.pack(), account_metas)
+}
+
+/// Proposes `new_authority` as the authority of the deque, see `build_accept_authority`.
+pub fn build_set_authority(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    new_authority: &Pubkey,
+) -> SolInstruction {
+    let instruction_data = Instruction::SetAuthority(SetAuthorityParams{ new_authority: *new_authority }).pack();
+    let account_metas = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, account_metas)
+}
+
+/// Makes `new_authority`, proposed by `build_set_authority`, the authority of the deque.
+pub fn build_accept_authority(
+    program_id: &Pubkey,
+    new_authority: &Pubkey,
+    meta: &Pubkey,
+) -> SolInstruction {
+    let account_metas = vec![
+        AccountMeta::new_readonly(*new_authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    SolInstruction::new_with_bytes(*program_id, &Instruction::AcceptAuthority.pack(), account_metas)
+}
+
+/// Adds `member` to the allowlist of `role`. `authority` is the authority, or an admin for the
+/// pusher and popper roles.
+pub fn build_grant_role(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    role: Role,
+    member: &Pubkey,
+) -> SolInstruction {
+    let instruction_data = Instruction::GrantRole(RoleParams{ role, member: *member }).pack();
+    let account_metas = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, account_metas)
+}
+
+/// Removes `member` from the allowlist of `role`, see `build_grant_role`.
+pub fn build_revoke_role(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    role: Role,
+    member: &Pubkey,
+) -> SolInstruction {
+    let instruction_data = Instruction::RevokeRole(RoleParams{ role, member: *member }).pack();
+    let account_metas = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, account_metas)
+}
+
+/// Sets whether only other programs may change the deque.
+pub fn build_set_cpi_only(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    cpi_only: bool,
+) -> SolInstruction {
+    let instruction_data = Instruction::SetCpiOnly(SetCpiOnlyParams{ cpi_only }).pack();
+    let account_metas = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, account_metas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{DequeMetaV0, DEQUE_META_LENS};
+
+    const MAX_LENGTH: u64 = 3;
+    const ELEMENT_SIZE: u64 = 4;
+
+    /// A version 0 meta as the first release initialized it, with a single data account.
+    fn meta_v0() -> Vec<u8> {
+        DequeMetaV0{
+            max_length: MAX_LENGTH,
+            element_size: ELEMENT_SIZE,
+            max_bytes: MAX_LENGTH * ELEMENT_SIZE,
+            start: 0,
+            length: 0,
+            max_elements_per_account: MAX_LENGTH,
+            max_bytes_per_account: MAX_LENGTH * ELEMENT_SIZE,
+        }.try_to_vec().unwrap()
+    }
+
+    fn meta_v0_key(program_id: &Pubkey, authority: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[authority.as_ref(), &MAX_LENGTH.to_le_bytes(), &ELEMENT_SIZE.to_le_bytes()], program_id).0
+    }
+
+    /// Runs the checks of `Migrate` with `signer` signing for the meta at `meta_key`.
+    fn read(program_id: &Pubkey, signer: &Pubkey, is_signer: bool, meta_key: &Pubkey, owner: &Pubkey, meta: &mut [u8]) -> Result<(DequeMeta, u8), ProgramError> {
+        let (mut auth_lamports, mut meta_lamports) = (0, 0);
+        let mut auth_data = vec![];
+        let auth = AccountInfo::new(signer, is_signer, true, &mut auth_lamports, &mut auth_data, &system_program::ID, false, 0);
+        let deque_meta_account = AccountInfo::new(meta_key, false, true, &mut meta_lamports, meta, owner, false, 0);
+        read_meta_to_migrate(&auth, &deque_meta_account, program_id)
+    }
+
+    #[test]
+    fn sets_authority_of_version_0_meta() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let meta_key = meta_v0_key(&program_id, &authority);
+
+        let (deque_meta, version) = read(&program_id, &authority, true, &meta_key, &program_id, &mut meta_v0()).unwrap();
+        assert_eq!(version, 0);
+        assert_eq!(deque_meta.authority, authority);
+        assert_eq!(deque_meta.provisioned_bytes, MAX_LENGTH * ELEMENT_SIZE);
+        assert!(deque_meta.legacy);
+    }
+
+    #[test]
+    fn rejects_wrong_signer() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let meta_key = meta_v0_key(&program_id, &authority);
+
+        let res = read(&program_id, &Pubkey::new_unique(), true, &meta_key, &program_id, &mut meta_v0());
+        assert_eq!(res.unwrap_err(), DequeError::InvalidMetaAccount.into());
+        let res = read(&program_id, &authority, false, &meta_key, &program_id, &mut meta_v0());
+        assert_eq!(res.unwrap_err(), DequeError::Unauthorized.into());
+
+        // a later layout already names its authority
+        let (mut deque_meta, _) = DequeMeta::from_any_version(&meta_v0()).unwrap();
+        deque_meta.version = 1;
+        deque_meta.authority = authority;
+        deque_meta.legacy = false;
+        let mut meta_v1 = deque_meta.try_to_vec().unwrap();
+        meta_v1.truncate(DEQUE_META_LENS[1] as usize);
+        let res = read(&program_id, &Pubkey::new_unique(), true, &meta_key, &program_id, &mut meta_v1.clone());
+        assert_eq!(res.unwrap_err(), DequeError::Unauthorized.into());
+        let (deque_meta, version) = read(&program_id, &authority, true, &meta_key, &program_id, &mut meta_v1).unwrap();
+        assert_eq!(version, 1);
+        assert_eq!(deque_meta.authority, authority);
+    }
+
+    #[test]
+    fn rejects_non_meta_account() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let meta_key = meta_v0_key(&program_id, &authority);
+
+        // any program owned account of the version 0 length, e.g. a data account
+        let res = read(&program_id, &authority, true, &Pubkey::new_unique(), &program_id, &mut meta_v0());
+        assert_eq!(res.unwrap_err(), DequeError::InvalidMetaAccount.into());
+        let res = read(&program_id, &authority, true, &meta_key, &Pubkey::new_unique(), &mut meta_v0());
+        assert_eq!(res.unwrap_err(), DequeError::InvalidMetaAccount.into());
+        let res = read(&program_id, &authority, true, &meta_key, &program_id, &mut [0; 16]);
+        assert!(res.is_err());
+    }
+
+    /// A meta of the current layout whose ring of `max_length` elements holds `length` elements from
+    /// `start` on, fully provisioned.
+    fn meta(authority: &Pubkey, max_length: u64, element_size: u64, start: u64, length: u64, lazy: bool) -> DequeMeta {
+        let account_size = if lazy { LAZY_ACCOUNT_SIZE } else { MAX_ACCOUNT_SIZE };
+        let max_elements_per_account = (account_size - DATA_HEADER_LEN) / element_size;
+        DequeMeta{
+            discriminator: DEQUE_META_DISCRIMINATOR,
+            version: LAYOUT_VERSION,
+            max_length,
+            element_size,
+            max_bytes: max_length * element_size,
+            start,
+            length,
+            max_elements_per_account,
+            max_bytes_per_account: max_elements_per_account * element_size,
+            authority: *authority,
+            delegate: Pubkey::default(),
+            provisioned_bytes: max_length * element_size,
+            lazy,
+            id: 0,
+            is_initialized: true,
+            legacy: false,
+            pending_authority: Pubkey::default(),
+            roles: Roles::default(),
+            cpi_only: false,
+            pending_max_length: 0,
+            moved_length: 0,
+        }
+    }
+
+    /// Data accounts of `elements_per_account` elements each that hold `max_length` elements, element
+    /// `i` filled with the bytes of `i`.
+    fn data_accounts(max_length: u64, element_size: u64, elements_per_account: u64) -> Vec<Vec<u8>> {
+        (0..(max_length + elements_per_account - 1) / elements_per_account).map(|page| {
+            let mut data = DATA_HEADER.try_to_vec().unwrap();
+            for i in page * elements_per_account..min((page + 1) * elements_per_account, max_length){
+                data.extend((0..element_size).map(|byte| (i >> (8 * (byte % 2))) as u8));
+            }
+            data
+        }).collect()
+    }
+
+    /// Runs `Resize` to `max_length` as the authority with the data accounts of `pages`.
+    fn run_resize(program_id: &Pubkey, authority: &Pubkey, meta_key: &Pubkey, meta: &mut DequeMeta, data: &mut [Vec<u8>], pages: &[u32], max_length: u64) -> ProgramResult {
+        let (mut auth_lamports, mut meta_lamports, mut system_lamports) = (0, 0, 0);
+        let (mut auth_data, mut system_data) = (vec![], vec![]);
+        let loader = Pubkey::default();
+        let mut meta_data = meta.try_to_vec().unwrap();
+        let keys: Vec<Pubkey> = pages.iter().map(|page| Pubkey::find_program_address(&[meta_key.as_ref(), &page.to_le_bytes()], program_id).0).collect();
+        let mut lamports = vec![0; pages.len()];
+        let mut accounts = vec![
+            AccountInfo::new(authority, true, true, &mut auth_lamports, &mut auth_data, &system_program::ID, false, 0),
+            AccountInfo::new(meta_key, false, true, &mut meta_lamports, &mut meta_data, program_id, false, 0),
+            AccountInfo::new(&system_program::ID, false, false, &mut system_lamports, &mut system_data, &loader, true, 0),
+        ];
+        let passed = data.iter_mut().enumerate().filter(|(page, _)| pages.contains(&(*page as u32))).map(|(_, data)| data);
+        for ((key, lamports), data) in keys.iter().zip(lamports.iter_mut()).zip(passed){
+            accounts.push(AccountInfo::new(key, false, true, lamports, data, program_id, false, 0));
+        }
+        let res = resize(&accounts, max_length, pages, program_id);
+        drop(accounts);
+        *meta = DequeMeta::try_from_slice(&meta_data).unwrap();
+        res
+    }
+
+    /// The elements of the deque from the front, each by the index it was filled with.
+    fn elements(meta: &DequeMeta, data: &[Vec<u8>]) -> Vec<u64> {
+        let per_account = meta.max_elements_per_account;
+        (0..meta.length).map(|i| {
+            let index = physical_index(meta, i);
+            let offset = (DATA_HEADER_LEN + index % per_account * meta.element_size) as usize;
+            let element = &data[(index / per_account) as usize][offset..offset + meta.element_size as usize];
+            u16::from_le_bytes([element[0], element[1]]) as u64
+        }).collect()
+    }
+
+    #[test]
+    fn resizes_wrapped_deque_once_extended() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let meta_key = Pubkey::new_unique();
+
+        // a ring of 1 KiB elements that wraps with 300 KiB parts on either side of the end
+        let element_size = 1024;
+        let mut deque_meta = meta(&authority, 600, element_size, 300, 600, false);
+        let mut data = data_accounts(600, element_size, deque_meta.max_elements_per_account);
+        let before = elements(&deque_meta, &data);
+        assert_eq!(before[..2], [300, 301]);
+        assert_eq!(before[300..302], [0, 1]);
+
+        // the new space is not provisioned yet, nothing moves
+        run_resize(&program_id, &authority, &meta_key, &mut deque_meta, &mut data, &[0], 1000).unwrap();
+        assert_eq!((deque_meta.max_length, deque_meta.pending_max_length, deque_meta.moved_length), (600, 1000, 0));
+        assert_eq!(deque_meta.max_bytes, 1000 * element_size);
+        assert_eq!(check_provisioned(&deque_meta), Err(DequeError::ResizePending.into()));
+        let res = run_resize(&program_id, &authority, &meta_key, &mut deque_meta, &mut data, &[0], 2000);
+        assert_eq!(res, Err(DequeError::ResizePending.into()));
+
+        // one `Extend` only reaches the first 10 elements of the new space
+        let extended = data[0].len() + MAX_PERMITTED_DATA_INCREASE;
+        data[0].resize(extended, 0);
+        run_resize(&program_id, &authority, &meta_key, &mut deque_meta, &mut data, &[0], 1000).unwrap();
+        assert_eq!(deque_meta.moved_length, 0);
+
+        // the back part moves behind the old capacity, at most `MAX_RESIZE_MOVE_BYTES` at a time
+        data[0].resize((DATA_HEADER_LEN + 1000 * element_size) as usize, 0);
+        deque_meta.provisioned_bytes = 1000 * element_size;
+        run_resize(&program_id, &authority, &meta_key, &mut deque_meta, &mut data, &[0], 1000).unwrap();
+        assert_eq!((deque_meta.max_length, deque_meta.moved_length), (600, MAX_RESIZE_MOVE_BYTES / element_size));
+        assert_eq!(elements(&deque_meta, &data), before);
+        run_resize(&program_id, &authority, &meta_key, &mut deque_meta, &mut data, &[0], 1000).unwrap();
+        assert_eq!((deque_meta.max_length, deque_meta.start, deque_meta.pending_max_length, deque_meta.moved_length), (1000, 300, 0, 0));
+        assert_eq!(elements(&deque_meta, &data), before);
+        assert!(check_provisioned(&deque_meta).is_ok());
+    }
+
+    #[test]
+    fn moves_front_part_with_the_data_accounts_passed() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let meta_key = Pubkey::new_unique();
+
+        // 9 elements per data account, the 26 elements of the back part outgrow the new space
+        let element_size = 1024;
+        let mut deque_meta = meta(&authority, 40, element_size, 30, 36, true);
+        let per_account = deque_meta.max_elements_per_account;
+        assert_eq!(per_account, 9);
+        let mut data = data_accounts(40, element_size, per_account);
+        let before = elements(&deque_meta, &data);
+        // the data accounts of the new space, created whole
+        data[4].resize((DATA_HEADER_LEN + per_account * element_size) as usize, 0);
+        data.push(data_accounts(per_account, element_size, per_account).remove(0));
+        data.push(data_accounts(per_account, element_size, per_account).remove(0));
+
+        // the front part, 30..40, moves to the end of the new capacity, 50..60, from its last
+        // element down as far as the data accounts passed hold both sides
+        run_resize(&program_id, &authority, &meta_key, &mut deque_meta, &mut data, &[4, 6], 60).unwrap();
+        assert_eq!((deque_meta.max_length, deque_meta.pending_max_length, deque_meta.moved_length), (40, 60, 4));
+        assert_eq!(elements(&deque_meta, &data), before);
+        run_resize(&program_id, &authority, &meta_key, &mut deque_meta, &mut data, &[5], 60).unwrap();
+        assert_eq!(deque_meta.moved_length, 4);
+
+        run_resize(&program_id, &authority, &meta_key, &mut deque_meta, &mut data, &[3, 5, 6], 60).unwrap();
+        assert_eq!((deque_meta.max_length, deque_meta.start, deque_meta.pending_max_length), (60, 50, 0));
+        assert_eq!(elements(&deque_meta, &data), before);
+    }
+}
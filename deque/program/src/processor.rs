@@ -1,15 +1,17 @@
 use solana_program::{
-    account_info::{next_account_info, AccountInfo},
+    account_info::AccountInfo,
     entrypoint::ProgramResult,
     msg,
-    program_error::ProgramError,
     pubkey::Pubkey,
 };
-use borsh::{BorshDeserialize};
 
-use crate::instruction::{Instruction, InitializeParams, PopParams, GetParams,
+use solana_data_structures_core::return_data::set_elements_return_data;
+
+use crate::state::LAYOUT_VERSION;
+
+use crate::instruction::{Instruction, InitializeParams, SetDelegateParams, SetAuthorityParams, RoleParams, SetCpiOnlyParams,
                          initialize_deque_signed, push_front, push_back, pop_slice_front, pop_slice_back, 
-                         slice, remove_slice, delete};
+                         slice, remove_slice, delete, set_delegate, extend, resize, shrink, clear, migrate, set_authority, accept_authority, grant_role, revoke_role, set_cpi_only, set};
 
 pub struct Processor;
 impl Processor {
@@ -18,101 +20,148 @@ impl Processor {
         accounts: &[AccountInfo],
         instruction_data: &[u8],
     ) -> ProgramResult {
-        let (tag, rest) = instruction_data.split_first().ok_or(ProgramError::InvalidInstructionData)?;
-        let instruction = Instruction::unpack(tag)?;
+        let (instruction, pages) = Instruction::unpack(instruction_data)?;
         match instruction {
-            Instruction::Initialize => {
+            Instruction::Initialize(params) => {
                 msg!("Instruction: Initialize");
-                let (inputs, seeds) = rest.split_at(16);
-                let params = InitializeParams::try_from_slice(inputs).unwrap();
-                Self::process_initialize(accounts, params.max_length, params.element_size, program_id, seeds)
+                Self::process_initialize(accounts, params, &pages, program_id)
             },
-            Instruction::PushFront => {
+            Instruction::PushFront(params) => {
                 msg!("Instruction: PushFront");
-                Self::process_push_front(accounts, rest)
+                Self::process_push_front(accounts, &params.data, &pages, program_id)
             }
-            Instruction::PushBack => {
+            Instruction::PushBack(params) => {
                 msg!("Instruction: PushBack");
-                Self::process_push_back(accounts, rest)
+                Self::process_push_back(accounts, &params.data, &pages, program_id)
             }
-            Instruction::PopFront => {
+            Instruction::PopFront(params) => {
                 msg!("Instruction: PopFront");
-                let params = PopParams::try_from_slice(rest).unwrap();
-                Self::process_pop_front(accounts, params.num_elements)
+                Self::process_pop_front(accounts, params.num_elements, &pages, program_id)
             }
-            Instruction::PopBack => {
+            Instruction::PopBack(params) => {
                 msg!("Instruction: PopBack");
-                let params = PopParams::try_from_slice(rest).unwrap();
-                Self::process_pop_back(accounts, params.num_elements)
+                Self::process_pop_back(accounts, params.num_elements, &pages, program_id)
             }
-            Instruction::Get => {
+            Instruction::Get(params) => {
                 msg!("Instruction: Get");
-                let params = GetParams::try_from_slice(rest).unwrap();
-                Self::process_get(accounts, params.start, params.end)
+                Self::process_get(accounts, params.start, params.end, &pages, program_id)
             }
-            Instruction::Remove => {
+            Instruction::Remove(params) => {
                 msg!("Instruction: Remove");
-                let params = GetParams::try_from_slice(rest).unwrap();
-                Self::process_remove(accounts, params.start, params.end)
+                Self::process_remove(accounts, params.start, params.end, &pages, program_id)
             }
             Instruction::Delete => {
                 msg!("Instruction: Delete");
-                Self::process_delete(accounts)
+                Self::process_delete(accounts, &pages, program_id)
+            }
+            Instruction::SetDelegate(params) => {
+                msg!("Instruction: SetDelegate");
+                Self::process_set_delegate(accounts, params, program_id)
+            }
+            Instruction::Extend => {
+                msg!("Instruction: Extend");
+                Self::process_extend(accounts, &pages, program_id)
+            }
+            Instruction::Resize(params) => {
+                msg!("Instruction: Resize");
+                Self::process_resize(accounts, params.max_length, &pages, program_id)
+            }
+            Instruction::Shrink(params) => {
+                msg!("Instruction: Shrink");
+                Self::process_shrink(accounts, params.max_length, &pages, program_id)
+            }
+            Instruction::Clear => {
+                msg!("Instruction: Clear");
+                Self::process_clear(accounts, program_id)
+            }
+            Instruction::Migrate => {
+                msg!("Instruction: Migrate");
+                Self::process_migrate(accounts, program_id)
+            }
+            Instruction::SetAuthority(params) => {
+                msg!("Instruction: SetAuthority");
+                Self::process_set_authority(accounts, params, program_id)
+            }
+            Instruction::AcceptAuthority => {
+                msg!("Instruction: AcceptAuthority");
+                Self::process_accept_authority(accounts, program_id)
+            }
+            Instruction::GrantRole(params) => {
+                msg!("Instruction: GrantRole");
+                Self::process_grant_role(accounts, params, program_id)
+            }
+            Instruction::RevokeRole(params) => {
+                msg!("Instruction: RevokeRole");
+                Self::process_revoke_role(accounts, params, program_id)
+            }
+            Instruction::SetCpiOnly(params) => {
+                msg!("Instruction: SetCpiOnly");
+                Self::process_set_cpi_only(accounts, params, program_id)
+            }
+            Instruction::Set(params) => {
+                msg!("Instruction: Set");
+                Self::process_set(accounts, params.index, &params.data, &pages, program_id)
             }
         }
     }
 
     fn process_initialize(
         accounts: &[AccountInfo],
-        max_length: u64,
-        element_size: u64,
+        params: InitializeParams,
+        pages: &[u32],
         program_id: &Pubkey,
-        seeds: &[u8],
     ) -> ProgramResult {
-        let auth = next_account_info(&mut accounts.iter())?;
-        let (meta_bumper, deque_bumper_seeds) = seeds.split_first().ok_or(ProgramError::InvalidInstructionData)?;
-        let meta_seeds = &[auth.key.as_ref(), &max_length.to_le_bytes(), &element_size.to_le_bytes(), &[*meta_bumper]];
-        initialize_deque_signed(accounts, max_length, element_size, program_id, meta_seeds, deque_bumper_seeds)?;
+        initialize_deque_signed(accounts, params.max_length, params.element_size, params.lazy, params.id, pages, program_id)?;
         Ok(())
     }
 
     fn process_push_front(
         accounts: &[AccountInfo],
-        data: &[u8]
+        data: &[u8],
+        pages: &[u32],
+        program_id: &Pubkey,
     ) -> ProgramResult {
-        push_front(accounts, data)?;
+        push_front(accounts, data, pages, program_id)?;
         Ok(())
     }
 
     fn process_push_back(
         accounts: &[AccountInfo],
-        data: &[u8]
+        data: &[u8],
+        pages: &[u32],
+        program_id: &Pubkey,
     ) -> ProgramResult {
-        push_back(accounts, data)?;
+        push_back(accounts, data, pages, program_id)?;
         Ok(())
     }
 
     fn process_pop_front(
         accounts: &[AccountInfo],
         num_elements: u64,
+        pages: &[u32],
+        program_id: &Pubkey,
     ) -> ProgramResult {
-        let res = pop_slice_front(accounts, num_elements)?;
+        let res = pop_slice_front(accounts, num_elements, pages, program_id)?;
         msg!("Popped the entries from front:");
-        for i in 0..res.len(){
-            msg!{"{:?}", res[i]};
+        for entry in res.iter(){
+            msg!{"{:?}", entry};
         }
+        set_elements_return_data(&res);
         Ok(())
     }
 
     fn process_pop_back(
         accounts: &[AccountInfo],
         num_elements: u64,
+        pages: &[u32],
+        program_id: &Pubkey,
     ) -> ProgramResult {
-        let res = pop_slice_back(accounts, num_elements)?;
+        let res = pop_slice_back(accounts, num_elements, pages, program_id)?;
         msg!("Popped the entries from back:");
-        for i in 0..res.len(){
-            msg!{"{:?}", res[i]};
+        for entry in res.iter(){
+            msg!{"{:?}", entry};
         }
+        set_elements_return_data(&res);
         Ok(())
     }
 
@@ -120,12 +169,15 @@ impl Processor {
         accounts: &[AccountInfo],
         start: u64,
         end: u64,
+        pages: &[u32],
+        program_id: &Pubkey,
     ) -> ProgramResult {
-        let res = slice(accounts, start, end)?;
+        let res = slice(accounts, start, end, pages, program_id)?;
         msg!("Got the entries:");
-        for i in 0..res.len(){
-            msg!{"{:?}", res[i]};
+        for entry in res.iter(){
+            msg!{"{:?}", entry};
         }
+        set_elements_return_data(&res);
         Ok(())
     }
 
@@ -133,20 +185,144 @@ impl Processor {
         accounts: &[AccountInfo],
         start: u64,
         end: u64,
+        pages: &[u32],
+        program_id: &Pubkey,
     ) -> ProgramResult {
-        let res = remove_slice(accounts, start, end)?;
+        let res = remove_slice(accounts, start, end, pages, program_id)?;
         msg!("Removed the entries:");
-        for i in 0..res.len(){
-            msg!{"{:?}", res[i]};
+        for entry in res.iter(){
+            msg!{"{:?}", entry};
         }
+        set_elements_return_data(&res);
+        Ok(())
+    }
+
+    fn process_set(
+        accounts: &[AccountInfo],
+        index: u64,
+        data: &[u8],
+        pages: &[u32],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        set(accounts, index, data, pages, program_id)?;
+        msg!("Set the entries from index {}", index);
         Ok(())
     }
 
     fn process_delete(
         accounts: &[AccountInfo],
+        pages: &[u32],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        delete(accounts, pages, program_id)?;
+        msg!("Closed the deque and moved the lamports to the destination");
+        Ok(())
+    }
+
+    fn process_set_delegate(
+        accounts: &[AccountInfo],
+        params: SetDelegateParams,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        set_delegate(accounts, params.delegate, program_id)?;
+        msg!("Set delegate to {}", params.delegate);
+        Ok(())
+    }
+
+    fn process_extend(
+        accounts: &[AccountInfo],
+        pages: &[u32],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        extend(accounts, pages, program_id)?;
+        msg!("Extended the deque data accounts");
+        Ok(())
+    }
+
+    fn process_resize(
+        accounts: &[AccountInfo],
+        max_length: u64,
+        pages: &[u32],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        resize(accounts, max_length, pages, program_id)
+    }
+
+    fn process_shrink(
+        accounts: &[AccountInfo],
+        max_length: u64,
+        pages: &[u32],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        shrink(accounts, max_length, pages, program_id)?;
+        msg!("Shrank the deque to {} elements", max_length);
+        Ok(())
+    }
+
+    fn process_clear(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        clear(accounts, program_id)?;
+        msg!("Cleared the deque");
+        Ok(())
+    }
+
+    fn process_migrate(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let version = migrate(accounts, program_id)?;
+        msg!("Migrated the deque meta from layout version {} to {}", version, LAYOUT_VERSION);
+        Ok(())
+    }
+
+    fn process_set_authority(
+        accounts: &[AccountInfo],
+        params: SetAuthorityParams,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        set_authority(accounts, params.new_authority, program_id)?;
+        msg!("Proposed {} as the new authority", params.new_authority);
+        Ok(())
+    }
+
+    fn process_accept_authority(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        accept_authority(accounts, program_id)?;
+        msg!("Accepted the authority of the deque");
+        Ok(())
+    }
+
+    fn process_grant_role(
+        accounts: &[AccountInfo],
+        params: RoleParams,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        grant_role(accounts, params.role, params.member, program_id)?;
+        msg!("Granted the {:?} role to {}", params.role, params.member);
+        Ok(())
+    }
+
+    fn process_revoke_role(
+        accounts: &[AccountInfo],
+        params: RoleParams,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        revoke_role(accounts, params.role, params.member, program_id)?;
+        msg!("Revoked the {:?} role from {}", params.role, params.member);
+        Ok(())
+    }
+
+    fn process_set_cpi_only(
+        accounts: &[AccountInfo],
+        params: SetCpiOnlyParams,
+        program_id: &Pubkey,
     ) -> ProgramResult {
-        delete(accounts)?;
-        msg!("Removed the lamports from all the accounts");
+        set_cpi_only(accounts, params.cpi_only, program_id)?;
+        msg!("Set cpi_only to {}", params.cpi_only);
         Ok(())
     }
 }
@@ -1,3 +1,4 @@
+import base64
 import struct
 
 from solana.rpc.api import Client
@@ -10,26 +11,46 @@ from solana.system_program import SYS_PROGRAM_ID
 
 PID = PublicKey('EVsbDYrp15AfLfGdKWQSg7o3QPhVdpcG9ujWoaFxpBQo')
 CLIENT_ADDRESS = "https://api.devnet.solana.com"
+MAX_ACCOUNT_SIZE = 10 * 1024 * 1024
+MAX_PERMITTED_DATA_INCREASE = 10 * 1024
+# every data account starts with an 8 byte discriminator and a version byte
+DATA_HEADER_LEN = 9
+# roles of the GrantRole and RevokeRole instructions
+ROLE_PUSHER = 0
+ROLE_POPPER = 1
+ROLE_ADMIN = 2
+
+def pack_pages(pages):
+    # the page indices of the data accounts an instruction passes follow it as a Borsh Vec<u32>
+    pages = list(pages)
+    return struct.pack(f'<I{len(pages)}I', len(pages), *pages)
 
 class Heap:
     
-    def __init__(self, auth, max_length=1048576, element_size=1, data=b'', num_accounts = 10, program_id=PID, client_address=CLIENT_ADDRESS, run_transaction = True):
+    def __init__(self, auth, max_length=1048576, element_size=1, data=b'', num_accounts = None, program_id=PID, client_address=CLIENT_ADDRESS, run_transaction = True, lazy = False, id = 0):
         assert(isinstance(auth, Keypair))
         self.solana_client = Client(client_address)
 
         self.auth = auth
         self.element_size = element_size
         self.max_length = max_length
+        self.lazy = lazy
+        self.id = id
+        # a lazy heap has data accounts small enough for a push to create one whole
+        self.account_size = MAX_PERMITTED_DATA_INCREASE if lazy else MAX_ACCOUNT_SIZE
+        self.elements_per_account = (self.account_size - DATA_HEADER_LEN) // element_size
+        if num_accounts is None:
+            # the data accounts that back max_length elements, each call passes the ones it touches
+            max_bytes_per_account = ((self.account_size - DATA_HEADER_LEN) // element_size) * element_size
+            num_accounts = -(-max_length * element_size // max_bytes_per_account)
         self.num_accounts = num_accounts
         self.program_id = program_id if isinstance(program_id, PublicKey) else PublicKey(program_id)
-        self.meta_key, self.meta_bumper = PublicKey.find_program_address([bytes(auth.public_key), struct.pack('<Q', max_length), struct.pack('<Q', element_size)], self.program_id)
+        self.meta_key, _ = PublicKey.find_program_address([bytes(auth.public_key), struct.pack('<Q', max_length), struct.pack('<Q', element_size), struct.pack('<Q', id)], self.program_id)
         
         self.account_keys = []
-        self.account_bumpers = []
         for i in range(0, num_accounts):
-            key, bumper = PublicKey.find_program_address([bytes(self.meta_key), struct.pack('<B', i)], self.program_id)
+            key, _ = PublicKey.find_program_address([bytes(self.meta_key), struct.pack('<I', i)], self.program_id)
             self.account_keys.append(key)
-            self.account_bumpers.append(bumper)
 
         if not run_transaction:
             return
@@ -40,30 +61,97 @@ class Heap:
             AccountMeta(SYS_PROGRAM_ID, False, False),
             AccountMeta(SYSVAR_RENT_PUBKEY, False, False),
         ]
-        for i in range(0, num_accounts):
-            keys += [AccountMeta(self.account_keys[i], False, True)]
 
         if not isinstance (data, bytes):
             data = b''.join(bytes(item) for item in data)
         if len(data) % element_size:
             raise ValueError("Size of the data given is not a multiple of the element size!")
-        start_length = len(data)//element_size
-        instruction_data = struct.pack('<BQQQ'+'B'*(len(data) + num_accounts + 1), 0, start_length, max_length, element_size,
-                                       *data, self.meta_bumper, *self.account_bumpers)
+        # a lazy heap creates its data accounts as pushes need them, starting with the ones holding data
+        max_bytes_per_account = ((self.account_size - DATA_HEADER_LEN) // element_size) * element_size
+        pages = range(-(-len(data) // max_bytes_per_account)) if lazy else range(num_accounts)
+        for i in pages:
+            keys += [AccountMeta(self.account_keys[i], False, True)]
+
+        instruction_data = (struct.pack('<BQQI', 0, max_length, element_size, len(data)) + data
+                            + struct.pack('<?Q', lazy, id) + pack_pages(pages))
         instruction = TransactionInstruction(keys, program_id, instruction_data)
 
         tx = Transaction().add(instruction)
         self.init_tx_sig = self.solana_client.send_transaction(tx, auth)
+        self.extend_tx_sigs = []
+        if lazy:
+            return
 
-    def push(self, data):
+        # each data account is created with at most MAX_PERMITTED_DATA_INCREASE bytes, extend grows every one of them by that much
+        largest_account = DATA_HEADER_LEN + min(max_length * element_size, max_bytes_per_account)
+        self.extend_tx_sigs = [self.extend() for _ in range(-(-largest_account // MAX_PERMITTED_DATA_INCREASE) - 1)]
+
+    def read_meta(self):
+        # the length of the heap, at byte 33 of its meta
+        value = self.solana_client.get_account_info(self.meta_key, encoding='base64')['result']['value']
+        data = base64.b64decode(value['data'][0])
+        length, = struct.unpack_from('<Q', data, 33)
+        return {'length': length}
+
+    def page_range(self, start, end):
+        # the pages of the elements start..end, element i lives on page i // elements_per_account
+        if end <= start:
+            return range(0)
+        return range(start // self.elements_per_account, (end - 1) // self.elements_per_account + 1)
+
+    def push_pages(self, length):
+        # a push writes the new element at length and sifts it up past its ancestors to the root
+        indices = [length]
+        while indices[-1] != 0:
+            indices.append((indices[-1] - 1) // 2)
+        return sorted({i // self.elements_per_account for i in indices})
 
+    def pop_pages(self, length):
+        # a pop moves the last element to the root and sifts it down, comparing both children of every node it reaches;
+        # which nodes those are depends on the elements, so the data accounts on the way are read
+        if length == 0:
+            return []
+        pages = {}
+        def element(i):
+            page = i // self.elements_per_account
+            if page not in pages:
+                value = self.solana_client.get_account_info(self.account_keys[page], encoding='base64')['result']['value']
+                pages[page] = base64.b64decode(value['data'][0])
+            offset = DATA_HEADER_LEN + (i % self.elements_per_account) * self.element_size
+            # the program orders elements as little-endian unsigned integers, smallest at the root
+            return int.from_bytes(pages[page][offset:offset + self.element_size], 'little')
+
+        length -= 1
+        last = element(length)
+        indices = [0, length]
+        cur = 0
+        while 2 * cur + 1 < length:
+            child = 2 * cur + 1
+            indices += [child, child + 1] if child + 1 < length else [child]
+            if child + 1 < length and element(child) >= element(child + 1):
+                child += 1
+            if last <= element(child):
+                break
+            cur = child
+        return sorted({i // self.elements_per_account for i in indices})
+
+    def push(self, data):
+        pages = self.push_pages(self.read_meta()['length'])
         keys = [
+            AccountMeta(self.auth.public_key, True, False),
             AccountMeta(self.meta_key, False, True),
         ]
-        for i in range(0, self.num_accounts):
+        if self.lazy:
+            # the pusher pays for the data accounts a push to a lazy heap creates
+            keys = [
+                AccountMeta(self.auth.public_key, True, True),
+                AccountMeta(self.meta_key, False, True),
+                AccountMeta(SYS_PROGRAM_ID, False, False),
+            ]
+        for i in pages:
             keys += [AccountMeta(self.account_keys[i], False, True)]
 
-        instruction_data = struct.pack('<B', 1) + data
+        instruction_data = struct.pack('<BI', 1, len(data)) + data + pack_pages(pages)
         instruction = TransactionInstruction(keys, self.program_id, instruction_data)
 
         tx = Transaction().add(instruction)
@@ -71,14 +159,15 @@ class Heap:
         return tx_sig
 
     def pop(self):
-
+        pages = self.pop_pages(self.read_meta()['length'])
         keys = [
+            AccountMeta(self.auth.public_key, True, False),
             AccountMeta(self.meta_key, False, True),
         ]
-        for i in range(0, self.num_accounts):
+        for i in pages:
             keys += [AccountMeta(self.account_keys[i], False, True)]
 
-        instruction_data = struct.pack('<B', 2)
+        instruction_data = struct.pack('<B', 2) + pack_pages(pages)
         instruction = TransactionInstruction(keys, self.program_id, instruction_data)
 
         tx = Transaction().add(instruction)
@@ -86,32 +175,234 @@ class Heap:
         return tx_sig
 
     def peek(self):
+        # the root is the first element
+        pages = [0]
+        keys = [
+            AccountMeta(self.meta_key, False, True),
+        ]
+        for i in pages:
+            keys += [AccountMeta(self.account_keys[i], False, True)]
+
+        instruction_data = struct.pack('<B', 3) + pack_pages(pages)
+        instruction = TransactionInstruction(keys, self.program_id, instruction_data)
 
+        tx = Transaction().add(instruction)
+        tx_sig = self.solana_client.send_transaction(tx, self.auth)
+        return tx_sig
+
+    def decode_elements(self, return_data):
+        # return data of pop/peek: [count u64 LE][count * element_size raw bytes], base64 when taken from the rpc
+        # count is below the number of elements removed when they do not all fit in the 1024 bytes of return data
+        if isinstance(return_data, str):
+            return_data = base64.b64decode(return_data)
+        count, = struct.unpack_from('<Q', return_data)
+        return [return_data[8 + i*self.element_size:8 + (i+1)*self.element_size] for i in range(count)]
+
+    def delete(self, destination=None):
+        destination = self.auth.public_key if destination is None else destination
+        destination = destination if isinstance(destination, PublicKey) else PublicKey(destination)
+        # delete closes every data account
+        pages = range(self.num_accounts)
         keys = [
+            AccountMeta(self.auth.public_key, True, False),
+            AccountMeta(destination, False, True),
             AccountMeta(self.meta_key, False, True),
         ]
-        for i in range(0, self.num_accounts):
+        for i in pages:
             keys += [AccountMeta(self.account_keys[i], False, True)]
 
-        instruction_data = struct.pack('<B', 3)
+        instruction_data = struct.pack('<B', 4) + pack_pages(pages)
         instruction = TransactionInstruction(keys, self.program_id, instruction_data)
 
         tx = Transaction().add(instruction)
         tx_sig = self.solana_client.send_transaction(tx, self.auth)
         return tx_sig
 
-    def delete(self):
+    def set_delegate(self, delegate):
         keys = [
             AccountMeta(self.auth.public_key, True, False),
             AccountMeta(self.meta_key, False, True),
         ]
-        for i in range(0, self.num_accounts):
+
+        delegate = delegate if isinstance(delegate, PublicKey) else PublicKey(delegate)
+        instruction_data = struct.pack('<B', 5) + bytes(delegate)
+        instruction = TransactionInstruction(keys, self.program_id, instruction_data)
+
+        tx = Transaction().add(instruction)
+        tx_sig = self.solana_client.send_transaction(tx, self.auth)
+        return tx_sig
+
+    def extend(self, pages=None):
+        pages = range(self.num_accounts) if pages is None else pages
+        keys = [
+            AccountMeta(self.auth.public_key, True, True),
+            AccountMeta(self.meta_key, False, True),
+            AccountMeta(SYS_PROGRAM_ID, False, False),
+        ]
+        for i in pages:
+            keys += [AccountMeta(self.account_keys[i], False, True)]
+
+        instruction_data = struct.pack('<B', 6) + pack_pages(pages)
+        instruction = TransactionInstruction(keys, self.program_id, instruction_data)
+
+        tx = Transaction().add(instruction)
+        tx_sig = self.solana_client.send_transaction(tx, self.auth)
+        return tx_sig
+
+    def resize(self, max_length):
+        # the meta keeps its address, only data accounts are added
+        max_bytes_per_account = ((self.account_size - DATA_HEADER_LEN) // self.element_size) * self.element_size
+        num_accounts = -(-max_length * self.element_size // max_bytes_per_account)
+        # the old last page and the new ones grow
+        pages = range(max(self.num_accounts - 1, 0), num_accounts)
+        for i in range(self.num_accounts, num_accounts):
+            key, _ = PublicKey.find_program_address([bytes(self.meta_key), struct.pack('<I', i)], self.program_id)
+            self.account_keys.append(key)
+        self.num_accounts = num_accounts
+        self.max_length = max_length
+
+        keys = [
+            AccountMeta(self.auth.public_key, True, True),
+            AccountMeta(self.meta_key, False, True),
+            AccountMeta(SYS_PROGRAM_ID, False, False),
+        ]
+        for i in pages:
             keys += [AccountMeta(self.account_keys[i], False, True)]
 
-        instruction_data = struct.pack('<B', 4)
+        instruction_data = struct.pack('<BQ', 7, max_length) + pack_pages(pages)
         instruction = TransactionInstruction(keys, self.program_id, instruction_data)
 
         tx = Transaction().add(instruction)
         tx_sig = self.solana_client.send_transaction(tx, self.auth)
-        return tx_sig        
-    
\ No newline at end of file
+        self.extend_tx_sigs = []
+        if self.lazy:
+            return tx_sig
+
+        # resize grew every data account like one extend
+        largest_account = DATA_HEADER_LEN + min(max_length * self.element_size, max_bytes_per_account)
+        self.extend_tx_sigs = [self.extend(pages) for _ in range(-(-largest_account // MAX_PERMITTED_DATA_INCREASE) - 1)]
+        return tx_sig
+
+    def shrink(self, max_length):
+        # shrinking to the current length fits the heap to its contents, an empty one keeps room for one element
+        max_length = max(max_length, 1)
+        # the pages from the new last one to the old last one
+        max_bytes_per_account = self.elements_per_account * self.element_size
+        pages = range(-(-max_length * self.element_size // max_bytes_per_account) - 1, self.num_accounts)
+        keys = [
+            AccountMeta(self.auth.public_key, True, True),
+            AccountMeta(self.meta_key, False, True),
+        ]
+        for i in pages:
+            keys += [AccountMeta(self.account_keys[i], False, True)]
+
+        instruction_data = struct.pack('<BQ', 8, max_length) + pack_pages(pages)
+        instruction = TransactionInstruction(keys, self.program_id, instruction_data)
+
+        tx = Transaction().add(instruction)
+        tx_sig = self.solana_client.send_transaction(tx, self.auth)
+
+        max_bytes_per_account = ((self.account_size - DATA_HEADER_LEN) // self.element_size) * self.element_size
+        self.num_accounts = -(-max_length * self.element_size // max_bytes_per_account)
+        self.account_keys = self.account_keys[:self.num_accounts]
+        self.max_length = max_length
+        return tx_sig
+
+    def clear(self):
+        keys = [
+            AccountMeta(self.auth.public_key, True, False),
+            AccountMeta(self.meta_key, False, True),
+        ]
+
+        instruction_data = struct.pack('<B', 9)
+        instruction = TransactionInstruction(keys, self.program_id, instruction_data)
+
+        tx = Transaction().add(instruction)
+        tx_sig = self.solana_client.send_transaction(tx, self.auth)
+        return tx_sig
+
+    def migrate(self):
+        # rewrites the meta in the current layout, the data accounts keep theirs
+        keys = [
+            AccountMeta(self.auth.public_key, True, True),
+            AccountMeta(self.meta_key, False, True),
+            AccountMeta(SYS_PROGRAM_ID, False, False),
+        ]
+
+        instruction_data = struct.pack('<B', 10)
+        instruction = TransactionInstruction(keys, self.program_id, instruction_data)
+
+        tx = Transaction().add(instruction)
+        tx_sig = self.solana_client.send_transaction(tx, self.auth)
+        return tx_sig
+
+    def set_authority(self, new_authority):
+        keys = [
+            AccountMeta(self.auth.public_key, True, False),
+            AccountMeta(self.meta_key, False, True),
+        ]
+
+        new_authority = new_authority if isinstance(new_authority, PublicKey) else PublicKey(new_authority)
+        instruction_data = struct.pack('<B', 11) + bytes(new_authority)
+        instruction = TransactionInstruction(keys, self.program_id, instruction_data)
+
+        tx = Transaction().add(instruction)
+        tx_sig = self.solana_client.send_transaction(tx, self.auth)
+        return tx_sig
+
+    def accept_authority(self, new_auth):
+        # new_auth is the keypair proposed by set_authority, it signs everything from here on
+        keys = [
+            AccountMeta(new_auth.public_key, True, False),
+            AccountMeta(self.meta_key, False, True),
+        ]
+
+        instruction_data = struct.pack('<B', 12)
+        instruction = TransactionInstruction(keys, self.program_id, instruction_data)
+
+        tx = Transaction().add(instruction)
+        tx_sig = self.solana_client.send_transaction(tx, new_auth)
+        self.auth = new_auth
+        return tx_sig
+
+    def grant_role(self, role, member):
+        keys = [
+            AccountMeta(self.auth.public_key, True, False),
+            AccountMeta(self.meta_key, False, True),
+        ]
+
+        member = member if isinstance(member, PublicKey) else PublicKey(member)
+        instruction_data = struct.pack('<BB', 13, role) + bytes(member)
+        instruction = TransactionInstruction(keys, self.program_id, instruction_data)
+
+        tx = Transaction().add(instruction)
+        tx_sig = self.solana_client.send_transaction(tx, self.auth)
+        return tx_sig
+
+    def revoke_role(self, role, member):
+        keys = [
+            AccountMeta(self.auth.public_key, True, False),
+            AccountMeta(self.meta_key, False, True),
+        ]
+
+        member = member if isinstance(member, PublicKey) else PublicKey(member)
+        instruction_data = struct.pack('<BB', 14, role) + bytes(member)
+        instruction = TransactionInstruction(keys, self.program_id, instruction_data)
+
+        tx = Transaction().add(instruction)
+        tx_sig = self.solana_client.send_transaction(tx, self.auth)
+        return tx_sig
+
+    def set_cpi_only(self, cpi_only):
+        # once set, the heap only accepts changes from other programs, including clearing the flag
+        keys = [
+            AccountMeta(self.auth.public_key, True, False),
+            AccountMeta(self.meta_key, False, True),
+        ]
+
+        instruction_data = struct.pack('<B?', 15, cpi_only)
+        instruction = TransactionInstruction(keys, self.program_id, instruction_data)
+
+        tx = Transaction().add(instruction)
+        tx_sig = self.solana_client.send_transaction(tx, self.auth)
+        return tx_sig
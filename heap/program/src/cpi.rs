@@ -0,0 +1,120 @@
+//! Helpers for calling the heap program from other on-chain programs.
+//!
+//! Every function builds the instruction with the builders in `instruction`, invokes the program
+//! with the context's signer seeds and decodes the return data of reads.
+
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+use borsh::BorshDeserialize;
+
+use solana_data_structures_core::cpi::{self, invoke, invoke_read};
+pub use solana_data_structures_core::cpi::CpiContext;
+
+use crate::{instruction::{build_delete, build_peek, build_pop, build_push, build_push_lazy, build_set_delegate, build_extend, build_resize, build_shrink, build_clear, build_migrate, build_set_authority, build_accept_authority, build_grant_role, build_revoke_role, build_set_cpi_only}, state::{HeapMeta, Role}};
+
+/// Pushes the element in `data`. `ctx.data_accounts` lists the pages `push_pages` names.
+pub fn push(ctx: &CpiContext, data: &[u8]) -> ProgramResult {
+    let instruction = build_push(ctx.program.key, ctx.authority.key, ctx.meta.key, &ctx.data_account_keys(), data);
+    invoke(ctx, &instruction, &[])
+}
+
+/// `push` for a lazy heap, creating data accounts as needed with the authority paying.
+pub fn push_lazy<'info>(ctx: &CpiContext<'_, 'info>, system_program: AccountInfo<'info>, data: &[u8]) -> ProgramResult {
+    let instruction = build_push_lazy(ctx.program.key, ctx.authority.key, ctx.meta.key, &ctx.data_account_keys(), data);
+    invoke(ctx, &instruction, &[system_program])
+}
+
+/// Pops the root element. `ctx.data_accounts` lists the pages `pop_pages` computes from a read of
+/// the data accounts. Fails with `InvalidAccountData` if it is too large for the return data.
+pub fn pop(ctx: &CpiContext) -> Result<Vec<u8>, ProgramError> {
+    let instruction = build_pop(ctx.program.key, ctx.authority.key, ctx.meta.key, &ctx.data_account_keys());
+    invoke(ctx, &instruction, &[])?;
+    read_elements(ctx)?.pop().ok_or(ProgramError::InvalidAccountData)
+}
+
+/// Reads the root element. The authority does not need to sign.
+pub fn peek(ctx: &CpiContext) -> Result<Vec<u8>, ProgramError> {
+    let instruction = build_peek(ctx.program.key, ctx.meta.key, &ctx.data_account_keys());
+    invoke_read(ctx, &instruction)?;
+    read_elements(ctx)?.pop().ok_or(ProgramError::InvalidAccountData)
+}
+
+/// Closes the heap and moves its lamports to `destination`.
+pub fn delete<'info>(ctx: &CpiContext<'_, 'info>, destination: AccountInfo<'info>) -> ProgramResult {
+    let instruction = build_delete(ctx.program.key, ctx.authority.key, destination.key, ctx.meta.key, &ctx.data_account_keys());
+    invoke(ctx, &instruction, &[destination])
+}
+
+pub fn set_delegate(ctx: &CpiContext, delegate: &Pubkey) -> ProgramResult {
+    let instruction = build_set_delegate(ctx.program.key, ctx.authority.key, ctx.meta.key, delegate);
+    invoke(ctx, &instruction, &[])
+}
+
+/// Grows the data accounts by up to `MAX_PERMITTED_DATA_INCREASE` bytes each, the authority
+/// paying the rent.
+pub fn extend<'info>(ctx: &CpiContext<'_, 'info>, system_program: AccountInfo<'info>) -> ProgramResult {
+    let instruction = build_extend(ctx.program.key, ctx.authority.key, ctx.meta.key, &ctx.data_account_keys());
+    invoke(ctx, &instruction, &[system_program])
+}
+
+/// Grows the heap to `max_length` elements. `ctx.data_accounts` lists data accounts of the grown
+/// heap to create or grow.
+pub fn resize<'info>(ctx: &CpiContext<'_, 'info>, system_program: AccountInfo<'info>, max_length: u64) -> ProgramResult {
+    let instruction = build_resize(ctx.program.key, ctx.authority.key, ctx.meta.key, &ctx.data_account_keys(), max_length);
+    invoke(ctx, &instruction, &[system_program])
+}
+
+/// Shrinks the heap to `max_length` elements, refunding the freed rent to the authority.
+/// `ctx.data_accounts` must list the data accounts from the last one kept on.
+pub fn shrink(ctx: &CpiContext, max_length: u64) -> ProgramResult {
+    let instruction = build_shrink(ctx.program.key, ctx.authority.key, ctx.meta.key, &ctx.data_account_keys(), max_length);
+    invoke(ctx, &instruction, &[])
+}
+
+pub fn clear(ctx: &CpiContext) -> ProgramResult {
+    let instruction = build_clear(ctx.program.key, ctx.authority.key, ctx.meta.key);
+    invoke(ctx, &instruction, &[])
+}
+
+/// Rewrites the meta in the current layout, the authority paying for the larger meta account.
+pub fn migrate<'info>(ctx: &CpiContext<'_, 'info>, system_program: AccountInfo<'info>) -> ProgramResult {
+    let instruction = build_migrate(ctx.program.key, ctx.authority.key, ctx.meta.key);
+    invoke(ctx, &instruction, &[system_program])
+}
+
+pub fn set_authority(ctx: &CpiContext, new_authority: &Pubkey) -> ProgramResult {
+    let instruction = build_set_authority(ctx.program.key, ctx.authority.key, ctx.meta.key, new_authority);
+    invoke(ctx, &instruction, &[])
+}
+
+/// Accepts the authority proposed by `set_authority`. `ctx.authority` is the proposed authority,
+/// e.g. a PDA of the calling program.
+pub fn accept_authority(ctx: &CpiContext) -> ProgramResult {
+    let instruction = build_accept_authority(ctx.program.key, ctx.authority.key, ctx.meta.key);
+    invoke(ctx, &instruction, &[])
+}
+
+pub fn grant_role(ctx: &CpiContext, role: Role, member: &Pubkey) -> ProgramResult {
+    let instruction = build_grant_role(ctx.program.key, ctx.authority.key, ctx.meta.key, role, member);
+    invoke(ctx, &instruction, &[])
+}
+
+pub fn revoke_role(ctx: &CpiContext, role: Role, member: &Pubkey) -> ProgramResult {
+    let instruction = build_revoke_role(ctx.program.key, ctx.authority.key, ctx.meta.key, role, member);
+    invoke(ctx, &instruction, &[])
+}
+
+pub fn set_cpi_only(ctx: &CpiContext, cpi_only: bool) -> ProgramResult {
+    let instruction = build_set_cpi_only(ctx.program.key, ctx.authority.key, ctx.meta.key, cpi_only);
+    invoke(ctx, &instruction, &[])
+}
+
+/// Decodes the elements the heap program just returned.
+fn read_elements(ctx: &CpiContext) -> Result<Vec<Vec<u8>>, ProgramError> {
+    let heap_meta = HeapMeta::try_from_slice(&ctx.meta.data.borrow())?;
+    cpi::read_elements(ctx, heap_meta.element_size)
+}
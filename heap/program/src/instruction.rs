@@ -1,52 +1,234 @@
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
-    entrypoint::ProgramResult,
+    entrypoint::{ProgramResult, MAX_PERMITTED_DATA_INCREASE},
+    instruction::{AccountMeta, Instruction as SolInstruction},
     msg,
-    program::{invoke, invoke_signed},
+    program::invoke,
     program_error::ProgramError,
     pubkey::Pubkey,
-    sysvar::{rent::Rent, Sysvar}
+    system_program,
+    sysvar::{self, rent::Rent, Sysvar}
 };
 use borsh::{BorshSerialize, BorshDeserialize};
-use std::{cmp::min};
+use std::{cmp::{max, min}};
 
-use crate::{error::HeapError, state::{MAX_ACCOUNT_SIZE, HEAP_META_LEN, HeapMeta}};
+use solana_data_structures_core::{
+    access::{check_authority, check_cpi_only, check_cpi_only_accounts, check_role, check_role_manager, Roles},
+    account::{close_account, create_pda_account, grow_account},
+    PagedStorage,
+};
+pub use solana_data_structures_core::cpi::with_instructions_sysvar;
+
+use crate::{error::HeapError, state::{DATA_HEADER, DATA_HEADER_LEN, LAYOUT_VERSION, LAZY_ACCOUNT_SIZE, MAX_ACCOUNT_SIZE, HEAP_META_DISCRIMINATOR, HEAP_META_LEN, HeapMeta, DataHeader, Role}};
 
-#[derive(BorshDeserialize, PartialEq, Debug)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
 pub struct InitializeParams{
-    pub start_length: u64,
     pub max_length: u64,
     pub element_size: u64,
+    pub data: Vec<u8>,
+    pub lazy: bool,
+    pub id: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+pub struct PushParams{
+    pub data: Vec<u8>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+pub struct ResizeParams{
+    pub max_length: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+pub struct SetDelegateParams{
+    pub delegate: Pubkey,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+pub struct SetAuthorityParams{
+    pub new_authority: Pubkey,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+pub struct RoleParams{
+    pub role: Role,
+    pub member: Pubkey,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+pub struct SetCpiOnlyParams{
+    pub cpi_only: bool,
 }
 
+/// Instructions of the heap program, serialized with Borsh: the variant index is the leading tag
+/// byte and the parameters follow it.
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
 pub enum Instruction {
-    Initialize,
-    Push,
+    Initialize(InitializeParams),
+    Push(PushParams),
     Pop,
     Peek,
     Delete,
+    SetDelegate(SetDelegateParams),
+    Extend,
+    Resize(ResizeParams),
+    Shrink(ResizeParams),
+    Clear,
+    Migrate,
+    SetAuthority(SetAuthorityParams),
+    AcceptAuthority,
+    GrantRole(RoleParams),
+    RevokeRole(RoleParams),
+    SetCpiOnly(SetCpiOnlyParams),
 }
 
 impl Instruction {
-    /// Unpacks a byte buffer into a [EscrowInstruction](enum.EscrowInstruction.html).
-    pub fn unpack(tag: &u8) -> Result<Self, ProgramError> {
+    /// Reads the instruction and the page indices of the data accounts passed with it, a Borsh
+    /// `Vec<u32>` after the instruction. Instructions without data accounts may leave it out.
+    pub fn unpack(input: &[u8]) -> Result<(Self, Vec<u32>), ProgramError> {
+        let mut input = input;
+        let instruction = Self::deserialize(&mut input).map_err(|_| ProgramError::InvalidInstructionData)?;
+        if input.is_empty(){
+            return Ok((instruction, Vec::new()));
+        }
+        let pages = Vec::<u32>::try_from_slice(input).map_err(|_| ProgramError::InvalidInstructionData)?;
+        Ok((instruction, pages))
+    }
+
+    pub fn pack(&self) -> Vec<u8> {
+        self.try_to_vec().unwrap()
+    }
+
+    /// Packs the instruction followed by the page indices of the data accounts passed with it.
+    pub fn pack_with_pages(&self, pages: &[u32]) -> Vec<u8> {
+        let mut data = self.pack();
+        data.extend(pages.to_vec().try_to_vec().unwrap());
+        data
+    }
+}
+
+/// Checks that the meta account is owned by this program and has the meta layout.
+fn check_meta_layout(
+    heap_meta_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    if heap_meta_account.owner != program_id || heap_meta_account.data_len() != HEAP_META_LEN as usize{
+        msg!("Invalid heap meta account");
+        return Err(HeapError::InvalidMetaAccount.into());
+    }
+    Ok(())
+}
+
+/// Checks that the meta account is a heap meta of the current layout that `Initialize` has set up.
+fn check_meta_account(
+    heap_meta_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    check_meta_layout(heap_meta_account, program_id)?;
+    let heap_meta = HeapMeta::try_from_slice(&heap_meta_account.data.borrow())?;
+    if heap_meta.discriminator != HEAP_META_DISCRIMINATOR || heap_meta.version != LAYOUT_VERSION{
+        msg!("Not a heap meta account of layout version {}", LAYOUT_VERSION);
+        return Err(HeapError::InvalidMetaAccount.into());
+    }
+    if !heap_meta.is_initialized{
+        msg!("Heap meta account is not initialized");
+        return Err(HeapError::InvalidMetaAccount.into());
+    }
+    Ok(())
+}
+
+/// Checks that the data accounts passed are the heap's data accounts at the page indices `pages`,
+/// in that order. The indices increase and each account is the PDA `[meta key, index as u32 LE]`,
+/// either owned by this program and starting with the data header or not created yet. Legacy data
+/// accounts have no header and the PDA `[meta key, index as u8]`.
+fn check_heap_accounts(
+    heap_meta_account: &AccountInfo,
+    heap_meta: &HeapMeta,
+    pages: &[u32],
+    heap_accounts: &[&AccountInfo],
+    program_id: &Pubkey,
+) -> ProgramResult {
+    if heap_accounts.len() < pages.len(){
+        msg!("Not enough accounts");
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    if heap_accounts.len() > pages.len(){
+        msg!("Too many heap accounts");
+        return Err(HeapError::InvalidDataAccount.into());
+    }
+
+    let num_accounts = heap_meta.num_accounts();
+    for (pages_index, (&page, heap_account)) in pages.iter().zip(heap_accounts.iter()).enumerate(){
+        if page as u64 >= num_accounts || (pages_index > 0 && page <= pages[pages_index - 1]){
+            msg!("Invalid page index {}", page);
+            return Err(HeapError::InvalidDataAccount.into());
+        }
+        let (heap_account_key, _) = Pubkey::find_program_address(
+            &[heap_meta_account.key.as_ref(), &heap_meta.data_account_seed(page)],
+            program_id,
+        );
+        // data accounts are created by Initialize, Extend or a push to a lazy heap
+        let uncreated = *heap_account.owner == system_program::id() && heap_account.data_len() == 0;
+        let created = heap_account.owner == program_id && (heap_meta.legacy || has_data_header(heap_account));
+        if (!created && !uncreated) || *heap_account.key != heap_account_key{
+            msg!("Invalid heap account {}", page);
+            return Err(HeapError::InvalidDataAccount.into());
+        }
+    }
+    Ok(())
+}
+
+/// The data account passed for page `page`, see `check_heap_accounts`.
+fn page_account<'b, 'a>(
+    pages: &[u32],
+    heap_accounts: &[&'b AccountInfo<'a>],
+    page: u64,
+) -> Result<&'b AccountInfo<'a>, ProgramError> {
+    let pages_index = pages.iter().position(|&passed| passed as u64 == page).ok_or_else(|| {
+        msg!("Missing heap account {}", page);
+        ProgramError::NotEnoughAccountKeys
+    })?;
+    Ok(heap_accounts[pages_index])
+}
+
+/// Rejects heaps that need more data accounts than their seeds can tell apart.
+fn check_num_accounts(
+    heap_meta: &HeapMeta,
+) -> ProgramResult {
+    if heap_meta.num_accounts() > heap_meta.max_num_accounts(){
+        msg!("The heap needs {} data accounts, at most {} can be addressed", heap_meta.num_accounts(), heap_meta.max_num_accounts());
+        return Err(HeapError::InvalidParameters.into());
+    }
+    Ok(())
+}
+
+/// Whether `heap_account` starts with the data header of the current layout.
+fn has_data_header(
+    heap_account: &AccountInfo,
+) -> bool {
+    let data = heap_account.data.borrow();
+    data.len() >= DATA_HEADER_LEN as usize
+        && DataHeader::try_from_slice(&data[..DATA_HEADER_LEN as usize]).map_or(false, |header| header == DATA_HEADER)
+}
 
-        Ok(match tag {
-            0 => Self::Initialize,
-            1 => Self::Push,
-            2 => Self::Pop,
-            3 => Self::Peek,
-            4 => Self::Delete,
-            _ => return Err(ProgramError::InvalidInstructionData),
-        })
-    }   
+/// Rejects heaps whose data accounts `Extend` has not grown to their full size yet. Lazy heaps
+/// create and size their data accounts as pushes need them.
+fn check_provisioned(
+    heap_meta: &HeapMeta,
+) -> ProgramResult {
+    if !heap_meta.lazy && heap_meta.provisioned_bytes < heap_meta.max_bytes{
+        msg!("Heap is not fully provisioned, send Extend first");
+        return Err(HeapError::NotProvisioned.into());
+    }
+    Ok(())
 }
 
 fn push_down(
     data: &mut Vec<Vec<u8>>,
     i: usize,
     n: usize,
-    compare: impl Fn(&Vec<u8>, &Vec<u8>) -> Result<i64, ProgramError>
+    compare: impl Fn(&[u8], &[u8]) -> Result<i64, ProgramError>
 ) -> ProgramResult {
 
     let mut smallest = i; // Initialize largest as root
@@ -78,7 +260,7 @@ fn push_down(
 
 fn heapify(
     data: &mut Vec<Vec<u8>>,
-    compare: impl Fn(&Vec<u8>, &Vec<u8>) -> Result<i64, ProgramError>
+    compare: impl Fn(&[u8], &[u8]) -> Result<i64, ProgramError>
 ) -> ProgramResult {
     let n = data.len();
     for i in (0..n/2 + 1).rev(){
@@ -88,12 +270,92 @@ fn heapify(
     Ok(())
 }
 
+/// Grows the data account at page `page` towards `target` bytes by at most
+/// `MAX_PERMITTED_DATA_INCREASE`, creating it first if it does not exist yet, with `payer` paying
+/// the rent. `target` includes the data header. Returns the number of element bytes added.
+#[allow(clippy::too_many_arguments)]
+fn provision_heap_account<'a>(
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    heap_meta_account: &AccountInfo<'a>,
+    heap_meta: &HeapMeta,
+    heap_account: &AccountInfo<'a>,
+    page: u32,
+    target: u64,
+    program_id: &Pubkey,
+) -> Result<u64, ProgramError> {
+    let current = heap_account.data_len() as u64;
+    if current >= target{
+        return Ok(0);
+    }
+    let space = min(target, current + MAX_PERMITTED_DATA_INCREASE as u64);
+    let rent = Rent::get()?;
+
+    if heap_account.owner != program_id{
+        let (heap_account_key, bump) = Pubkey::find_program_address(
+            &[heap_meta_account.key.as_ref(), &heap_meta.data_account_seed(page)],
+            program_id,
+        );
+        if *heap_account.key != heap_account_key{
+            msg!("Invalid heap account {}", page);
+            return Err(HeapError::InvalidDataAccount.into());
+        }
+        let heap_account_seeds: &[&[u8]] = &[heap_meta_account.key.as_ref(), &heap_meta.data_account_seed(page), &[bump]];
+        create_pda_account(payer, system_program, heap_account, space, heap_account_seeds, &rent, program_id)?;
+        if !heap_meta.legacy{
+            DATA_HEADER.serialize(&mut *heap_account.data.borrow_mut())?;
+        }
+        msg!("Created heap account {}", page);
+        return Ok(space - heap_meta.data_header_len());
+    } else {
+        grow_account(payer, system_program, heap_account, space, &rent)?;
+    }
+
+    Ok(space - current)
+}
+
+/// Creates the data accounts of a lazy heap that hold the elements at `indices` and do not exist
+/// yet, with `payer` paying the rent.
+#[allow(clippy::too_many_arguments)]
+fn provision_elements<'a>(
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    heap_meta_account: &AccountInfo<'a>,
+    heap_meta: &mut HeapMeta,
+    pages: &[u32],
+    heap_accounts: &[&AccountInfo<'a>],
+    indices: impl Iterator<Item = u64>,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    for index in indices{
+        let page = index / heap_meta.max_elements_per_account;
+        let heap_account = page_account(pages, heap_accounts, page)?;
+        let target = heap_meta.data_header_len() + heap_meta.account_bytes(page);
+        heap_meta.provisioned_bytes += provision_heap_account(payer, system_program, heap_meta_account, heap_meta, heap_account, page as u32, target, program_id)?;
+    }
+    Ok(())
+}
+
+/// Rejects parameters the paging arithmetic cannot handle: zero sizes, elements larger than a
+/// data account and capacities whose byte size overflows.
+fn check_parameters(
+    max_length: u64,
+    element_size: u64,
+) -> ProgramResult {
+    if max_length == 0 || element_size == 0 || element_size > MAX_ACCOUNT_SIZE - DATA_HEADER_LEN{
+        msg!("Invalid max length or element size");
+        return Err(HeapError::InvalidParameters.into());
+    }
+    max_length.checked_mul(element_size).ok_or(HeapError::Overflow)?;
+    Ok(())
+}
+
 pub fn initialize_heap(
     accounts: &[AccountInfo],
     max_length: u64,
     element_size: u64,
     data: &[u8],
-    compare: impl Fn(&Vec<u8>, &Vec<u8>) -> Result<i64, ProgramError>,
+    compare: impl Fn(&[u8], &[u8]) -> Result<i64, ProgramError>,
     program_id: &Pubkey,
 ) -> ProgramResult {
 
@@ -108,10 +370,22 @@ pub fn initialize_heap(
         heap_accounts.push(next_account_info(account_info_iter)?);
     }
 
+    if !auth.is_signer{
+        msg!("Missing authority signature");
+        return Err(HeapError::Unauthorized.into());
+    }
+
+    check_parameters(max_length, element_size)?;
+
     if data.len() % element_size as usize != 0{
         msg!("Data length not multiple of element size");
         return Err(ProgramError::InvalidArgument);
     }
+
+    if data.len() as u64 > max_length * element_size{
+        msg!("Not enough space");
+        return Err(HeapError::InsufficientSpace.into());
+    }
     
     // create heap meta account if it doesn't exist
     if heap_meta_account.data_len() == 0{
@@ -136,14 +410,29 @@ pub fn initialize_heap(
 
     let mut heap_meta = HeapMeta::try_from_slice(&heap_meta_account.data.borrow())?;
 
+    // the meta of a live heap is never overwritten
+    if heap_meta.is_initialized{
+        msg!("Heap already initialized");
+        return Err(HeapError::AlreadyInitialized.into());
+    }
+
+    heap_meta.discriminator = HEAP_META_DISCRIMINATOR;
+    heap_meta.version = LAYOUT_VERSION;
     heap_meta.max_length = max_length;
     heap_meta.element_size = element_size;
     heap_meta.max_bytes = max_length * element_size;
     heap_meta.length = data.len() as u64 / element_size;
-    heap_meta.max_elements_per_account = MAX_ACCOUNT_SIZE / element_size;
+    heap_meta.max_elements_per_account = (MAX_ACCOUNT_SIZE - DATA_HEADER_LEN) / element_size;
     heap_meta.max_bytes_per_account = heap_meta.max_elements_per_account * element_size;
-
-    heap_meta.serialize(&mut *heap_meta_account.data.borrow_mut())?;
+    heap_meta.authority = *auth.key;
+    heap_meta.delegate = Pubkey::default();
+    heap_meta.pending_authority = Pubkey::default();
+    heap_meta.roles = Roles::default();
+    heap_meta.cpi_only = false;
+    heap_meta.provisioned_bytes = 0;
+    heap_meta.lazy = false;
+    heap_meta.id = 0;
+    heap_meta.is_initialized = true;
 
     let mut size_to_allocate = max_length * element_size;
     let mut heap_accounts_index = 0;
@@ -153,8 +442,9 @@ pub fn initialize_heap(
             return Err(ProgramError::NotEnoughAccountKeys);
         }
 
+        let target = min(size_to_allocate, heap_meta.max_bytes_per_account);
         if heap_accounts[heap_accounts_index].data_len() == 0{
-            let space = min(size_to_allocate, heap_meta.max_bytes_per_account);
+            let space = min(DATA_HEADER_LEN + target, MAX_PERMITTED_DATA_INCREASE as u64);
             let required_lamports = rent.minimum_balance(space as usize);
             invoke(
                 &solana_program::system_instruction::create_account(
@@ -170,49 +460,47 @@ pub fn initialize_heap(
                     system_program.clone(),
                 ]
             )?;
-
-            size_to_allocate -= space;
-            heap_accounts_index += 1;
         }
+
+        DATA_HEADER.serialize(&mut *heap_accounts[heap_accounts_index].data.borrow_mut())?;
+        heap_meta.provisioned_bytes += (heap_accounts[heap_accounts_index].data_len() as u64).saturating_sub(DATA_HEADER_LEN);
+        size_to_allocate -= target;
+        heap_accounts_index += 1;
+    }
+
+    heap_meta.serialize(&mut *heap_meta_account.data.borrow_mut())?;
+
+    if !data.is_empty(){
+        check_provisioned(&heap_meta)?;
     }
 
     let start_length = data.len() / element_size as usize;
     let mut data_vec = Vec::with_capacity(start_length);
-    for i in 0..start_length as usize{
-        data_vec.push(Vec::with_capacity(element_size as usize));
-        for j in 0..element_size as usize{
-            data_vec[i].push(data[i * element_size as usize + j]);
-        }
+    for element in data.chunks(element_size as usize){
+        data_vec.push(element.to_vec());
     }
 
     heapify(&mut data_vec, &compare)?;
 
-    let mut heap_accounts_index = 0;
-    let mut heap_data = heap_accounts[heap_accounts_index].data.borrow_mut();
-    let mut heap_data_index = 0;
-
-    for i in 0..data.len(){
-        heap_data[heap_data_index] = data_vec[i / element_size as usize][i % element_size as usize];
-        heap_data_index += 1;
-        if heap_data_index as u64 >= heap_meta.max_bytes_per_account{
-            heap_accounts_index += 1;
-            heap_data = heap_accounts[heap_accounts_index].data.borrow_mut();
-            heap_data_index = 0;
-        }
-    }
+    // the heap accounts are passed in page order, starting at the first
+    let pages: Vec<u32> = (0..heap_accounts.len() as u32).collect();
+    let mut heap_storage = PagedStorage::new(&pages, &heap_accounts, element_size, heap_meta.max_elements_per_account, heap_meta.data_header_len())?;
+    heap_storage.write_range(0, &data_vec.concat())?;
 
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn initialize_heap_signed(
     accounts: &[AccountInfo],
     max_length: u64,
     element_size: u64,
     data: &[u8],
-    compare: impl Fn(&Vec<u8>, &Vec<u8>) -> Result<i64, ProgramError>,
+    lazy: bool,
+    id: u64,
+    compare: impl Fn(&[u8], &[u8]) -> Result<i64, ProgramError>,
+    pages: &[u32],
     program_id: &Pubkey,
-    meta_seeds: &[&[u8]],
-    heap_bump_seeds: &[u8],
 ) -> ProgramResult {
 
     let account_info_iter = &mut accounts.iter().peekable();
@@ -226,117 +514,127 @@ pub fn initialize_heap_signed(
         heap_accounts.push(next_account_info(account_info_iter)?);
     }
 
+    if !auth.is_signer{
+        msg!("Missing authority signature");
+        return Err(HeapError::Unauthorized.into());
+    }
+
+    check_parameters(max_length, element_size)?;
+    if lazy && element_size > LAZY_ACCOUNT_SIZE - DATA_HEADER_LEN{
+        msg!("Element size larger than a lazy heap data account");
+        return Err(HeapError::InvalidParameters.into());
+    }
+    let account_size = if lazy { LAZY_ACCOUNT_SIZE } else { MAX_ACCOUNT_SIZE };
+
     if data.len() % element_size as usize != 0{
         msg!("Data length not multiple of element size");
         return Err(ProgramError::InvalidArgument);
     }
+
+    if data.len() as u64 > max_length * element_size{
+        msg!("Not enough space");
+        return Err(HeapError::InsufficientSpace.into());
+    }
     
+    // the meta and the data accounts only take the canonical bump, so every set of parameters has
+    // exactly one meta and each index one data account
+    let (heap_meta_key, meta_bump) = find_meta_address(program_id, auth.key, max_length, element_size, id);
+    if *heap_meta_account.key != heap_meta_key{
+        msg!("Heap meta account does not match the authority and parameters");
+        return Err(HeapError::InvalidMetaAccount.into());
+    }
+    let meta_seeds: &[&[u8]] = &[auth.key.as_ref(), &max_length.to_le_bytes(), &element_size.to_le_bytes(), &id.to_le_bytes(), &[meta_bump]];
+
     // create heap meta account if it doesn't exist
     if heap_meta_account.data_len() == 0{
-
         let space = HEAP_META_LEN;
-        let required_lamports = rent.minimum_balance(space as usize);
-        invoke_signed(
-            &solana_program::system_instruction::create_account(
-                auth.key,
-                heap_meta_account.key,
-                required_lamports,
-                space,
-                program_id,
-            ),
-            &[
-                auth.clone(),
-                heap_meta_account.clone(),
-                system_program.clone(),
-            ],
-            &[meta_seeds]
-        )?;
+        create_pda_account(auth, system_program, heap_meta_account, space, meta_seeds, rent, program_id)?;
     }
 
+    check_meta_layout(heap_meta_account, program_id)?;
+
     msg!("Created heap meta account");
 
     let mut heap_meta = HeapMeta::try_from_slice(&heap_meta_account.data.borrow())?;
 
+    // the meta of a live heap is never overwritten
+    if heap_meta.is_initialized{
+        msg!("Heap already initialized");
+        return Err(HeapError::AlreadyInitialized.into());
+    }
+
+    heap_meta.discriminator = HEAP_META_DISCRIMINATOR;
+    heap_meta.version = LAYOUT_VERSION;
     heap_meta.max_length = max_length;
     heap_meta.element_size = element_size;
     heap_meta.max_bytes = max_length * element_size;
     heap_meta.length = data.len() as u64 / element_size;
-    heap_meta.max_elements_per_account = MAX_ACCOUNT_SIZE / element_size;
+    heap_meta.max_elements_per_account = (account_size - DATA_HEADER_LEN) / element_size;
     heap_meta.max_bytes_per_account = heap_meta.max_elements_per_account * element_size;
+    heap_meta.authority = *auth.key;
+    heap_meta.delegate = Pubkey::default();
+    heap_meta.pending_authority = Pubkey::default();
+    heap_meta.roles = Roles::default();
+    heap_meta.cpi_only = false;
+    heap_meta.provisioned_bytes = 0;
+    heap_meta.lazy = lazy;
+    heap_meta.id = id;
+    heap_meta.is_initialized = true;
+
+
+    check_num_accounts(&heap_meta)?;
+    check_heap_accounts(heap_meta_account, &heap_meta, pages, &heap_accounts, program_id)?;
+
+    // the data accounts passed are created here, Extend creates and grows the rest and a lazy
+    // heap creates them when a push first needs them
+    for (&page, &heap_account) in pages.iter().zip(heap_accounts.iter()){
+        let (_, bump) = Pubkey::find_program_address(&[heap_meta_account.key.as_ref(), &page.to_le_bytes()], program_id);
+        let heap_account_seeds: &[&[u8]] = &[heap_meta_account.key.as_ref(), &page.to_le_bytes(), &[bump]];
+
+        // a program may only allocate MAX_PERMITTED_DATA_INCREASE bytes per account in one
+        // instruction, the rest is grown by Extend
+        let space = min(DATA_HEADER_LEN + heap_meta.account_bytes(page as u64), MAX_PERMITTED_DATA_INCREASE as u64);
+        create_pda_account(auth, system_program, heap_account, space, heap_account_seeds, rent, program_id)?;
+        DATA_HEADER.serialize(&mut *heap_account.data.borrow_mut())?;
+        heap_meta.provisioned_bytes += space - DATA_HEADER_LEN;
+
+        msg!("Created heap account {}", page);
+    }
 
-    heap_meta.serialize(&mut *heap_meta_account.data.borrow_mut())?;
-
-
-    let mut size_to_allocate = max_length * element_size;
-    let mut heap_accounts_index = 0;
-    while size_to_allocate > 0 {
-        if heap_accounts_index == heap_accounts.len(){
-            msg!("Not enough accounts");
-            return Err(ProgramError::NotEnoughAccountKeys);
-        }
-
-
-        let space = min(size_to_allocate, heap_meta.max_bytes_per_account);
-        let required_lamports = rent.minimum_balance(space as usize);
-        invoke_signed(
-            &solana_program::system_instruction::create_account(
-                auth.key,
-                heap_accounts[heap_accounts_index].key,
-                required_lamports,
-                space,
-                program_id,
-            ),
-            &[
-                auth.clone(),
-                heap_accounts[heap_accounts_index].clone(),
-                system_program.clone(),
-            ],
-            &[&[heap_meta_account.key.as_ref(), &[heap_accounts_index as u8], 
-              &[heap_bump_seeds[heap_accounts_index]]]],
-        )?;
-
-        size_to_allocate -= space;
+    if lazy{
+        let length = heap_meta.length;
+        provision_elements(auth, system_program, heap_meta_account, &mut heap_meta, pages, &heap_accounts, 0..length, program_id)?;
+    }
 
-        msg!("Created heap account {}", heap_accounts_index);
+    heap_meta.serialize(&mut *heap_meta_account.data.borrow_mut())?;
 
-        heap_accounts_index += 1;
+    if !data.is_empty(){
+        check_provisioned(&heap_meta)?;
     }
 
     let start_length = data.len() / element_size as usize;
     let mut data_vec = Vec::with_capacity(start_length);
-    for i in 0..start_length as usize{
-        data_vec.push(Vec::with_capacity(element_size as usize));
-        for j in 0..element_size as usize{
-            data_vec[i].push(data[i * element_size as usize + j]);
-        }
+    for element in data.chunks(element_size as usize){
+        data_vec.push(element.to_vec());
     }
 
     heapify(&mut data_vec, &compare)?;
 
-    let mut heap_accounts_index = 0;
-    let mut heap_data = heap_accounts[heap_accounts_index].data.borrow_mut();
-    let mut heap_data_index = 0;
-
-    for i in 0..data.len(){
-        heap_data[heap_data_index] = data_vec[i / element_size as usize][i % element_size as usize];
-        heap_data_index += 1;
-        if heap_data_index as u64 >= heap_meta.max_bytes_per_account{
-            heap_accounts_index += 1;
-            heap_data = heap_accounts[heap_accounts_index].data.borrow_mut();
-            heap_data_index = 0;
-        }
-    }
+    let mut heap_storage = PagedStorage::new(pages, &heap_accounts, element_size, heap_meta.max_elements_per_account, heap_meta.data_header_len())?;
+    heap_storage.write_range(0, &data_vec.concat())?;
 
     Ok(())
 }
 
 pub fn get_meta(
     accounts: &[AccountInfo],
+    program_id: &Pubkey,
 ) -> Result<HeapMeta, ProgramError> {
 
     let account_info_iter = &mut accounts.iter().peekable();
 
     let heap_meta_account = next_account_info(account_info_iter)?;
+    check_meta_account(heap_meta_account, program_id)?;
     let heap_meta = HeapMeta::try_from_slice(&heap_meta_account.data.borrow())?;
 
     Ok(heap_meta)
@@ -345,18 +643,31 @@ pub fn get_meta(
 pub fn push(
     accounts: &[AccountInfo],
     data: &[u8],
-    compare: impl Fn(&Vec<u8>, &Vec<u8>) -> Result<i64, ProgramError>
+    compare: impl Fn(&[u8], &[u8]) -> Result<i64, ProgramError>,
+    pages: &[u32],
+    program_id: &Pubkey,
  ) -> ProgramResult {
     let account_info_iter = &mut accounts.iter().peekable();
 
+    let auth = next_account_info(account_info_iter)?;
     let heap_meta_account = next_account_info(account_info_iter)?;
 
+    check_meta_account(heap_meta_account, program_id)?;
+    let mut heap_meta = HeapMeta::try_from_slice(&heap_meta_account.data.borrow())?;
+
+    // a lazy heap takes the system program after the meta to create data accounts on demand
+    let system_program = if heap_meta.lazy { Some(next_account_info(account_info_iter)?) } else { None };
+
     let mut heap_accounts = Vec::new();
     while account_info_iter.peek().is_some(){
         heap_accounts.push(next_account_info(account_info_iter)?);
     }
 
-    let mut heap_meta = HeapMeta::try_from_slice(&heap_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&heap_meta, &mut heap_accounts, program_id)?;
+
+    check_role(&heap_meta, auth, Role::Pusher)?;
+    check_heap_accounts(heap_meta_account, &heap_meta, pages, &heap_accounts, program_id)?;
+    check_provisioned(&heap_meta)?;
 
     if data.len() != heap_meta.element_size as usize{
         msg!("Not inserting a single element");
@@ -368,40 +679,25 @@ pub fn push(
         return Err(HeapError::InsufficientSpace.into());
     }
 
-    let mut heap_account_refs = Vec::with_capacity(heap_accounts.len());
-    for i in 0..heap_accounts.len(){
-        heap_account_refs.push(heap_accounts[i].data.borrow_mut());
+    if let Some(system_program) = system_program{
+        let indices = heap_meta.length..heap_meta.length + 1;
+        provision_elements(auth, system_program, heap_meta_account, &mut heap_meta, pages, &heap_accounts, indices, program_id)?;
     }
 
-    let mut cur = heap_meta.length;
-    let mut heap_accounts_index_cur = (cur / heap_meta.max_elements_per_account) as usize;
-    let mut heap_data_index_cur = ((cur % heap_meta.max_elements_per_account) * heap_meta.element_size) as usize;
+    let mut heap_storage = PagedStorage::new(pages, &heap_accounts, heap_meta.element_size, heap_meta.max_elements_per_account, heap_meta.data_header_len())?;
 
-    // write new element into account
-    for i in 0..data.len(){
-        heap_account_refs[heap_accounts_index_cur][heap_data_index_cur + i] = data[i];
-    }
+    // write new element into the first free slot
+    let mut cur = heap_meta.length;
+    heap_storage.write(cur, data)?;
 
     // use swaps to maintain heap invariant
-    let element_cur = (heap_account_refs[heap_accounts_index_cur][heap_data_index_cur..heap_data_index_cur + heap_meta.element_size as usize]).to_vec();
     while cur != 0{
         let par = (cur - 1) / 2;
-        let heap_accounts_index_par = (par / heap_meta.max_elements_per_account) as usize;
-        let heap_data_index_par = ((par % heap_meta.max_elements_per_account) * heap_meta.element_size) as usize;
-
-        let element_par = (heap_account_refs[heap_accounts_index_par][heap_data_index_par..heap_data_index_par + heap_meta.element_size as usize]).to_vec();
-        if compare(&element_cur, &element_par)? >= 0{
+        if compare(heap_storage.element(cur)?, heap_storage.element(par)?)? >= 0{
             break;
         }
-        // swap with parent
-        for i in 0..heap_meta.element_size as usize{
-            let tmp = heap_account_refs[heap_accounts_index_cur][heap_data_index_cur + i];
-            heap_account_refs[heap_accounts_index_cur][heap_data_index_cur + i] = heap_account_refs[heap_accounts_index_par][heap_data_index_par + i];
-            heap_account_refs[heap_accounts_index_par][heap_data_index_par + i] = tmp;
-        }
+        heap_storage.swap(cur, par)?;
         cur = par;
-        heap_accounts_index_cur = heap_accounts_index_par;
-        heap_data_index_cur = heap_data_index_par;
     }
 
     heap_meta.length += 1;
@@ -412,11 +708,14 @@ pub fn push(
 
 pub fn pop(
     accounts: &[AccountInfo],
-    compare: impl Fn(&Vec<u8>, &Vec<u8>) -> Result<i64, ProgramError>
+    compare: impl Fn(&[u8], &[u8]) -> Result<i64, ProgramError>,
+    pages: &[u32],
+    program_id: &Pubkey,
  ) -> Result<Vec<u8>, ProgramError> {
 
     let account_info_iter = &mut accounts.iter().peekable();
 
+    let auth = next_account_info(account_info_iter)?;
     let heap_meta_account = next_account_info(account_info_iter)?;
 
     let mut heap_accounts = Vec::new();
@@ -424,7 +723,12 @@ pub fn pop(
         heap_accounts.push(next_account_info(account_info_iter)?);
     }
 
+    check_meta_account(heap_meta_account, program_id)?;
     let mut heap_meta = HeapMeta::try_from_slice(&heap_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&heap_meta, &mut heap_accounts, program_id)?;
+    check_role(&heap_meta, auth, Role::Popper)?;
+    check_heap_accounts(heap_meta_account, &heap_meta, pages, &heap_accounts, program_id)?;
+    check_provisioned(&heap_meta)?;
 
 
     if heap_meta.length == 0{
@@ -432,79 +736,29 @@ pub fn pop(
         return Err(HeapError::RemoveFromEmpty.into());
     }
 
-    let mut heap_account_refs = Vec::with_capacity(heap_accounts.len());
-    for i in 0..heap_accounts.len(){
-        heap_account_refs.push(heap_accounts[i].data.borrow_mut());
-    }
+    let mut heap_storage = PagedStorage::new(pages, &heap_accounts, heap_meta.element_size, heap_meta.max_elements_per_account, heap_meta.data_header_len())?;
 
-    // put root into heap
-    let mut ret = Vec::with_capacity(heap_meta.element_size as usize);
-    for i in 0..heap_meta.element_size{
-        ret.push(heap_account_refs[0][i as usize]);
-    }
+    let ret = heap_storage.read(0)?;
 
     // write leaf into root
-    let last = heap_meta.length - 1;
-    let heap_accounts_index_leaf = (last / heap_meta.max_elements_per_account) as usize;
-    let heap_data_index_leaf = ((last % heap_meta.max_elements_per_account) * heap_meta.element_size) as usize;
-    for i in 0..heap_meta.element_size as usize{
-        heap_account_refs[0][i] = heap_account_refs[heap_accounts_index_leaf][heap_data_index_leaf];
-    }
-
     heap_meta.length -= 1;
+    heap_storage.copy_range(heap_meta.length, 0, 1)?;
+
     // swap root down to maintain heap invariant
     let mut cur = 0;
-    let mut heap_accounts_index_cur = (cur / heap_meta.max_elements_per_account) as usize;
-    let mut heap_data_index_cur = ((cur % heap_meta.max_elements_per_account) * heap_meta.element_size) as usize;
-    let mut element_cur = (heap_account_refs[heap_accounts_index_cur][heap_data_index_cur..heap_data_index_cur + heap_meta.element_size as usize]).to_vec();
     loop {
-        if 2 * cur + 1 >= heap_meta.length{ // no children
+        let mut child = 2 * cur + 1;
+        if child >= heap_meta.length{ // no children
             break;
         }
-        else if 2 * cur + 2 >= heap_meta.length{ // left child only
-            let child = 2 * cur + 1;
-            let heap_accounts_index_child = (child / heap_meta.max_elements_per_account) as usize;
-            let heap_data_index_child = ((child % heap_meta.max_elements_per_account) * heap_meta.element_size) as usize;
-            let element_child = (heap_account_refs[heap_accounts_index_child][heap_data_index_child..heap_data_index_child + heap_meta.element_size as usize]).to_vec();
-            if compare(&element_cur, &element_child)? <= 0{
-                break;
-            }
-            // swap with child
-            for i in 0..heap_meta.element_size as usize{
-                let tmp = heap_account_refs[heap_accounts_index_cur][heap_data_index_cur + i];
-                heap_account_refs[heap_accounts_index_cur][heap_data_index_cur + i] = heap_account_refs[heap_accounts_index_child][heap_data_index_child + i];
-                heap_account_refs[heap_accounts_index_child][heap_data_index_child + i] = tmp;
-            }
+        if child + 1 < heap_meta.length && compare(heap_storage.element(child)?, heap_storage.element(child + 1)?)? >= 0{ // let child be the smaller one
+            child += 1;
         }
-        else{ // two children
-            let child_a = 2 * cur + 1;
-            let mut heap_accounts_index_child_a = (child_a / heap_meta.max_elements_per_account) as usize;
-            let mut heap_data_index_child_a = ((child_a % heap_meta.max_elements_per_account) * heap_meta.element_size) as usize;
-            let mut element_child_a = (heap_account_refs[heap_accounts_index_child_a][heap_data_index_child_a..heap_data_index_child_a + heap_meta.element_size as usize]).to_vec();
-            let child_b = 2 * cur + 2;
-            let heap_accounts_index_child_b = (child_b / heap_meta.max_elements_per_account) as usize;
-            let heap_data_index_child_b = ((child_b % heap_meta.max_elements_per_account) * heap_meta.element_size) as usize;
-            let element_child_b = (heap_account_refs[heap_accounts_index_child_b][heap_data_index_child_b..heap_data_index_child_b + heap_meta.element_size as usize]).to_vec();
-            if compare(&element_child_a, &element_child_b)? >= 0{ // let child a be the smaller one
-                heap_accounts_index_child_a = heap_accounts_index_child_b;
-                heap_data_index_child_a = heap_data_index_child_b;
-                element_child_a = element_child_b;
-            }
-            if compare(&element_cur, &element_child_a)? <= 0{
-                break;
-            }
-            // swap with child
-            for i in 0..heap_meta.element_size as usize{
-                let tmp = heap_account_refs[heap_accounts_index_cur][heap_data_index_cur + i];
-                heap_account_refs[heap_accounts_index_cur][heap_data_index_cur + i] = heap_account_refs[heap_accounts_index_child_a][heap_data_index_child_a + i];
-                heap_account_refs[heap_accounts_index_child_a][heap_data_index_child_a + i] = tmp;
-            }
-
-            cur = child_a;
-            heap_accounts_index_cur = heap_accounts_index_child_a;
-            heap_data_index_cur = heap_data_index_child_a;
-            element_cur = element_child_a;
+        if compare(heap_storage.element(cur)?, heap_storage.element(child)?)? <= 0{
+            break;
         }
+        heap_storage.swap(cur, child)?;
+        cur = child;
     }
 
     heap_meta.serialize(&mut *heap_meta_account.data.borrow_mut())?;
@@ -514,6 +768,8 @@ pub fn pop(
 
  pub fn peek(
     accounts: &[AccountInfo],
+    pages: &[u32],
+    program_id: &Pubkey,
  ) -> Result<Vec<u8>, ProgramError> {
 
     let account_info_iter = &mut accounts.iter().peekable();
@@ -525,29 +781,117 @@ pub fn pop(
         heap_accounts.push(next_account_info(account_info_iter)?);
     }
 
+    check_meta_account(heap_meta_account, program_id)?;
     let heap_meta = HeapMeta::try_from_slice(&heap_meta_account.data.borrow())?;
+    check_heap_accounts(heap_meta_account, &heap_meta, pages, &heap_accounts, program_id)?;
+    check_provisioned(&heap_meta)?;
 
     if heap_meta.length == 0{
         msg!("Removing from empty heap");
         return Err(HeapError::RemoveFromEmpty.into());
     }
 
-    let mut heap_account_refs = Vec::with_capacity(heap_accounts.len());
-    for i in 0..heap_accounts.len(){
-        heap_account_refs.push(heap_accounts[i].data.borrow_mut());
+    let heap_storage = PagedStorage::new(pages, &heap_accounts, heap_meta.element_size, heap_meta.max_elements_per_account, heap_meta.data_header_len())?;
+    heap_storage.read(0)
+}
+
+/// Grows the data accounts passed towards their full size by up to `MAX_PERMITTED_DATA_INCREASE`
+/// bytes, the most a program may allocate per account in one instruction, with the signer paying
+/// the rent. Large heaps need several `Extend` instructions after `Initialize` before they can be
+/// used. Data accounts that do not exist yet are created.
+pub fn extend(
+    accounts: &[AccountInfo],
+    pages: &[u32],
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let heap_meta_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let mut heap_accounts = Vec::new();
+    while account_info_iter.peek().is_some(){
+        heap_accounts.push(next_account_info(account_info_iter)?);
+    }
+
+    check_meta_account(heap_meta_account, program_id)?;
+    let mut heap_meta = HeapMeta::try_from_slice(&heap_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&heap_meta, &mut heap_accounts, program_id)?;
+    check_role(&heap_meta, auth, Role::Admin)?;
+    check_heap_accounts(heap_meta_account, &heap_meta, pages, &heap_accounts, program_id)?;
+
+    for (&page, &heap_account) in pages.iter().zip(heap_accounts.iter()){
+        let target = heap_meta.data_header_len() + heap_meta.account_bytes(page as u64);
+        heap_meta.provisioned_bytes += provision_heap_account(auth, system_program, heap_meta_account, &heap_meta, heap_account, page, target, program_id)?;
+    }
+
+    heap_meta.serialize(&mut *heap_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Grows the heap to `max_length` elements in place. The data accounts passed are created or
+/// grown by up to `MAX_PERMITTED_DATA_INCREASE` bytes, `Extend` provisions the rest before the
+/// heap can be used again. The meta keeps the address derived from the max length it was created
+/// with.
+pub fn resize(
+    accounts: &[AccountInfo],
+    max_length: u64,
+    pages: &[u32],
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let heap_meta_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let mut heap_accounts = Vec::new();
+    while account_info_iter.peek().is_some(){
+        heap_accounts.push(next_account_info(account_info_iter)?);
+    }
+
+    check_meta_account(heap_meta_account, program_id)?;
+    let mut heap_meta = HeapMeta::try_from_slice(&heap_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&heap_meta, &mut heap_accounts, program_id)?;
+
+    // only the authority itself may resize the heap
+    check_authority(&heap_meta, auth)?;
+    check_provisioned(&heap_meta)?;
+
+    if max_length < heap_meta.max_length{
+        msg!("Resize cannot shrink the heap");
+        return Err(HeapError::InvalidParameters.into());
     }
+    check_parameters(max_length, heap_meta.element_size)?;
 
-    // put root into heap
-    let mut ret = Vec::with_capacity(heap_meta.element_size as usize);
-    for i in 0..heap_meta.element_size{
-        ret.push(heap_account_refs[0][i as usize]);
+    heap_meta.max_length = max_length;
+    heap_meta.max_bytes = max_length * heap_meta.element_size;
+    check_num_accounts(&heap_meta)?;
+    check_heap_accounts(heap_meta_account, &heap_meta, pages, &heap_accounts, program_id)?;
+
+    // a lazy heap creates its new data accounts when a push first needs them
+    if !heap_meta.lazy{
+        for (&page, &heap_account) in pages.iter().zip(heap_accounts.iter()){
+            let target = heap_meta.data_header_len() + heap_meta.account_bytes(page as u64);
+            heap_meta.provisioned_bytes += provision_heap_account(auth, system_program, heap_meta_account, &heap_meta, heap_account, page, target, program_id)?;
+        }
     }
-    Ok(ret)
+
+    heap_meta.serialize(&mut *heap_meta_account.data.borrow_mut())?;
+
+    Ok(())
 }
 
-pub fn delete(
+/// Shrinks the heap to `max_length` elements, which must still hold its elements; shrinking to the
+/// current length fits it to its contents, an empty heap keeps room for one element. Data accounts past the new capacity are closed, the
+/// last one kept is truncated, and the freed rent goes to the authority. Takes the data accounts
+/// from the last one kept on.
+pub fn shrink(
     accounts: &[AccountInfo],
-) -> ProgramResult{
+    max_length: u64,
+    pages: &[u32],
+    program_id: &Pubkey,
+) -> ProgramResult {
 
     let account_info_iter = &mut accounts.iter().peekable();
     let auth = next_account_info(account_info_iter)?;
@@ -557,21 +901,941 @@ pub fn delete(
         heap_accounts.push(next_account_info(account_info_iter)?);
     }
 
-    let mut auth_lamports = auth.lamports.borrow_mut();
-    let mut heap_meta_lamports = heap_meta_account.lamports.borrow_mut();
+    check_meta_account(heap_meta_account, program_id)?;
+    let mut heap_meta = HeapMeta::try_from_slice(&heap_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&heap_meta, &mut heap_accounts, program_id)?;
+
+    // only the authority itself may shrink the heap
+    check_authority(&heap_meta, auth)?;
+    check_heap_accounts(heap_meta_account, &heap_meta, pages, &heap_accounts, program_id)?;
+
+    // the paging arithmetic needs a capacity of at least one element
+    let max_length = max(max_length, 1);
+    if max_length > heap_meta.max_length || max_length < heap_meta.length{
+        msg!("Shrink cannot grow the heap or drop elements");
+        return Err(HeapError::InvalidParameters.into());
+    }
+    check_parameters(max_length, heap_meta.element_size)?;
 
-    **auth_lamports = auth_lamports.
-        checked_add(**heap_meta_lamports)
-        .ok_or(HeapError::Overflow)?;
-    **heap_meta_lamports = 0;
+    let old_num_accounts = heap_meta.num_accounts();
+    heap_meta.max_length = max_length;
+    heap_meta.max_bytes = max_length * heap_meta.element_size;
+    let num_accounts = heap_meta.num_accounts();
+    if !(num_accounts - 1..old_num_accounts).all(|page| pages.contains(&(page as u32))){
+        msg!("Shrink takes the heap accounts from {} to {}", num_accounts - 1, old_num_accounts - 1);
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
 
-    for i in 0..heap_accounts.len(){
-        let mut account_lamports = heap_accounts[i].lamports.borrow_mut();
-        **auth_lamports = auth_lamports
-                .checked_add(**account_lamports)
-                .ok_or(HeapError::Overflow)?;
-        **account_lamports = 0;
+    let rent = Rent::get()?;
+    for (&page, &heap_account) in pages.iter().zip(heap_accounts.iter()){
+        let provisioned = (heap_account.data_len() as u64).saturating_sub(heap_meta.data_header_len());
+        if page as u64 >= num_accounts{
+            // uncreated data accounts have nothing to close
+            if heap_account.owner == program_id{
+                close_account(heap_account, auth)?;
+            }
+            heap_meta.provisioned_bytes = heap_meta.provisioned_bytes.saturating_sub(provisioned);
+            continue;
+        }
+        let size = heap_meta.data_header_len() + heap_meta.account_bytes(page as u64);
+        if (heap_account.data_len() as u64) > size{
+            heap_account.realloc(size as usize, false)?;
+
+            let refund = heap_account.lamports().saturating_sub(rent.minimum_balance(size as usize));
+            let auth_lamports = auth.lamports().checked_add(refund).ok_or(HeapError::Overflow)?;
+            **heap_account.try_borrow_mut_lamports()? -= refund;
+            **auth.try_borrow_mut_lamports()? = auth_lamports;
+            heap_meta.provisioned_bytes = heap_meta.provisioned_bytes.saturating_sub(provisioned - heap_meta.account_bytes(page as u64));
+        }
     }
 
+    heap_meta.serialize(&mut *heap_meta_account.data.borrow_mut())?;
+
     Ok(())
 }
+
+pub fn delete(
+    accounts: &[AccountInfo],
+    pages: &[u32],
+    program_id: &Pubkey,
+) -> ProgramResult{
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let destination = next_account_info(account_info_iter)?;
+    let heap_meta_account = next_account_info(account_info_iter)?;
+    let mut heap_accounts = Vec::new();
+    while account_info_iter.peek().is_some(){
+        heap_accounts.push(next_account_info(account_info_iter)?);
+    }
+
+    check_meta_account(heap_meta_account, program_id)?;
+    let heap_meta = HeapMeta::try_from_slice(&heap_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&heap_meta, &mut heap_accounts, program_id)?;
+    check_heap_accounts(heap_meta_account, &heap_meta, pages, &heap_accounts, program_id)?;
+    // the data accounts cannot be closed without the meta, heaps with more of them than fit in
+    // a transaction are shrunk first
+    if pages.len() as u64 != heap_meta.num_accounts(){
+        msg!("Delete takes every heap account");
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+
+    // only the authority itself may close the heap
+    check_authority(&heap_meta, auth)?;
+
+    if destination.key == heap_meta_account.key || heap_accounts.iter().any(|heap_account| heap_account.key == destination.key){
+        msg!("Destination cannot be one of the heap accounts");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    close_account(heap_meta_account, destination)?;
+    for heap_account in heap_accounts.iter(){
+        // uncreated data accounts have nothing to close
+        if heap_account.owner == program_id{
+            close_account(heap_account, destination)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn set_delegate(
+    accounts: &[AccountInfo],
+    delegate: Pubkey,
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let heap_meta_account = next_account_info(account_info_iter)?;
+
+    check_meta_account(heap_meta_account, program_id)?;
+    let mut heap_meta = HeapMeta::try_from_slice(&heap_meta_account.data.borrow())?;
+    check_cpi_only(&heap_meta, account_info_iter.next(), program_id)?;
+
+    // only the authority itself may change the delegate
+    check_authority(&heap_meta, auth)?;
+
+    heap_meta.delegate = delegate;
+    heap_meta.serialize(&mut *heap_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Empties the heap. The data accounts keep their contents until elements are pushed over them.
+pub fn clear(
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let heap_meta_account = next_account_info(account_info_iter)?;
+
+    check_meta_account(heap_meta_account, program_id)?;
+    let mut heap_meta = HeapMeta::try_from_slice(&heap_meta_account.data.borrow())?;
+    check_cpi_only(&heap_meta, account_info_iter.next(), program_id)?;
+
+    // only the authority itself may clear the heap
+    check_authority(&heap_meta, auth)?;
+
+    heap_meta.length = 0;
+    heap_meta.serialize(&mut *heap_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Reads the meta `Migrate` rewrites and checks that `auth` may rewrite it. A version 0 meta comes
+/// back with `auth` as its authority and fully provisioned.
+fn read_meta_to_migrate(
+    auth: &AccountInfo,
+    heap_meta_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> Result<(HeapMeta, u8), ProgramError> {
+    if heap_meta_account.owner != program_id{
+        msg!("Invalid heap meta account");
+        return Err(HeapError::InvalidMetaAccount.into());
+    }
+    let (mut heap_meta, version) = HeapMeta::from_any_version(&heap_meta_account.data.borrow())?;
+    if version == LAYOUT_VERSION{
+        msg!("Heap meta is already at layout version {}", LAYOUT_VERSION);
+        return Ok((heap_meta, version));
+    }
+
+    if version == 0{
+        if !auth.is_signer{
+            msg!("Missing authority signature");
+            return Err(HeapError::Unauthorized.into());
+        }
+        // a version 0 meta is told apart by its length alone, so it has to be at the PDA the first
+        // release derived from its authority and sizes, which also makes the signer that authority
+        let (heap_meta_key, _) = Pubkey::find_program_address(
+            &[auth.key.as_ref(), &heap_meta.max_length.to_le_bytes(), &heap_meta.element_size.to_le_bytes()],
+            program_id,
+        );
+        if *heap_meta_account.key != heap_meta_key{
+            msg!("Heap meta account is not the version 0 meta of {}", auth.key);
+            return Err(HeapError::InvalidMetaAccount.into());
+        }
+        if heap_meta.max_bytes_per_account == 0{
+            msg!("Heap meta was never initialized");
+            return Err(HeapError::InvalidMetaAccount.into());
+        }
+        heap_meta.authority = *auth.key;
+        // the first release created every data account at its full size in Initialize
+        heap_meta.provisioned_bytes = heap_meta.max_bytes;
+        msg!("Set the authority to {}, the data accounts keep layout version 0", auth.key);
+    } else {
+        check_authority(&heap_meta, auth)?;
+    }
+
+    Ok((heap_meta, version))
+}
+
+/// Rewrites a meta written in an earlier layout version in the current one, growing the meta
+/// account with `authority` paying the rent, and returns the version it was written in. Fields
+/// that version did not have start zeroed. Version 0 metas predate authorities, so the signer
+/// becomes the authority and has to be the one the `[authority, max_length, element_size]` meta
+/// PDA was derived from; their data accounts keep their layout and the `[meta key, index as u8]`
+/// PDAs.
+pub fn migrate(
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+) -> Result<u8, ProgramError> {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let heap_meta_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    let (heap_meta, version) = read_meta_to_migrate(auth, heap_meta_account, program_id)?;
+    if version == LAYOUT_VERSION{
+        return Ok(version);
+    }
+
+    msg!("Grew the heap meta from {} to {} bytes", heap_meta_account.data_len(), HEAP_META_LEN);
+    grow_account(auth, system_program, heap_meta_account, HEAP_META_LEN, &Rent::get()?)?;
+    heap_meta.serialize(&mut *heap_meta_account.data.borrow_mut())?;
+
+    Ok(version)
+}
+
+/// Proposes `new_authority` as the authority of the heap. It takes over, along with the rent refunds,
+/// once it signs `AcceptAuthority`. Proposing the default pubkey withdraws the proposal.
+pub fn set_authority(
+    accounts: &[AccountInfo],
+    new_authority: Pubkey,
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let heap_meta_account = next_account_info(account_info_iter)?;
+
+    check_meta_account(heap_meta_account, program_id)?;
+    let mut heap_meta = HeapMeta::try_from_slice(&heap_meta_account.data.borrow())?;
+    check_cpi_only(&heap_meta, account_info_iter.next(), program_id)?;
+
+    // only the authority itself may hand the heap over
+    check_authority(&heap_meta, auth)?;
+
+    heap_meta.pending_authority = new_authority;
+    heap_meta.serialize(&mut *heap_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Makes the proposed authority, which must sign, the authority of the heap. The delegate of the
+/// previous authority is cleared.
+pub fn accept_authority(
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let new_auth = next_account_info(account_info_iter)?;
+    let heap_meta_account = next_account_info(account_info_iter)?;
+
+    check_meta_account(heap_meta_account, program_id)?;
+    let mut heap_meta = HeapMeta::try_from_slice(&heap_meta_account.data.borrow())?;
+    check_cpi_only(&heap_meta, account_info_iter.next(), program_id)?;
+
+    if heap_meta.pending_authority == Pubkey::default() || !new_auth.is_signer || *new_auth.key != heap_meta.pending_authority{
+        msg!("Missing signature of the proposed authority");
+        return Err(HeapError::Unauthorized.into());
+    }
+
+    heap_meta.authority = heap_meta.pending_authority;
+    heap_meta.pending_authority = Pubkey::default();
+    heap_meta.delegate = Pubkey::default();
+    heap_meta.serialize(&mut *heap_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Adds `member` to the allowlist of `role`.
+pub fn grant_role(
+    accounts: &[AccountInfo],
+    role: Role,
+    member: Pubkey,
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let heap_meta_account = next_account_info(account_info_iter)?;
+
+    check_meta_account(heap_meta_account, program_id)?;
+    let mut heap_meta = HeapMeta::try_from_slice(&heap_meta_account.data.borrow())?;
+    check_cpi_only(&heap_meta, account_info_iter.next(), program_id)?;
+    check_role_manager(&heap_meta, auth, role)?;
+
+    if member == Pubkey::default(){
+        msg!("Cannot grant a role to the default pubkey");
+        return Err(HeapError::InvalidParameters.into());
+    }
+    if !heap_meta.roles.grant(role, member){
+        msg!("The {:?} allowlist is full", role);
+        return Err(HeapError::RoleListFull.into());
+    }
+    heap_meta.serialize(&mut *heap_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Removes `member` from the allowlist of `role`, if it is on it.
+pub fn revoke_role(
+    accounts: &[AccountInfo],
+    role: Role,
+    member: Pubkey,
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let heap_meta_account = next_account_info(account_info_iter)?;
+
+    check_meta_account(heap_meta_account, program_id)?;
+    let mut heap_meta = HeapMeta::try_from_slice(&heap_meta_account.data.borrow())?;
+    check_cpi_only(&heap_meta, account_info_iter.next(), program_id)?;
+    check_role_manager(&heap_meta, auth, role)?;
+
+    heap_meta.roles.revoke(role, &member);
+    heap_meta.serialize(&mut *heap_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Sets whether only other programs may change the heap, e.g. the program whose PDA is the
+/// authority. Reads stay open to everyone. Once set, clearing it takes a call from another program
+/// as well.
+pub fn set_cpi_only(
+    accounts: &[AccountInfo],
+    cpi_only: bool,
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let heap_meta_account = next_account_info(account_info_iter)?;
+
+    check_meta_account(heap_meta_account, program_id)?;
+    let mut heap_meta = HeapMeta::try_from_slice(&heap_meta_account.data.borrow())?;
+    check_cpi_only(&heap_meta, account_info_iter.next(), program_id)?;
+
+    // only the authority itself may restrict the callers
+    check_authority(&heap_meta, auth)?;
+
+    heap_meta.cpi_only = cpi_only;
+    heap_meta.serialize(&mut *heap_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Address and bump of the meta account of the heap `authority` creates with these parameters.
+/// `id` tells apart the heaps of one authority that share their sizes.
+pub fn find_meta_address(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    max_length: u64,
+    element_size: u64,
+    id: u64,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[authority.as_ref(), &max_length.to_le_bytes(), &element_size.to_le_bytes(), &id.to_le_bytes()], program_id)
+}
+
+/// Page indices and addresses of the data accounts at `pages` of the heap with meta account `meta`,
+/// as the builders take them. The seed of each one is its page index as a little-endian u32.
+pub fn find_data_account_addresses(
+    program_id: &Pubkey,
+    meta: &Pubkey,
+    pages: &[u32],
+) -> Vec<(u32, Pubkey)> {
+    pages.iter().map(|&page| (page, Pubkey::find_program_address(&[meta.as_ref(), &page.to_le_bytes()], program_id).0)).collect()
+}
+
+/// Page indices of the data accounts a `Push` onto the heap touches: the pages of the new element at
+/// index `length` and of its ancestors up to the root, which it may sift up past. They follow from
+/// the length alone.
+pub fn push_pages(
+    heap_meta: &HeapMeta,
+) -> Vec<u32> {
+    let mut indices = vec![heap_meta.length];
+    let mut cur = heap_meta.length;
+    while cur != 0{
+        cur = (cur - 1) / 2;
+        indices.push(cur);
+    }
+    element_pages(heap_meta, &indices)
+}
+
+/// Page indices of the data accounts a `Pop` from the heap touches, computed from a read of its
+/// data: `element(i)` returns element `i` as the data accounts hold it before the pop. `Pop` moves
+/// the last element to the root and sifts it down, comparing both children of every node it
+/// reaches, so it touches the pages of the root, of the last element and of the children of each
+/// node on that path. `compare` is the order of the heap, `processor::compare` for this program.
+pub fn pop_pages(
+    heap_meta: &HeapMeta,
+    element: impl Fn(u64) -> Result<Vec<u8>, ProgramError>,
+    compare: impl Fn(&[u8], &[u8]) -> Result<i64, ProgramError>,
+) -> Result<Vec<u32>, ProgramError> {
+    if heap_meta.length == 0{
+        return Ok(Vec::new());
+    }
+    let length = heap_meta.length - 1;
+    let last = element(length)?;
+    let mut indices = vec![0, length];
+    let mut cur = 0;
+    loop {
+        let mut child = 2 * cur + 1;
+        if child >= length{
+            break;
+        }
+        indices.push(child);
+        let mut smaller = element(child)?;
+        if child + 1 < length{
+            indices.push(child + 1);
+            let right = element(child + 1)?;
+            if compare(&smaller, &right)? >= 0{
+                child += 1;
+                smaller = right;
+            }
+        }
+        if compare(&last, &smaller)? <= 0{
+            break;
+        }
+        cur = child;
+    }
+    Ok(element_pages(heap_meta, &indices))
+}
+
+/// Sorted page indices of the data accounts holding the elements at `indices`.
+fn element_pages(
+    heap_meta: &HeapMeta,
+    indices: &[u64],
+) -> Vec<u32> {
+    let mut pages: Vec<u32> = indices.iter().map(|index| (index / heap_meta.max_elements_per_account) as u32).collect();
+    pages.sort_unstable();
+    pages.dedup();
+    pages
+}
+
+/// Number of data accounts backing `max_length` elements of `element_size` bytes, in a lazy
+/// heap if `lazy`.
+pub fn num_data_accounts(
+    max_length: u64,
+    element_size: u64,
+    lazy: bool,
+) -> u64 {
+    let account_size = if lazy { LAZY_ACCOUNT_SIZE } else { MAX_ACCOUNT_SIZE };
+    let max_bytes_per_account = ((account_size - DATA_HEADER_LEN) / element_size) * element_size;
+    (max_length * element_size + max_bytes_per_account - 1) / max_bytes_per_account
+}
+
+/// Creates the heap with its meta and the data accounts at `pages`, paid for by `authority`, and
+/// pushes the elements in `data`, which have to fit the pages passed; `Extend` creates the others. A
+/// `lazy` heap creates its data accounts as pushes need them instead, with the pusher paying.
+#[allow(clippy::too_many_arguments)]
+pub fn build_initialize(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    max_length: u64,
+    element_size: u64,
+    data: &[u8],
+    lazy: bool,
+    id: u64,
+    pages: &[u32],
+) -> SolInstruction {
+    let (meta, _) = find_meta_address(program_id, authority, max_length, element_size, id);
+    let data_accounts = find_data_account_addresses(program_id, &meta, pages);
+
+    let mut account_metas = vec![
+        AccountMeta::new(*authority, true),
+        AccountMeta::new(meta, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+    account_metas.extend(data_accounts.iter().map(|(_, key)| AccountMeta::new(*key, false)));
+
+    let instruction = Instruction::Initialize(InitializeParams{
+        max_length,
+        element_size,
+        data: data.to_vec(),
+        lazy,
+        id,
+    });
+    SolInstruction::new_with_bytes(*program_id, &instruction.pack_with_pages(pages), account_metas)
+}
+
+/// Page indices of `data_accounts`, which the instruction data carries after the instruction.
+fn page_indices(
+    data_accounts: &[(u32, Pubkey)],
+) -> Vec<u32> {
+    data_accounts.iter().map(|(page, _)| *page).collect()
+}
+
+fn mutating_account_metas(
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+) -> Vec<AccountMeta> {
+    let mut account_metas = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    account_metas.extend(data_accounts.iter().map(|(_, key)| AccountMeta::new(*key, false)));
+    account_metas
+}
+
+/// Like `mutating_account_metas` for pushes to a lazy heap, which may create data accounts with the
+/// authority paying.
+fn lazy_push_account_metas(
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+) -> Vec<AccountMeta> {
+    let mut account_metas = vec![
+        AccountMeta::new(*authority, true),
+        AccountMeta::new(*meta, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    account_metas.extend(data_accounts.iter().map(|(_, key)| AccountMeta::new(*key, false)));
+    account_metas
+}
+
+fn read_only_account_metas(
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+) -> Vec<AccountMeta> {
+    let mut account_metas = vec![AccountMeta::new_readonly(*meta, false)];
+    account_metas.extend(data_accounts.iter().map(|(_, key)| AccountMeta::new_readonly(*key, false)));
+    account_metas
+}
+
+/// Pushes the element in `data` onto the heap. `data_accounts` lists the pages `push_pages` names.
+pub fn build_push(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    data: &[u8],
+) -> SolInstruction {
+    let instruction_data = Instruction::Push(PushParams{ data: data.to_vec() }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, mutating_account_metas(authority, meta, data_accounts))
+}
+
+/// `build_push` for a lazy heap, creating data accounts as needed with `authority` paying.
+pub fn build_push_lazy(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    data: &[u8],
+) -> SolInstruction {
+    let instruction_data = Instruction::Push(PushParams{ data: data.to_vec() }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, lazy_push_account_metas(authority, meta, data_accounts))
+}
+
+/// Pops the root element. `data_accounts` lists the pages `pop_pages` computes from a read of the
+/// data accounts; which of them the element sifting down passes depends on the elements.
+pub fn build_pop(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+) -> SolInstruction {
+    SolInstruction::new_with_bytes(*program_id, &Instruction::Pop.pack_with_pages(&page_indices(data_accounts)), mutating_account_metas(authority, meta, data_accounts))
+}
+
+/// Reads the root element. Read-only, the authority does not sign.
+pub fn build_peek(
+    program_id: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+) -> SolInstruction {
+    SolInstruction::new_with_bytes(*program_id, &Instruction::Peek.pack_with_pages(&page_indices(data_accounts)), read_only_account_metas(meta, data_accounts))
+}
+
+/// Closes the heap and moves its lamports to `destination`. `data_accounts` lists every data account.
+pub fn build_delete(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    destination: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+) -> SolInstruction {
+    let mut account_metas = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*destination, false),
+        AccountMeta::new(*meta, false),
+    ];
+    account_metas.extend(data_accounts.iter().map(|(_, key)| AccountMeta::new(*key, false)));
+    SolInstruction::new_with_bytes(*program_id, &Instruction::Delete.pack_with_pages(&page_indices(data_accounts)), account_metas)
+}
+
+pub fn build_set_delegate(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    delegate: &Pubkey,
+) -> SolInstruction {
+    let instruction_data = Instruction::SetDelegate(SetDelegateParams{ delegate: *delegate }).pack();
+    let account_metas = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, account_metas)
+}
+
+/// Grows `data_accounts` by up to `MAX_PERMITTED_DATA_INCREASE` bytes each, creating the ones that
+/// do not exist yet, paid by `authority`.
+pub fn build_extend(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+) -> SolInstruction {
+    let mut account_metas = vec![
+        AccountMeta::new(*authority, true),
+        AccountMeta::new(*meta, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    account_metas.extend(data_accounts.iter().map(|(_, key)| AccountMeta::new(*key, false)));
+    SolInstruction::new_with_bytes(*program_id, &Instruction::Extend.pack_with_pages(&page_indices(data_accounts)), account_metas)
+}
+
+/// Grows the heap to `max_length` elements. `data_accounts` lists data accounts of the grown
+/// heap to create or grow, see `find_data_account_addresses`.
+pub fn build_resize(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    max_length: u64,
+) -> SolInstruction {
+    let mut account_metas = vec![
+        AccountMeta::new(*authority, true),
+        AccountMeta::new(*meta, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    account_metas.extend(data_accounts.iter().map(|(_, key)| AccountMeta::new(*key, false)));
+    let instruction_data = Instruction::Resize(ResizeParams{ max_length }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, account_metas)
+}
+
+/// Shrinks the heap to `max_length` elements and refunds the freed rent to `authority`.
+/// `data_accounts` lists the data accounts from the last one kept on, the ones past the new
+/// capacity are closed.
+pub fn build_shrink(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    max_length: u64,
+) -> SolInstruction {
+    let mut account_metas = vec![
+        AccountMeta::new(*authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    account_metas.extend(data_accounts.iter().map(|(_, key)| AccountMeta::new(*key, false)));
+    let instruction_data = Instruction::Shrink(ResizeParams{ max_length }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, account_metas)
+}
+
+/// Empties the heap.
+pub fn build_clear(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+) -> SolInstruction {
+    let account_metas = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    SolInstruction::new_with_bytes(*program_id, &Instruction::Clear.pack(), account_metas)
+}
+
+/// Rewrites the meta in the current layout, `authority` paying for the larger meta account.
+pub fn build_migrate(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+) -> SolInstruction {
+    let account_metas = vec![
+        AccountMeta::new(*authority, true),
+        AccountMeta::new(*meta, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    SolInstruction::new_with_bytes(*program_id, &Instruction::Migrate.pack(), account_metas)
+}
+
+/// Proposes `new_authority` as the authority of the heap, see `build_accept_authority`.
+pub fn build_set_authority(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    new_authority: &Pubkey,
+) -> SolInstruction {
+    let instruction_data = Instruction::SetAuthority(SetAuthorityParams{ new_authority: *new_authority }).pack();
+    let account_metas = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, account_metas)
+}
+
+/// Makes `new_authority`, proposed by `build_set_authority`, the authority of the heap.
+pub fn build_accept_authority(
+    program_id: &Pubkey,
+    new_authority: &Pubkey,
+    meta: &Pubkey,
+) -> SolInstruction {
+    let account_metas = vec![
+        AccountMeta::new_readonly(*new_authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    SolInstruction::new_with_bytes(*program_id, &Instruction::AcceptAuthority.pack(), account_metas)
+}
+
+/// Adds `member` to the allowlist of `role`. `authority` is the authority, or an admin for the
+/// pusher and popper roles.
+pub fn build_grant_role(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    role: Role,
+    member: &Pubkey,
+) -> SolInstruction {
+    let instruction_data = Instruction::GrantRole(RoleParams{ role, member: *member }).pack();
+    let account_metas = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, account_metas)
+}
+
+/// Removes `member` from the allowlist of `role`, see `build_grant_role`.
+pub fn build_revoke_role(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    role: Role,
+    member: &Pubkey,
+) -> SolInstruction {
+    let instruction_data = Instruction::RevokeRole(RoleParams{ role, member: *member }).pack();
+    let account_metas = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, account_metas)
+}
+
+/// Sets whether only other programs may change the heap.
+pub fn build_set_cpi_only(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    cpi_only: bool,
+) -> SolInstruction {
+    let instruction_data = Instruction::SetCpiOnly(SetCpiOnlyParams{ cpi_only }).pack();
+    let account_metas = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, account_metas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{processor::compare, state::{HeapMetaV0, HEAP_META_LENS}};
+
+    const MAX_LENGTH: u64 = 3;
+    const ELEMENT_SIZE: u64 = 4;
+
+    /// A version 0 meta as the first release initialized it, with a single data account.
+    fn meta_v0() -> Vec<u8> {
+        HeapMetaV0{
+            max_length: MAX_LENGTH,
+            element_size: ELEMENT_SIZE,
+            max_bytes: MAX_LENGTH * ELEMENT_SIZE,
+            length: 0,
+            max_elements_per_account: MAX_LENGTH,
+            max_bytes_per_account: MAX_LENGTH * ELEMENT_SIZE,
+        }.try_to_vec().unwrap()
+    }
+
+    fn meta_v0_key(program_id: &Pubkey, authority: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[authority.as_ref(), &MAX_LENGTH.to_le_bytes(), &ELEMENT_SIZE.to_le_bytes()], program_id).0
+    }
+
+    /// Runs the checks of `Migrate` with `signer` signing for the meta at `meta_key`.
+    fn read(program_id: &Pubkey, signer: &Pubkey, is_signer: bool, meta_key: &Pubkey, owner: &Pubkey, meta: &mut [u8]) -> Result<(HeapMeta, u8), ProgramError> {
+        let (mut auth_lamports, mut meta_lamports) = (0, 0);
+        let mut auth_data = vec![];
+        let auth = AccountInfo::new(signer, is_signer, true, &mut auth_lamports, &mut auth_data, &system_program::ID, false, 0);
+        let heap_meta_account = AccountInfo::new(meta_key, false, true, &mut meta_lamports, meta, owner, false, 0);
+        read_meta_to_migrate(&auth, &heap_meta_account, program_id)
+    }
+
+    #[test]
+    fn sets_authority_of_version_0_meta() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let meta_key = meta_v0_key(&program_id, &authority);
+
+        let (heap_meta, version) = read(&program_id, &authority, true, &meta_key, &program_id, &mut meta_v0()).unwrap();
+        assert_eq!(version, 0);
+        assert_eq!(heap_meta.authority, authority);
+        assert_eq!(heap_meta.provisioned_bytes, MAX_LENGTH * ELEMENT_SIZE);
+        assert!(heap_meta.legacy);
+    }
+
+    #[test]
+    fn rejects_wrong_signer() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let meta_key = meta_v0_key(&program_id, &authority);
+
+        let res = read(&program_id, &Pubkey::new_unique(), true, &meta_key, &program_id, &mut meta_v0());
+        assert_eq!(res.unwrap_err(), HeapError::InvalidMetaAccount.into());
+        let res = read(&program_id, &authority, false, &meta_key, &program_id, &mut meta_v0());
+        assert_eq!(res.unwrap_err(), HeapError::Unauthorized.into());
+
+        // a later layout already names its authority
+        let (mut heap_meta, _) = HeapMeta::from_any_version(&meta_v0()).unwrap();
+        heap_meta.version = 1;
+        heap_meta.authority = authority;
+        heap_meta.legacy = false;
+        let mut meta_v1 = heap_meta.try_to_vec().unwrap();
+        meta_v1.truncate(HEAP_META_LENS[1] as usize);
+        let res = read(&program_id, &Pubkey::new_unique(), true, &meta_key, &program_id, &mut meta_v1.clone());
+        assert_eq!(res.unwrap_err(), HeapError::Unauthorized.into());
+        let (heap_meta, version) = read(&program_id, &authority, true, &meta_key, &program_id, &mut meta_v1).unwrap();
+        assert_eq!(version, 1);
+        assert_eq!(heap_meta.authority, authority);
+    }
+
+    #[test]
+    fn rejects_non_meta_account() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let meta_key = meta_v0_key(&program_id, &authority);
+
+        // any program owned account of the version 0 length, e.g. a data account
+        let res = read(&program_id, &authority, true, &Pubkey::new_unique(), &program_id, &mut meta_v0());
+        assert_eq!(res.unwrap_err(), HeapError::InvalidMetaAccount.into());
+        let res = read(&program_id, &authority, true, &meta_key, &Pubkey::new_unique(), &mut meta_v0());
+        assert_eq!(res.unwrap_err(), HeapError::InvalidMetaAccount.into());
+        let res = read(&program_id, &authority, true, &meta_key, &program_id, &mut [0; 16]);
+        assert!(res.is_err());
+    }
+
+    /// A heap of the current layout holding the u16 elements `0..length` in order, `per_account` to a
+    /// data account, with its data accounts.
+    fn sorted_heap(authority: &Pubkey, length: u64, per_account: u64) -> (HeapMeta, Vec<Vec<u8>>) {
+        let element_size = 2;
+        let heap_meta = HeapMeta{
+            discriminator: HEAP_META_DISCRIMINATOR,
+            version: LAYOUT_VERSION,
+            max_length: length,
+            element_size,
+            max_bytes: length * element_size,
+            length,
+            max_elements_per_account: per_account,
+            max_bytes_per_account: per_account * element_size,
+            authority: *authority,
+            delegate: Pubkey::default(),
+            provisioned_bytes: length * element_size,
+            lazy: false,
+            id: 0,
+            is_initialized: true,
+            legacy: false,
+            pending_authority: Pubkey::default(),
+            roles: Roles::default(),
+            cpi_only: false,
+        };
+        let data = (0..heap_meta.num_accounts()).map(|page| {
+            let mut data = DATA_HEADER.try_to_vec().unwrap();
+            for i in page * per_account..min((page + 1) * per_account, length){
+                data.extend((i as u16).to_le_bytes());
+            }
+            data
+        }).collect();
+        (heap_meta, data)
+    }
+
+    /// Runs `Pop` as the authority with the data accounts of `pages`.
+    fn run_pop(program_id: &Pubkey, authority: &Pubkey, heap_meta: &mut HeapMeta, data: &mut [Vec<u8>], pages: &[u32]) -> Result<Vec<u8>, ProgramError> {
+        let meta_key = Pubkey::new_unique();
+        let (mut auth_lamports, mut meta_lamports) = (0, 0);
+        let mut auth_data = vec![];
+        let mut meta_data = heap_meta.try_to_vec().unwrap();
+        let keys: Vec<Pubkey> = pages.iter().map(|page| Pubkey::find_program_address(&[meta_key.as_ref(), &page.to_le_bytes()], program_id).0).collect();
+        let mut lamports = vec![0; pages.len()];
+        let mut accounts = vec![
+            AccountInfo::new(authority, true, true, &mut auth_lamports, &mut auth_data, &system_program::ID, false, 0),
+            AccountInfo::new(&meta_key, false, true, &mut meta_lamports, &mut meta_data, program_id, false, 0),
+        ];
+        let passed = data.iter_mut().enumerate().filter(|(page, _)| pages.contains(&(*page as u32))).map(|(_, data)| data);
+        for ((key, lamports), data) in keys.iter().zip(lamports.iter_mut()).zip(passed){
+            accounts.push(AccountInfo::new(key, false, true, lamports, data, program_id, false, 0));
+        }
+        let res = pop(&accounts, compare, pages, program_id);
+        drop(accounts);
+        *heap_meta = HeapMeta::try_from_slice(&meta_data).unwrap();
+        res
+    }
+
+    fn read_element(heap_meta: &HeapMeta, data: &[Vec<u8>], index: u64) -> Vec<u8> {
+        let offset = (DATA_HEADER_LEN + index % heap_meta.max_elements_per_account * heap_meta.element_size) as usize;
+        data[(index / heap_meta.max_elements_per_account) as usize][offset..offset + heap_meta.element_size as usize].to_vec()
+    }
+
+    #[test]
+    fn names_the_pages_push_and_pop_touch() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let (mut heap_meta, mut data) = sorted_heap(&authority, 15, 2);
+
+        // the new element at 15 sifts up past 7, 3, 1 and 0
+        assert_eq!(push_pages(&heap_meta), vec![0, 1, 3, 7]);
+
+        // the last element, 14, sifts down past 1, 3 and 7 and stops above 15 and 16, which the
+        // heap no longer holds
+        let pages = pop_pages(&heap_meta, |index| Ok(read_element(&heap_meta, &data, index)), compare).unwrap();
+        assert_eq!(pages, vec![0, 1, 2, 3, 4, 7]);
+        let without_page_3: Vec<u32> = pages.iter().copied().filter(|&page| page != 3).collect();
+        let (mut untouched_meta, mut untouched_data) = sorted_heap(&authority, 15, 2);
+        let res = run_pop(&program_id, &authority, &mut untouched_meta, &mut untouched_data, &without_page_3);
+        assert_eq!(res, Err(ProgramError::NotEnoughAccountKeys));
+
+        assert_eq!(run_pop(&program_id, &authority, &mut heap_meta, &mut data, &pages).unwrap(), vec![0, 0]);
+        assert_eq!(heap_meta.length, 14);
+        let elements: Vec<u16> = (0..14).map(|index| {
+            let element = read_element(&heap_meta, &data, index);
+            u16::from_le_bytes([element[0], element[1]])
+        }).collect();
+        assert_eq!(elements, vec![1, 3, 2, 7, 4, 5, 6, 14, 8, 9, 10, 11, 12, 13]);
+    }
+}
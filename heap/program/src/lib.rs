@@ -1,3 +1,5 @@
+#[cfg(feature = "cpi")]
+pub mod cpi;
 pub mod error;
 pub mod instruction;
 pub mod processor;
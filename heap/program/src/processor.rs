@@ -1,14 +1,17 @@
 use solana_program::{
-    account_info::{next_account_info, AccountInfo},
+    account_info::AccountInfo,
     entrypoint::ProgramResult,
     msg,
     program_error::ProgramError,
     pubkey::Pubkey,
 };
-use borsh::{BorshDeserialize};
 
-use crate::instruction::{Instruction, InitializeParams,
-                         initialize_heap_signed, push, pop, peek, delete};
+use solana_data_structures_core::return_data::set_elements_return_data;
+
+use crate::state::LAYOUT_VERSION;
+
+use crate::instruction::{Instruction, InitializeParams, SetDelegateParams, SetAuthorityParams, RoleParams, SetCpiOnlyParams,
+                         initialize_heap_signed, push, pop, peek, delete, set_delegate, extend, resize, shrink, clear, migrate, set_authority, accept_authority, grant_role, revoke_role, set_cpi_only};
 
 pub struct Processor;
 impl Processor {
@@ -17,84 +20,239 @@ impl Processor {
         accounts: &[AccountInfo],
         instruction_data: &[u8],
     ) -> ProgramResult {
-        let (tag, rest) = instruction_data.split_first().ok_or(ProgramError::InvalidInstructionData)?;
-        let instruction = Instruction::unpack(tag)?;
+        let (instruction, pages) = Instruction::unpack(instruction_data)?;
         match instruction {
-            Instruction::Initialize => {
+            Instruction::Initialize(params) => {
                 msg!("Instruction: Initialize");
-                let (inputs, data_seeds) = rest.split_at(24);
-                let params = InitializeParams::try_from_slice(inputs).unwrap();
-                let (data, seeds) = data_seeds.split_at((params.start_length*params.element_size) as usize);
-                Self::process_initialize(accounts, params.max_length, params.element_size, data, program_id, seeds)
+                Self::process_initialize(accounts, params, &pages, program_id)
             },
-            Instruction::Push => {
+            Instruction::Push(params) => {
                 msg!("Instruction: Push");
-                Self::process_push(accounts, rest)
+                Self::process_push(accounts, &params.data, &pages, program_id)
             }
             Instruction::Pop => {
                 msg!("Instruction: Pop");
-                Self::process_pop(accounts)
+                Self::process_pop(accounts, &pages, program_id)
             }
             Instruction::Peek => {
                 msg!("Instruction: Peek");
-                Self::process_peek(accounts)
+                Self::process_peek(accounts, &pages, program_id)
             }
             Instruction::Delete => {
                 msg!("Instruction: Delete");
-                Self::process_delete(accounts)
+                Self::process_delete(accounts, &pages, program_id)
+            }
+            Instruction::SetDelegate(params) => {
+                msg!("Instruction: SetDelegate");
+                Self::process_set_delegate(accounts, params, program_id)
+            }
+            Instruction::Extend => {
+                msg!("Instruction: Extend");
+                Self::process_extend(accounts, &pages, program_id)
+            }
+            Instruction::Resize(params) => {
+                msg!("Instruction: Resize");
+                Self::process_resize(accounts, params.max_length, &pages, program_id)
+            }
+            Instruction::Shrink(params) => {
+                msg!("Instruction: Shrink");
+                Self::process_shrink(accounts, params.max_length, &pages, program_id)
+            }
+            Instruction::Clear => {
+                msg!("Instruction: Clear");
+                Self::process_clear(accounts, program_id)
+            }
+            Instruction::Migrate => {
+                msg!("Instruction: Migrate");
+                Self::process_migrate(accounts, program_id)
+            }
+            Instruction::SetAuthority(params) => {
+                msg!("Instruction: SetAuthority");
+                Self::process_set_authority(accounts, params, program_id)
+            }
+            Instruction::AcceptAuthority => {
+                msg!("Instruction: AcceptAuthority");
+                Self::process_accept_authority(accounts, program_id)
+            }
+            Instruction::GrantRole(params) => {
+                msg!("Instruction: GrantRole");
+                Self::process_grant_role(accounts, params, program_id)
+            }
+            Instruction::RevokeRole(params) => {
+                msg!("Instruction: RevokeRole");
+                Self::process_revoke_role(accounts, params, program_id)
+            }
+            Instruction::SetCpiOnly(params) => {
+                msg!("Instruction: SetCpiOnly");
+                Self::process_set_cpi_only(accounts, params, program_id)
             }
         }
     }
 
     fn process_initialize(
         accounts: &[AccountInfo],
-        max_length: u64,
-        element_size: u64,
-        data: &[u8],
+        params: InitializeParams,
+        pages: &[u32],
         program_id: &Pubkey,
-        seeds: &[u8],
     ) -> ProgramResult {
-        let auth = next_account_info(&mut accounts.iter())?;
-        let (meta_bumper, heap_bumper_seeds) = seeds.split_first().ok_or(ProgramError::InvalidInstructionData)?;
-        let meta_seeds = &[auth.key.as_ref(), &max_length.to_le_bytes(), &element_size.to_le_bytes(), &[*meta_bumper]];
-        initialize_heap_signed(accounts, max_length, element_size, data, &compare, program_id, meta_seeds, heap_bumper_seeds)?;
+        initialize_heap_signed(accounts, params.max_length, params.element_size, &params.data, params.lazy, params.id, compare, pages, program_id)?;
         Ok(())
     }
 
     fn process_push(
         accounts: &[AccountInfo],
-        data: &[u8]
+        data: &[u8],
+        pages: &[u32],
+        program_id: &Pubkey,
     ) -> ProgramResult {
-        push(accounts, data, &compare)?;
+        push(accounts, data, compare, pages, program_id)?;
         Ok(())
     }
 
     fn process_pop(
         accounts: &[AccountInfo],
+        pages: &[u32],
+        program_id: &Pubkey,
     ) -> ProgramResult {
-        let res = pop(accounts, &compare)?;
+        let res = pop(accounts, compare, pages, program_id)?;
         msg!("Popped the entry: {:?}", res);
+        set_elements_return_data(&[res]);
         Ok(())
     }
 
     fn process_peek(
         accounts: &[AccountInfo],
+        pages: &[u32],
+        program_id: &Pubkey,
     ) -> ProgramResult {
-        let res = peek(accounts)?;
+        let res = peek(accounts, pages, program_id)?;
         msg!("Root entry: {:?}", res);
+        set_elements_return_data(&[res]);
         Ok(())
     }
 
     fn process_delete(
         accounts: &[AccountInfo],
+        pages: &[u32],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        delete(accounts, pages, program_id)?;
+        msg!("Closed the heap and moved the lamports to the destination");
+        Ok(())
+    }
+
+    fn process_set_delegate(
+        accounts: &[AccountInfo],
+        params: SetDelegateParams,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        set_delegate(accounts, params.delegate, program_id)?;
+        msg!("Set delegate to {}", params.delegate);
+        Ok(())
+    }
+
+    fn process_extend(
+        accounts: &[AccountInfo],
+        pages: &[u32],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        extend(accounts, pages, program_id)?;
+        msg!("Extended the heap data accounts");
+        Ok(())
+    }
+
+    fn process_resize(
+        accounts: &[AccountInfo],
+        max_length: u64,
+        pages: &[u32],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        resize(accounts, max_length, pages, program_id)?;
+        msg!("Resized the heap to {} elements", max_length);
+        Ok(())
+    }
+
+    fn process_shrink(
+        accounts: &[AccountInfo],
+        max_length: u64,
+        pages: &[u32],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        shrink(accounts, max_length, pages, program_id)?;
+        msg!("Shrank the heap to {} elements", max_length);
+        Ok(())
+    }
+
+    fn process_clear(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        clear(accounts, program_id)?;
+        msg!("Cleared the heap");
+        Ok(())
+    }
+
+    fn process_migrate(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        let version = migrate(accounts, program_id)?;
+        msg!("Migrated the heap meta from layout version {} to {}", version, LAYOUT_VERSION);
+        Ok(())
+    }
+
+    fn process_set_authority(
+        accounts: &[AccountInfo],
+        params: SetAuthorityParams,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        set_authority(accounts, params.new_authority, program_id)?;
+        msg!("Proposed {} as the new authority", params.new_authority);
+        Ok(())
+    }
+
+    fn process_accept_authority(
+        accounts: &[AccountInfo],
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        accept_authority(accounts, program_id)?;
+        msg!("Accepted the authority of the heap");
+        Ok(())
+    }
+
+    fn process_grant_role(
+        accounts: &[AccountInfo],
+        params: RoleParams,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        grant_role(accounts, params.role, params.member, program_id)?;
+        msg!("Granted the {:?} role to {}", params.role, params.member);
+        Ok(())
+    }
+
+    fn process_revoke_role(
+        accounts: &[AccountInfo],
+        params: RoleParams,
+        program_id: &Pubkey,
+    ) -> ProgramResult {
+        revoke_role(accounts, params.role, params.member, program_id)?;
+        msg!("Revoked the {:?} role from {}", params.role, params.member);
+        Ok(())
+    }
+
+    fn process_set_cpi_only(
+        accounts: &[AccountInfo],
+        params: SetCpiOnlyParams,
+        program_id: &Pubkey,
     ) -> ProgramResult {
-        delete(accounts)?;
-        msg!("Removed the lamports from all the accounts");
+        set_cpi_only(accounts, params.cpi_only, program_id)?;
+        msg!("Set cpi_only to {}", params.cpi_only);
         Ok(())
     }
 }
 
-fn compare(a: &Vec<u8>, b: &Vec<u8>) -> Result<i64, ProgramError> {
+/// The order of the heap: elements compare as little-endian unsigned integers, smallest at the root.
+pub fn compare(a: &[u8], b: &[u8]) -> Result<i64, ProgramError> {
     if a.len() != b.len(){
         return Err(ProgramError::InvalidArgument)
     }
@@ -108,5 +266,5 @@ fn compare(a: &Vec<u8>, b: &Vec<u8>) -> Result<i64, ProgramError> {
         }
     }
 
-    return Ok(0)
+    Ok(0)
 }
@@ -1,10 +1,59 @@
 use borsh::{BorshSerialize, BorshDeserialize};
+use std::cmp::min;
+use solana_program::{entrypoint::MAX_PERMITTED_DATA_INCREASE, program_error::ProgramError, pubkey::Pubkey};
+
+use solana_data_structures_core::{access::{Access, Roles}, layout::read_appended_meta};
+pub use solana_data_structures_core::{access::Role, return_data::{pack_elements, unpack_elements}};
+
+use crate::error::HeapError;
 
 pub const MAX_ACCOUNT_SIZE : u64 = 10 * 1024 * 1024;
-pub const HEAP_META_LEN : u64 = 48;
+pub const HEAP_META_LEN : u64 = 941;
+/// Layout version of the meta. Fields are only ever appended to the meta, `Migrate` brings metas
+/// of earlier versions up to this one.
+pub const LAYOUT_VERSION : u8 = 5;
+/// Layout version of the data accounts, bumped only when their layout changes.
+pub const DATA_LAYOUT_VERSION : u8 = 1;
+/// Length of the meta in every layout version, indexed by version. Version 0 is the layout of the
+/// first release, without a discriminator, authority or flags.
+pub const HEAP_META_LENS : [u64; LAYOUT_VERSION as usize + 1] = [48, 139, 140, 172, 940, HEAP_META_LEN];
+pub const HEAP_META_DISCRIMINATOR : [u8; 8] = *b"hep_meta";
+pub const HEAP_DATA_DISCRIMINATOR : [u8; 8] = *b"hep_data";
+/// Length of the `DataHeader` every data account starts with, before its elements.
+pub const DATA_HEADER_LEN : u64 = 9;
+pub const DATA_HEADER : DataHeader = DataHeader{ discriminator: HEAP_DATA_DISCRIMINATOR, version: DATA_LAYOUT_VERSION };
+/// Size of the data accounts of a lazy heap, small enough for a push to create one whole.
+pub const LAZY_ACCOUNT_SIZE : u64 = MAX_PERMITTED_DATA_INCREASE as u64;
 
 #[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
 pub struct HeapMeta{
+    pub discriminator: [u8; 8],
+    pub version: u8,
+    pub max_length: u64,
+    pub element_size: u64,
+    pub max_bytes: u64,
+    pub length: u64,
+    pub max_elements_per_account: u64,
+    pub max_bytes_per_account: u64,
+    pub authority: Pubkey,
+    pub delegate: Pubkey,
+    pub provisioned_bytes: u64,
+    pub lazy: bool,
+    pub id: u64,
+    pub is_initialized: bool,
+    // set on metas migrated from layout version 0, whose data accounts keep that layout
+    pub legacy: bool,
+    // proposed by `SetAuthority`, becomes the authority once it signs `AcceptAuthority`
+    pub pending_authority: Pubkey,
+    // allowlists of the pusher, popper and admin roles
+    pub roles: Roles,
+    // only calls from other programs may change the heap, see `SetCpiOnly`
+    pub cpi_only: bool,
+}
+
+/// The meta as layout version 0 wrote it.
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+pub struct HeapMetaV0{
     pub max_length: u64,
     pub element_size: u64,
     pub max_bytes: u64,
@@ -13,5 +62,176 @@ pub struct HeapMeta{
     pub max_bytes_per_account: u64,
 }
 
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+pub struct DataHeader{
+    pub discriminator: [u8; 8],
+    pub version: u8,
+}
+
+impl HeapMeta {
+    /// Reads a meta written in any layout version into the current layout and returns it with the
+    /// version it was written in. Fields that version did not have are zeroed. A version 0 meta comes
+    /// back initialized and `legacy`, without an authority.
+    pub fn from_any_version(data: &[u8]) -> Result<(Self, u8), ProgramError> {
+        if data.len() as u64 == HEAP_META_LENS[0] {
+            let meta_v0 = HeapMetaV0::try_from_slice(data)?;
+            let meta = HeapMeta{
+                discriminator: HEAP_META_DISCRIMINATOR,
+                version: LAYOUT_VERSION,
+                max_length: meta_v0.max_length,
+                element_size: meta_v0.element_size,
+                max_bytes: meta_v0.max_bytes,
+                length: meta_v0.length,
+                max_elements_per_account: meta_v0.max_elements_per_account,
+                max_bytes_per_account: meta_v0.max_bytes_per_account,
+                authority: Pubkey::default(),
+                delegate: Pubkey::default(),
+                provisioned_bytes: 0,
+                lazy: false,
+                id: 0,
+                is_initialized: true,
+                legacy: true,
+                pending_authority: Pubkey::default(),
+                roles: Roles::default(),
+                cpi_only: false,
+            };
+            return Ok((meta, 0));
+        }
+
+        let (current, version) = read_appended_meta(data, &HEAP_META_DISCRIMINATOR, &HEAP_META_LENS)
+            .ok_or(HeapError::InvalidMetaAccount)?;
+        let mut meta = Self::try_from_slice(&current)?;
+        meta.version = LAYOUT_VERSION;
+        Ok((meta, version))
+    }
+
+    /// Length of the header the data accounts start with.
+    pub fn data_header_len(&self) -> u64 {
+        if self.legacy { 0 } else { DATA_HEADER_LEN }
+    }
+
+    /// Seed that tells apart the data account at `index` from the other data accounts of the meta:
+    /// the index as a little-endian u32, or as a single byte in legacy layouts.
+    pub fn data_account_seed(&self, index: u32) -> Vec<u8> {
+        if self.legacy { vec![index as u8] } else { index.to_le_bytes().to_vec() }
+    }
+
+    pub fn num_accounts(&self) -> u64 {
+        (self.max_bytes + self.max_bytes_per_account - 1) / self.max_bytes_per_account
+    }
+
+    /// Number of data accounts their seeds can tell apart.
+    pub fn max_num_accounts(&self) -> u64 {
+        if self.legacy { 1 << 8 } else { 1 << 32 }
+    }
+
+    /// Element bytes the data account at `index` holds once it is fully provisioned.
+    pub fn account_bytes(&self, index: u64) -> u64 {
+        min(self.max_bytes.saturating_sub(index * self.max_bytes_per_account), self.max_bytes_per_account)
+    }
+}
+
+impl Access for HeapMeta {
+    fn unauthorized() -> ProgramError {
+        HeapError::Unauthorized.into()
+    }
+
+    fn authority(&self) -> &Pubkey {
+        &self.authority
+    }
+
+    fn delegate(&self) -> &Pubkey {
+        &self.delegate
+    }
+
+    fn roles(&self) -> &Roles {
+        &self.roles
+    }
+
+    fn cpi_only(&self) -> bool {
+        self.cpi_only
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrates_version_0_meta() {
+        let meta_v0 = HeapMetaV0{
+            max_length: 3,
+            element_size: 4,
+            max_bytes: 5,
+            length: 6,
+            max_elements_per_account: 7,
+            max_bytes_per_account: 8,
+        };
+        let data = meta_v0.try_to_vec().unwrap();
+        assert_eq!(data.len() as u64, HEAP_META_LENS[0]);
+
+        let (meta, version) = HeapMeta::from_any_version(&data).unwrap();
+        assert_eq!(version, 0);
+        assert_eq!(meta.discriminator, HEAP_META_DISCRIMINATOR);
+        assert_eq!(meta.version, LAYOUT_VERSION);
+        assert_eq!(meta.max_length, 3);
+        assert_eq!(meta.element_size, 4);
+        assert_eq!(meta.max_bytes, 5);
+        assert_eq!(meta.length, 6);
+        assert_eq!(meta.max_elements_per_account, 7);
+        assert_eq!(meta.max_bytes_per_account, 8);
+        assert_eq!(meta.authority, Pubkey::default());
+        assert_eq!(meta.provisioned_bytes, 0);
+        assert!(!meta.lazy);
+        assert!(meta.is_initialized);
+        assert!(meta.legacy);
+        assert_eq!(meta.data_header_len(), 0);
+        assert_eq!(meta.data_account_seed(1), vec![1]);
+        assert_eq!(meta.try_to_vec().unwrap().len() as u64, HEAP_META_LEN);
+    }
+
+    #[test]
+    fn migrates_version_1_meta() {
+        let mut data = vec![0; HEAP_META_LENS[1] as usize];
+        data[..8].copy_from_slice(&HEAP_META_DISCRIMINATOR);
+        data[8] = 1;
+        data[9] = 7;
+
+        let (meta, version) = HeapMeta::from_any_version(&data).unwrap();
+        assert_eq!(version, 1);
+        assert_eq!(meta.version, LAYOUT_VERSION);
+        assert_eq!(meta.max_length, 7);
+        assert!(!meta.legacy);
+        assert_eq!(meta.pending_authority, Pubkey::default());
+        assert_eq!(meta.try_to_vec().unwrap().len() as u64, HEAP_META_LEN);
+    }
 
+    #[test]
+    fn reads_current_meta_unchanged() {
+        let mut data = vec![0; HEAP_META_LEN as usize];
+        data[..8].copy_from_slice(&HEAP_META_DISCRIMINATOR);
+        data[8] = LAYOUT_VERSION;
+        data[9] = 7;
 
+        let (meta, version) = HeapMeta::from_any_version(&data).unwrap();
+        assert_eq!(version, LAYOUT_VERSION);
+        assert_eq!(meta.max_length, 7);
+        assert!(!meta.legacy);
+        assert_eq!(meta.data_header_len(), DATA_HEADER_LEN);
+        assert_eq!(meta.data_account_seed(1), 1u32.to_le_bytes().to_vec());
+        assert_eq!(meta.try_to_vec().unwrap(), data);
+    }
+
+    #[test]
+    fn rejects_unknown_layouts() {
+        let mut data = vec![0; HEAP_META_LEN as usize];
+        assert!(HeapMeta::from_any_version(&data).is_err());
+
+        data[..8].copy_from_slice(&HEAP_META_DISCRIMINATOR);
+        data[8] = LAYOUT_VERSION + 1;
+        assert!(HeapMeta::from_any_version(&data).is_err());
+
+        data[8] = LAYOUT_VERSION;
+        assert!(HeapMeta::from_any_version(&data[..HEAP_META_LEN as usize - 1]).is_err());
+    }
+}
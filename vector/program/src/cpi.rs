@@ -0,0 +1,147 @@
+//! Helpers for calling the vector program from other on-chain programs.
+//!
+//! Every function builds the instruction with the builders in `instruction`, invokes the program
+//! with the context's signer seeds and decodes the return data of reads.
+
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+use borsh::BorshDeserialize;
+
+use solana_data_structures_core::cpi::{self, invoke, invoke_read};
+pub use solana_data_structures_core::cpi::CpiContext;
+
+use crate::{instruction::{build_delete, build_get, build_pop, build_push, build_push_lazy, build_remove, build_set, build_swap_remove, build_set_delegate, build_extend, build_resize, build_shrink, build_clear, build_migrate, build_set_authority, build_accept_authority, build_grant_role, build_revoke_role, build_set_cpi_only, build_insert, build_insert_lazy}, state::{VectorMeta, Role}};
+
+pub fn push(ctx: &CpiContext, data: &[u8]) -> ProgramResult {
+    let instruction = build_push(ctx.program.key, ctx.authority.key, ctx.meta.key, &ctx.data_account_keys(), data);
+    invoke(ctx, &instruction, &[])
+}
+
+/// `push` for a lazy vector, creating data accounts as needed with the authority paying.
+pub fn push_lazy<'info>(ctx: &CpiContext<'_, 'info>, system_program: AccountInfo<'info>, data: &[u8]) -> ProgramResult {
+    let instruction = build_push_lazy(ctx.program.key, ctx.authority.key, ctx.meta.key, &ctx.data_account_keys(), data);
+    invoke(ctx, &instruction, &[system_program])
+}
+
+pub fn insert(ctx: &CpiContext, index: u64, data: &[u8]) -> ProgramResult {
+    let instruction = build_insert(ctx.program.key, ctx.authority.key, ctx.meta.key, &ctx.data_account_keys(), index, data);
+    invoke(ctx, &instruction, &[])
+}
+
+/// `insert` for a lazy vector, creating data accounts as needed with the authority paying.
+pub fn insert_lazy<'info>(ctx: &CpiContext<'_, 'info>, system_program: AccountInfo<'info>, index: u64, data: &[u8]) -> ProgramResult {
+    let instruction = build_insert_lazy(ctx.program.key, ctx.authority.key, ctx.meta.key, &ctx.data_account_keys(), index, data);
+    invoke(ctx, &instruction, &[system_program])
+}
+
+pub fn pop(ctx: &CpiContext, num_elements: u64) -> Result<Vec<Vec<u8>>, ProgramError> {
+    let instruction = build_pop(ctx.program.key, ctx.authority.key, ctx.meta.key, &ctx.data_account_keys(), num_elements);
+    invoke(ctx, &instruction, &[])?;
+    read_elements(ctx)
+}
+
+/// Reads elements `start..end`. The authority does not need to sign.
+pub fn get(ctx: &CpiContext, start: u64, end: u64) -> Result<Vec<Vec<u8>>, ProgramError> {
+    let instruction = build_get(ctx.program.key, ctx.meta.key, &ctx.data_account_keys(), start, end);
+    invoke_read(ctx, &instruction)?;
+    read_elements(ctx)
+}
+
+pub fn remove(ctx: &CpiContext, start: u64, end: u64) -> Result<Vec<Vec<u8>>, ProgramError> {
+    let instruction = build_remove(ctx.program.key, ctx.authority.key, ctx.meta.key, &ctx.data_account_keys(), start, end);
+    invoke(ctx, &instruction, &[])?;
+    read_elements(ctx)
+}
+
+pub fn set(ctx: &CpiContext, index: u64, data: &[u8]) -> ProgramResult {
+    let instruction = build_set(ctx.program.key, ctx.authority.key, ctx.meta.key, &ctx.data_account_keys(), index, data);
+    invoke(ctx, &instruction, &[])
+}
+
+/// Removes element `index`, moving the last element into its place. Fails with `InvalidAccountData`
+/// if the element is too large for the return data.
+pub fn swap_remove(ctx: &CpiContext, index: u64) -> Result<Vec<u8>, ProgramError> {
+    let instruction = build_swap_remove(ctx.program.key, ctx.authority.key, ctx.meta.key, &ctx.data_account_keys(), index);
+    invoke(ctx, &instruction, &[])?;
+    read_elements(ctx)?.pop().ok_or(ProgramError::InvalidAccountData)
+}
+
+/// Closes the vector and moves its lamports to `destination`.
+pub fn delete<'info>(ctx: &CpiContext<'_, 'info>, destination: AccountInfo<'info>) -> ProgramResult {
+    let instruction = build_delete(ctx.program.key, ctx.authority.key, destination.key, ctx.meta.key, &ctx.data_account_keys());
+    invoke(ctx, &instruction, &[destination])
+}
+
+pub fn set_delegate(ctx: &CpiContext, delegate: &Pubkey) -> ProgramResult {
+    let instruction = build_set_delegate(ctx.program.key, ctx.authority.key, ctx.meta.key, delegate);
+    invoke(ctx, &instruction, &[])
+}
+
+/// Grows the data accounts by up to `MAX_PERMITTED_DATA_INCREASE` bytes each, the authority
+/// paying the rent.
+pub fn extend<'info>(ctx: &CpiContext<'_, 'info>, system_program: AccountInfo<'info>) -> ProgramResult {
+    let instruction = build_extend(ctx.program.key, ctx.authority.key, ctx.meta.key, &ctx.data_account_keys());
+    invoke(ctx, &instruction, &[system_program])
+}
+
+/// Grows the vector to `max_length` elements. `ctx.data_accounts` lists data accounts of the grown
+/// vector to create or grow.
+pub fn resize<'info>(ctx: &CpiContext<'_, 'info>, system_program: AccountInfo<'info>, max_length: u64) -> ProgramResult {
+    let instruction = build_resize(ctx.program.key, ctx.authority.key, ctx.meta.key, &ctx.data_account_keys(), max_length);
+    invoke(ctx, &instruction, &[system_program])
+}
+
+/// Shrinks the vector to `max_length` elements, refunding the freed rent to the authority.
+/// `ctx.data_accounts` must list the data accounts from the last one kept on.
+pub fn shrink(ctx: &CpiContext, max_length: u64) -> ProgramResult {
+    let instruction = build_shrink(ctx.program.key, ctx.authority.key, ctx.meta.key, &ctx.data_account_keys(), max_length);
+    invoke(ctx, &instruction, &[])
+}
+
+pub fn clear(ctx: &CpiContext) -> ProgramResult {
+    let instruction = build_clear(ctx.program.key, ctx.authority.key, ctx.meta.key);
+    invoke(ctx, &instruction, &[])
+}
+
+/// Rewrites the meta in the current layout, the authority paying for the larger meta account.
+pub fn migrate<'info>(ctx: &CpiContext<'_, 'info>, system_program: AccountInfo<'info>) -> ProgramResult {
+    let instruction = build_migrate(ctx.program.key, ctx.authority.key, ctx.meta.key);
+    invoke(ctx, &instruction, &[system_program])
+}
+
+pub fn set_authority(ctx: &CpiContext, new_authority: &Pubkey) -> ProgramResult {
+    let instruction = build_set_authority(ctx.program.key, ctx.authority.key, ctx.meta.key, new_authority);
+    invoke(ctx, &instruction, &[])
+}
+
+/// Accepts the authority proposed by `set_authority`. `ctx.authority` is the proposed authority,
+/// e.g. a PDA of the calling program.
+pub fn accept_authority(ctx: &CpiContext) -> ProgramResult {
+    let instruction = build_accept_authority(ctx.program.key, ctx.authority.key, ctx.meta.key);
+    invoke(ctx, &instruction, &[])
+}
+
+pub fn grant_role(ctx: &CpiContext, role: Role, member: &Pubkey) -> ProgramResult {
+    let instruction = build_grant_role(ctx.program.key, ctx.authority.key, ctx.meta.key, role, member);
+    invoke(ctx, &instruction, &[])
+}
+
+pub fn revoke_role(ctx: &CpiContext, role: Role, member: &Pubkey) -> ProgramResult {
+    let instruction = build_revoke_role(ctx.program.key, ctx.authority.key, ctx.meta.key, role, member);
+    invoke(ctx, &instruction, &[])
+}
+
+pub fn set_cpi_only(ctx: &CpiContext, cpi_only: bool) -> ProgramResult {
+    let instruction = build_set_cpi_only(ctx.program.key, ctx.authority.key, ctx.meta.key, cpi_only);
+    invoke(ctx, &instruction, &[])
+}
+
+/// Decodes the elements the vector program just returned.
+fn read_elements(ctx: &CpiContext) -> Result<Vec<Vec<u8>>, ProgramError> {
+    let vector_meta = VectorMeta::try_from_slice(&ctx.meta.data.borrow())?;
+    cpi::read_elements(ctx, vector_meta.element_size)
+}
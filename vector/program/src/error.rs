@@ -12,6 +12,22 @@ pub enum VectorError {
     Overflow,
     #[error("IndexOutofBounds")]
     IndexOutofBounds,
+    #[error("Unauthorized")]
+    Unauthorized,
+    #[error("Invalid Meta Account")]
+    InvalidMetaAccount,
+    #[error("Invalid Data Account")]
+    InvalidDataAccount,
+    #[error("Return Data Too Large")]
+    ReturnDataTooLarge,
+    #[error("Invalid Parameters")]
+    InvalidParameters,
+    #[error("Not Provisioned")]
+    NotProvisioned,
+    #[error("Already Initialized")]
+    AlreadyInitialized,
+    #[error("Role List Full")]
+    RoleListFull,
 }
 
 impl From<VectorError> for ProgramError {
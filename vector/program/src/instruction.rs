@@ -1,57 +1,338 @@
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
-    entrypoint::ProgramResult,
+    entrypoint::{ProgramResult, MAX_PERMITTED_DATA_INCREASE},
+    instruction::{AccountMeta, Instruction as SolInstruction},
     msg,
-    program::{invoke, invoke_signed},
+    program::invoke,
     program_error::ProgramError,
     pubkey::Pubkey,
-    sysvar::{rent::Rent, Sysvar}
+    system_program,
+    sysvar::{self, rent::Rent, Sysvar}
 };
 use borsh::{BorshSerialize, BorshDeserialize};
-use std::{cmp::min};
+use std::{cmp::{max, min}};
 
-use crate::{error::VectorError, state::{MAX_ACCOUNT_SIZE, VECTOR_META_LEN, VectorMeta}};
+use solana_data_structures_core::{
+    access::{check_authority, check_cpi_only, check_cpi_only_accounts, check_role, check_role_manager, Roles},
+    account::{close_account, create_pda_account, grow_account},
+    PagedStorage,
+};
+pub use solana_data_structures_core::cpi::with_instructions_sysvar;
+
+use crate::{error::VectorError, state::{DATA_HEADER, DATA_HEADER_LEN, LAYOUT_VERSION, LAZY_ACCOUNT_SIZE, MAX_ACCOUNT_SIZE, VECTOR_META_DISCRIMINATOR, VECTOR_META_LEN, VectorMeta, DataHeader, Role}};
 
-#[derive(BorshDeserialize, PartialEq, Debug)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
 pub struct InitializeParams{
     pub max_length: u64,
     pub element_size: u64,
+    pub lazy: bool,
+    pub id: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+pub struct PushParams{
+    pub data: Vec<u8>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+pub struct InsertParams{
+    pub index: u64,
+    pub data: Vec<u8>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+pub struct SetParams{
+    pub index: u64,
+    pub data: Vec<u8>,
 }
 
-#[derive(BorshDeserialize, PartialEq, Debug)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
 pub struct PopParams{
     pub num_elements: u64,
 }
 
-#[derive(BorshDeserialize, PartialEq, Debug)]
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+pub struct SwapRemoveParams{
+    pub index: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
 pub struct GetParams{
     pub start: u64,
     pub end: u64,
 }
 
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+pub struct ResizeParams{
+    pub max_length: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+pub struct SetDelegateParams{
+    pub delegate: Pubkey,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+pub struct SetAuthorityParams{
+    pub new_authority: Pubkey,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+pub struct RoleParams{
+    pub role: Role,
+    pub member: Pubkey,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
+pub struct SetCpiOnlyParams{
+    pub cpi_only: bool,
+}
+
+/// Instructions of the vector program, serialized with Borsh: the variant index is the leading tag
+/// byte and the parameters follow it.
+#[derive(BorshSerialize, BorshDeserialize, PartialEq, Debug)]
 pub enum Instruction {
-    Initialize,
-    Push,
-    Pop,
-    Get,
-    Remove,
+    Initialize(InitializeParams),
+    Push(PushParams),
+    Pop(PopParams),
+    Get(GetParams),
+    Remove(GetParams),
     Delete,
+    SetDelegate(SetDelegateParams),
+    Extend,
+    Resize(ResizeParams),
+    Shrink(ResizeParams),
+    Clear,
+    Migrate,
+    SetAuthority(SetAuthorityParams),
+    AcceptAuthority,
+    GrantRole(RoleParams),
+    RevokeRole(RoleParams),
+    SetCpiOnly(SetCpiOnlyParams),
+    Insert(InsertParams),
+    Set(SetParams),
+    SwapRemove(SwapRemoveParams),
 }
 
 impl Instruction {
-    /// Unpacks a byte buffer into a [EscrowInstruction](enum.EscrowInstruction.html).
-    pub fn unpack(tag: &u8) -> Result<Self, ProgramError> {
+    /// Reads the instruction and the page indices of the data accounts passed with it, a Borsh
+    /// `Vec<u32>` after the instruction. Instructions without data accounts may leave it out.
+    pub fn unpack(input: &[u8]) -> Result<(Self, Vec<u32>), ProgramError> {
+        let mut input = input;
+        let instruction = Self::deserialize(&mut input).map_err(|_| ProgramError::InvalidInstructionData)?;
+        if input.is_empty(){
+            return Ok((instruction, Vec::new()));
+        }
+        let pages = Vec::<u32>::try_from_slice(input).map_err(|_| ProgramError::InvalidInstructionData)?;
+        Ok((instruction, pages))
+    }
+
+    pub fn pack(&self) -> Vec<u8> {
+        self.try_to_vec().unwrap()
+    }
+
+    /// Packs the instruction followed by the page indices of the data accounts passed with it.
+    pub fn pack_with_pages(&self, pages: &[u32]) -> Vec<u8> {
+        let mut data = self.pack();
+        data.extend(pages.to_vec().try_to_vec().unwrap());
+        data
+    }
+}
+
+/// Checks that the meta account is owned by this program and has the meta layout.
+fn check_meta_layout(
+    vector_meta_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    if vector_meta_account.owner != program_id || vector_meta_account.data_len() != VECTOR_META_LEN as usize{
+        msg!("Invalid vector meta account");
+        return Err(VectorError::InvalidMetaAccount.into());
+    }
+    Ok(())
+}
+
+/// Checks that the meta account is a vector meta of the current layout that `Initialize` has set up.
+fn check_meta_account(
+    vector_meta_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    check_meta_layout(vector_meta_account, program_id)?;
+    let vector_meta = VectorMeta::try_from_slice(&vector_meta_account.data.borrow())?;
+    if vector_meta.discriminator != VECTOR_META_DISCRIMINATOR || vector_meta.version != LAYOUT_VERSION{
+        msg!("Not a vector meta account of layout version {}", LAYOUT_VERSION);
+        return Err(VectorError::InvalidMetaAccount.into());
+    }
+    if !vector_meta.is_initialized{
+        msg!("Vector meta account is not initialized");
+        return Err(VectorError::InvalidMetaAccount.into());
+    }
+    Ok(())
+}
+
+/// Checks that the data accounts passed are the vector's data accounts at the page indices `pages`,
+/// in that order. The indices increase and each account is the PDA `[meta key, index as u32 LE]`,
+/// either owned by this program and starting with the data header or not created yet. Legacy data
+/// accounts have no header and the PDA `[meta key, index as u8]`.
+fn check_vector_accounts(
+    vector_meta_account: &AccountInfo,
+    vector_meta: &VectorMeta,
+    pages: &[u32],
+    vector_accounts: &[&AccountInfo],
+    program_id: &Pubkey,
+) -> ProgramResult {
+    if vector_accounts.len() < pages.len(){
+        msg!("Not enough accounts");
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    if vector_accounts.len() > pages.len(){
+        msg!("Too many vector accounts");
+        return Err(VectorError::InvalidDataAccount.into());
+    }
+
+    let num_accounts = vector_meta.num_accounts();
+    for (pages_index, (&page, vector_account)) in pages.iter().zip(vector_accounts.iter()).enumerate(){
+        if page as u64 >= num_accounts || (pages_index > 0 && page <= pages[pages_index - 1]){
+            msg!("Invalid page index {}", page);
+            return Err(VectorError::InvalidDataAccount.into());
+        }
+        let (vector_account_key, _) = Pubkey::find_program_address(
+            &[vector_meta_account.key.as_ref(), &vector_meta.data_account_seed(page)],
+            program_id,
+        );
+        // data accounts are created by Initialize, Extend or a push to a lazy vector
+        let uncreated = *vector_account.owner == system_program::id() && vector_account.data_len() == 0;
+        let created = vector_account.owner == program_id && (vector_meta.legacy || has_data_header(vector_account));
+        if (!created && !uncreated) || *vector_account.key != vector_account_key{
+            msg!("Invalid vector account {}", page);
+            return Err(VectorError::InvalidDataAccount.into());
+        }
+    }
+    Ok(())
+}
+
+/// The data account passed for page `page`, see `check_vector_accounts`.
+fn page_account<'b, 'a>(
+    pages: &[u32],
+    vector_accounts: &[&'b AccountInfo<'a>],
+    page: u64,
+) -> Result<&'b AccountInfo<'a>, ProgramError> {
+    let pages_index = pages.iter().position(|&passed| passed as u64 == page).ok_or_else(|| {
+        msg!("Missing vector account {}", page);
+        ProgramError::NotEnoughAccountKeys
+    })?;
+    Ok(vector_accounts[pages_index])
+}
+
+/// Rejects vectors that need more data accounts than their seeds can tell apart.
+fn check_num_accounts(
+    vector_meta: &VectorMeta,
+) -> ProgramResult {
+    if vector_meta.num_accounts() > vector_meta.max_num_accounts(){
+        msg!("The vector needs {} data accounts, at most {} can be addressed", vector_meta.num_accounts(), vector_meta.max_num_accounts());
+        return Err(VectorError::InvalidParameters.into());
+    }
+    Ok(())
+}
+
+/// Whether `vector_account` starts with the data header of the current layout.
+fn has_data_header(
+    vector_account: &AccountInfo,
+) -> bool {
+    let data = vector_account.data.borrow();
+    data.len() >= DATA_HEADER_LEN as usize
+        && DataHeader::try_from_slice(&data[..DATA_HEADER_LEN as usize]).map_or(false, |header| header == DATA_HEADER)
+}
+
+/// Rejects vectors whose data accounts `Extend` has not grown to their full size yet. Lazy vectors
+/// create and size their data accounts as pushes need them.
+fn check_provisioned(
+    vector_meta: &VectorMeta,
+) -> ProgramResult {
+    if !vector_meta.lazy && vector_meta.provisioned_bytes < vector_meta.max_bytes(){
+        msg!("Vector is not fully provisioned, send Extend first");
+        return Err(VectorError::NotProvisioned.into());
+    }
+    Ok(())
+}
+
+/// Grows the data account at `vector_accounts_index` towards `target` bytes by at most
+/// `MAX_PERMITTED_DATA_INCREASE`, creating it first if it does not exist yet, with `payer` paying
+/// the rent. `target` includes the data header. Returns the number of element bytes added.
+#[allow(clippy::too_many_arguments)]
+fn provision_vector_account<'a>(
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    vector_meta_account: &AccountInfo<'a>,
+    vector_meta: &VectorMeta,
+    vector_account: &AccountInfo<'a>,
+    page: u32,
+    target: u64,
+    program_id: &Pubkey,
+) -> Result<u64, ProgramError> {
+    let current = vector_account.data_len() as u64;
+    if current >= target{
+        return Ok(0);
+    }
+    let space = min(target, current + MAX_PERMITTED_DATA_INCREASE as u64);
+    let rent = Rent::get()?;
+
+    if vector_account.owner != program_id{
+        let (vector_account_key, bump) = Pubkey::find_program_address(
+            &[vector_meta_account.key.as_ref(), &vector_meta.data_account_seed(page)],
+            program_id,
+        );
+        if *vector_account.key != vector_account_key{
+            msg!("Invalid vector account {}", page);
+            return Err(VectorError::InvalidDataAccount.into());
+        }
+        let vector_account_seeds: &[&[u8]] = &[vector_meta_account.key.as_ref(), &vector_meta.data_account_seed(page), &[bump]];
+        create_pda_account(payer, system_program, vector_account, space, vector_account_seeds, &rent, program_id)?;
+        if !vector_meta.legacy{
+            DATA_HEADER.serialize(&mut *vector_account.data.borrow_mut())?;
+        }
+        msg!("Created vector account {}", page);
+        return Ok(space - vector_meta.data_header_len());
+    } else {
+        grow_account(payer, system_program, vector_account, space, &rent)?;
+    }
+
+    Ok(space - current)
+}
+
+/// Creates the data accounts of a lazy vector that hold the elements at `indices` and do not exist
+/// yet, with `payer` paying the rent.
+#[allow(clippy::too_many_arguments)]
+fn provision_elements<'a>(
+    payer: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    vector_meta_account: &AccountInfo<'a>,
+    vector_meta: &mut VectorMeta,
+    pages: &[u32],
+    vector_accounts: &[&AccountInfo<'a>],
+    indices: impl Iterator<Item = u64>,
+    program_id: &Pubkey,
+) -> ProgramResult {
+    for index in indices{
+        let page = index / vector_meta.max_elements_per_account;
+        let vector_account = page_account(pages, vector_accounts, page)?;
+        let target = vector_meta.data_header_len() + vector_meta.account_bytes(page);
+        vector_meta.provisioned_bytes += provision_vector_account(payer, system_program, vector_meta_account, vector_meta, vector_account, page as u32, target, program_id)?;
+    }
+    Ok(())
+}
 
-        Ok(match tag {
-            0 => Self::Initialize,
-            1 => Self::Push,
-            2 => Self::Pop,
-            3 => Self::Get,
-            4 => Self::Remove,
-            5 => Self::Delete,
-            _ => return Err(ProgramError::InvalidInstructionData),
-        })
-    }   
+/// Rejects parameters the paging arithmetic cannot handle: zero sizes, elements larger than a
+/// data account and capacities whose byte size overflows.
+fn check_parameters(
+    max_length: u64,
+    element_size: u64,
+) -> ProgramResult {
+    if max_length == 0 || element_size == 0 || element_size > MAX_ACCOUNT_SIZE - DATA_HEADER_LEN{
+        msg!("Invalid max length or element size");
+        return Err(VectorError::InvalidParameters.into());
+    }
+    max_length.checked_mul(element_size).ok_or(VectorError::Overflow)?;
+    Ok(())
 }
 
 pub fn initialize_vector(
@@ -72,6 +353,13 @@ pub fn initialize_vector(
         vector_accounts.push(next_account_info(account_info_iter)?);
     }
 
+    if !auth.is_signer{
+        msg!("Missing authority signature");
+        return Err(VectorError::Unauthorized.into());
+    }
+
+    check_parameters(max_length, element_size)?;
+
     msg!("Done parsing accounts and instruction data");
     
     // create vector meta account if it doesn't exist
@@ -97,13 +385,28 @@ pub fn initialize_vector(
 
     let mut vector_meta = VectorMeta::try_from_slice(&vector_meta_account.data.borrow())?;
 
+    // the meta of a live vector is never overwritten
+    if vector_meta.is_initialized{
+        msg!("Vector already initialized");
+        return Err(VectorError::AlreadyInitialized.into());
+    }
+
+    vector_meta.discriminator = VECTOR_META_DISCRIMINATOR;
+    vector_meta.version = LAYOUT_VERSION;
     vector_meta.max_length = max_length;
     vector_meta.element_size = element_size;
     vector_meta.length = 0;
-    vector_meta.max_elements_per_account = MAX_ACCOUNT_SIZE / element_size;
+    vector_meta.max_elements_per_account = (MAX_ACCOUNT_SIZE - DATA_HEADER_LEN) / element_size;
     vector_meta.max_bytes_per_account = vector_meta.max_elements_per_account * element_size;
-
-    vector_meta.serialize(&mut *vector_meta_account.data.borrow_mut())?;
+    vector_meta.authority = *auth.key;
+    vector_meta.delegate = Pubkey::default();
+    vector_meta.pending_authority = Pubkey::default();
+    vector_meta.roles = Roles::default();
+    vector_meta.cpi_only = false;
+    vector_meta.provisioned_bytes = 0;
+    vector_meta.lazy = false;
+    vector_meta.id = 0;
+    vector_meta.is_initialized = true;
 
     let mut size_to_allocate = max_length * element_size;
     let mut vector_accounts_index = 0;
@@ -113,8 +416,9 @@ pub fn initialize_vector(
             return Err(ProgramError::NotEnoughAccountKeys);
         }
 
+        let target = min(size_to_allocate, vector_meta.max_bytes_per_account);
         if vector_accounts[vector_accounts_index].data_len() == 0{
-            let space = min(size_to_allocate, vector_meta.max_bytes_per_account);
+            let space = min(DATA_HEADER_LEN + target, MAX_PERMITTED_DATA_INCREASE as u64);
             let required_lamports = rent.minimum_balance(space as usize);
             invoke(
                 &solana_program::system_instruction::create_account(
@@ -130,12 +434,16 @@ pub fn initialize_vector(
                     system_program.clone(),
                 ]
             )?;
-
-            size_to_allocate -= space;
-            vector_accounts_index += 1;
         }
+
+        DATA_HEADER.serialize(&mut *vector_accounts[vector_accounts_index].data.borrow_mut())?;
+        vector_meta.provisioned_bytes += (vector_accounts[vector_accounts_index].data_len() as u64).saturating_sub(DATA_HEADER_LEN);
+        size_to_allocate -= target;
+        vector_accounts_index += 1;
     }
 
+    vector_meta.serialize(&mut *vector_meta_account.data.borrow_mut())?;
+
     msg!("Completed initialize"); 
 
     Ok(())
@@ -145,9 +453,10 @@ pub fn initialize_vector_signed(
     accounts: &[AccountInfo],
     max_length: u64,
     element_size: u64,
+    lazy: bool,
+    id: u64,
+    pages: &[u32],
     program_id: &Pubkey,
-    meta_seeds: &[&[u8]],
-    vector_bump_seeds: &[u8],
 ) -> ProgramResult {
 
     let account_info_iter = &mut accounts.iter().peekable();
@@ -160,85 +469,96 @@ pub fn initialize_vector_signed(
     while account_info_iter.peek().is_some(){
         vector_accounts.push(next_account_info(account_info_iter)?);
     }
+
+    if !auth.is_signer{
+        msg!("Missing authority signature");
+        return Err(VectorError::Unauthorized.into());
+    }
+
+    check_parameters(max_length, element_size)?;
+    if lazy && element_size > LAZY_ACCOUNT_SIZE - DATA_HEADER_LEN{
+        msg!("Element size larger than a lazy vector data account");
+        return Err(VectorError::InvalidParameters.into());
+    }
+    let account_size = if lazy { LAZY_ACCOUNT_SIZE } else { MAX_ACCOUNT_SIZE };
+
+    // the meta and the data accounts only take the canonical bump, so every set of parameters has
+    // exactly one meta and each index one data account
+    let (vector_meta_key, meta_bump) = find_meta_address(program_id, auth.key, max_length, element_size, id);
+    if *vector_meta_account.key != vector_meta_key{
+        msg!("Vector meta account does not match the authority and parameters");
+        return Err(VectorError::InvalidMetaAccount.into());
+    }
+    let meta_seeds: &[&[u8]] = &[auth.key.as_ref(), &max_length.to_le_bytes(), &element_size.to_le_bytes(), &id.to_le_bytes(), &[meta_bump]];
     
     // create vector meta account if it doesn't exist
     if vector_meta_account.data_len() == 0{
-
         let space = VECTOR_META_LEN;
-        let required_lamports = rent.minimum_balance(space as usize);
-        invoke_signed(
-            &solana_program::system_instruction::create_account(
-                auth.key,
-                vector_meta_account.key,
-                required_lamports,
-                space,
-                program_id,
-            ),
-            &[
-                auth.clone(),
-                vector_meta_account.clone(),
-                system_program.clone(),
-            ],
-            &[meta_seeds]
-        )?;
+        create_pda_account(auth, system_program, vector_meta_account, space, meta_seeds, rent, program_id)?;
     }
 
+    check_meta_layout(vector_meta_account, program_id)?;
+
     msg!("Created vector meta account");
 
     let mut vector_meta = VectorMeta::try_from_slice(&vector_meta_account.data.borrow())?;
 
+    // the meta of a live vector is never overwritten
+    if vector_meta.is_initialized{
+        msg!("Vector already initialized");
+        return Err(VectorError::AlreadyInitialized.into());
+    }
+
+    vector_meta.discriminator = VECTOR_META_DISCRIMINATOR;
+    vector_meta.version = LAYOUT_VERSION;
     vector_meta.max_length = max_length;
     vector_meta.element_size = element_size;
     vector_meta.length = 0;
-    vector_meta.max_elements_per_account = MAX_ACCOUNT_SIZE / element_size;
+    vector_meta.max_elements_per_account = (account_size - DATA_HEADER_LEN) / element_size;
     vector_meta.max_bytes_per_account = vector_meta.max_elements_per_account * element_size;
+    vector_meta.authority = *auth.key;
+    vector_meta.delegate = Pubkey::default();
+    vector_meta.pending_authority = Pubkey::default();
+    vector_meta.roles = Roles::default();
+    vector_meta.cpi_only = false;
+    vector_meta.provisioned_bytes = 0;
+    vector_meta.lazy = lazy;
+    vector_meta.id = id;
+    vector_meta.is_initialized = true;
+
+    check_num_accounts(&vector_meta)?;
+    check_vector_accounts(vector_meta_account, &vector_meta, pages, &vector_accounts, program_id)?;
+
+    // the data accounts passed are created here, Extend creates and grows the rest and a lazy
+    // vector creates them when a push first needs them
+    for (&page, &vector_account) in pages.iter().zip(vector_accounts.iter()){
+        let (_, bump) = Pubkey::find_program_address(&[vector_meta_account.key.as_ref(), &page.to_le_bytes()], program_id);
+        let vector_account_seeds: &[&[u8]] = &[vector_meta_account.key.as_ref(), &page.to_le_bytes(), &[bump]];
+
+        // a program may only allocate MAX_PERMITTED_DATA_INCREASE bytes per account in one
+        // instruction, the rest is grown by Extend
+        let space = min(DATA_HEADER_LEN + vector_meta.account_bytes(page as u64), MAX_PERMITTED_DATA_INCREASE as u64);
+        create_pda_account(auth, system_program, vector_account, space, vector_account_seeds, rent, program_id)?;
+        DATA_HEADER.serialize(&mut *vector_account.data.borrow_mut())?;
+        vector_meta.provisioned_bytes += space - DATA_HEADER_LEN;
+
+        msg!("Created vector account {}", page);
+    }
 
     vector_meta.serialize(&mut *vector_meta_account.data.borrow_mut())?;
 
-    let mut size_to_allocate = max_length * element_size;
-    let mut vector_accounts_index = 0;
-    while size_to_allocate > 0 {
-        if vector_accounts_index == vector_accounts.len(){
-            msg!("Not enough accounts");
-            return Err(ProgramError::NotEnoughAccountKeys);
-        }
-
-        let space = min(size_to_allocate, vector_meta.max_bytes_per_account);
-        let required_lamports = rent.minimum_balance(space as usize);
-        invoke_signed(
-            &solana_program::system_instruction::create_account(
-                auth.key,
-                vector_accounts[vector_accounts_index].key,
-                required_lamports,
-                space,
-                program_id,
-            ),
-            &[
-                auth.clone(),
-                vector_accounts[vector_accounts_index].clone(),
-                system_program.clone(),
-            ],
-            &[&[vector_meta_account.key.as_ref(), &[vector_accounts_index as u8], 
-              &[vector_bump_seeds[vector_accounts_index]]]],
-        )?;
-
-        size_to_allocate -= space;
-
-        msg!("Created vector account {}", vector_accounts_index);
-
-        vector_accounts_index += 1;
-    }
-
     Ok(())
 }
 
 pub fn length(
     accounts: &[AccountInfo],
+    program_id: &Pubkey,
 ) -> Result<u64, ProgramError> {
 
     let account_info_iter = &mut accounts.iter().peekable();
 
     let vector_meta_account = next_account_info(account_info_iter)?;
+    check_meta_account(vector_meta_account, program_id)?;
     let vector_meta = VectorMeta::try_from_slice(&vector_meta_account.data.borrow())?;
 
     Ok(vector_meta.length)
@@ -247,18 +567,31 @@ pub fn length(
 pub fn push(
     accounts: &[AccountInfo],
     data: &[u8],
+    pages: &[u32],
+    program_id: &Pubkey,
 ) -> ProgramResult {
 
     let account_info_iter = &mut accounts.iter().peekable();
 
+    let auth = next_account_info(account_info_iter)?;
     let vector_meta_account = next_account_info(account_info_iter)?;
 
+    check_meta_account(vector_meta_account, program_id)?;
+    let mut vector_meta = VectorMeta::try_from_slice(&vector_meta_account.data.borrow())?;
+
+    // a lazy vector takes the system program after the meta to create data accounts on demand
+    let system_program = if vector_meta.lazy { Some(next_account_info(account_info_iter)?) } else { None };
+
     let mut vector_accounts = Vec::new();
     while account_info_iter.peek().is_some(){
         vector_accounts.push(next_account_info(account_info_iter)?);
     }
 
-    let mut vector_meta = VectorMeta::try_from_slice(&vector_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&vector_meta, &mut vector_accounts, program_id)?;
+
+    check_role(&vector_meta, auth, Role::Pusher)?;
+    check_vector_accounts(vector_meta_account, &vector_meta, pages, &vector_accounts, program_id)?;
+    check_provisioned(&vector_meta)?;
 
     if data.len() % (vector_meta.element_size) as usize != 0{
         msg!("Data length not multiple of element size");
@@ -266,38 +599,142 @@ pub fn push(
     }
 
     let num_elements = data.len() as u64 / vector_meta.element_size;
-    if vector_meta.length + num_elements > vector_meta.max_length{
+    if vector_meta.length.checked_add(num_elements).ok_or(VectorError::Overflow)? > vector_meta.max_length{
         msg!("Not enough space");
         return Err(VectorError::InsufficientSpace.into());
     }
 
-    let mut vector_accounts_index = (vector_meta.length / vector_meta.max_elements_per_account) as usize;
-    let mut vector_data = vector_accounts[vector_accounts_index].data.borrow_mut();
-    let mut vector_data_index = ((vector_meta.length % vector_meta.max_elements_per_account) * vector_meta.element_size) as usize;
+    if let Some(system_program) = system_program{
+        let indices = vector_meta.length..vector_meta.length + num_elements;
+        provision_elements(auth, system_program, vector_meta_account, &mut vector_meta, pages, &vector_accounts, indices, program_id)?;
+    }
+
+    let mut vector_storage = PagedStorage::new(pages, &vector_accounts, vector_meta.element_size, vector_meta.max_elements_per_account, vector_meta.data_header_len())?;
+    vector_storage.write_range(vector_meta.length, data)?;
 
-    for data_index in 0..data.len(){
-        vector_data[vector_data_index] = data[data_index];
-        vector_data_index += 1;
-        if vector_data_index as u64 >= vector_meta.max_bytes_per_account{
-            vector_accounts_index += 1;
-            vector_data = vector_accounts[vector_accounts_index].data.borrow_mut();
-            vector_data_index = 0;
-        }
+    vector_meta.length += num_elements;
+    vector_meta.serialize(&mut *vector_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Inserts the elements packed in `data` at `index`, shifting the elements from `index` on right.
+/// Takes the same accounts as `push`.
+pub fn insert(
+    accounts: &[AccountInfo],
+    index: u64,
+    data: &[u8],
+    pages: &[u32],
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+
+    let auth = next_account_info(account_info_iter)?;
+    let vector_meta_account = next_account_info(account_info_iter)?;
+
+    check_meta_account(vector_meta_account, program_id)?;
+    let mut vector_meta = VectorMeta::try_from_slice(&vector_meta_account.data.borrow())?;
+
+    // a lazy vector takes the system program after the meta to create data accounts on demand
+    let system_program = if vector_meta.lazy { Some(next_account_info(account_info_iter)?) } else { None };
+
+    let mut vector_accounts = Vec::new();
+    while account_info_iter.peek().is_some(){
+        vector_accounts.push(next_account_info(account_info_iter)?);
+    }
+
+    check_cpi_only_accounts(&vector_meta, &mut vector_accounts, program_id)?;
+
+    check_role(&vector_meta, auth, Role::Pusher)?;
+    check_vector_accounts(vector_meta_account, &vector_meta, pages, &vector_accounts, program_id)?;
+    check_provisioned(&vector_meta)?;
+
+    if data.len() % (vector_meta.element_size) as usize != 0{
+        msg!("Data length not multiple of element size");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    if index > vector_meta.length{
+        msg!("Index Out of Bounds");
+        return Err(VectorError::IndexOutofBounds.into());
+    }
+
+    let num_elements = data.len() as u64 / vector_meta.element_size;
+    if vector_meta.length.checked_add(num_elements).ok_or(VectorError::Overflow)? > vector_meta.max_length{
+        msg!("Not enough space");
+        return Err(VectorError::InsufficientSpace.into());
+    }
+
+    if let Some(system_program) = system_program{
+        let indices = vector_meta.length..vector_meta.length + num_elements;
+        provision_elements(auth, system_program, vector_meta_account, &mut vector_meta, pages, &vector_accounts, indices, program_id)?;
     }
 
+    let mut vector_storage = PagedStorage::new(pages, &vector_accounts, vector_meta.element_size, vector_meta.max_elements_per_account, vector_meta.data_header_len())?;
+    vector_storage.copy_range(index, index + num_elements, vector_meta.length - index)?;
+    vector_storage.write_range(index, data)?;
+
     vector_meta.length += num_elements;
     vector_meta.serialize(&mut *vector_meta_account.data.borrow_mut())?;
 
     Ok(())
 }
 
+/// Overwrites the elements from `index` on with the elements packed in `data`. The length does not
+/// change.
+pub fn set(
+    accounts: &[AccountInfo],
+    index: u64,
+    data: &[u8],
+    pages: &[u32],
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+
+    let auth = next_account_info(account_info_iter)?;
+    let vector_meta_account = next_account_info(account_info_iter)?;
+
+    let mut vector_accounts = Vec::new();
+    while account_info_iter.peek().is_some(){
+        vector_accounts.push(next_account_info(account_info_iter)?);
+    }
+
+    check_meta_account(vector_meta_account, program_id)?;
+    let vector_meta = VectorMeta::try_from_slice(&vector_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&vector_meta, &mut vector_accounts, program_id)?;
+    check_role(&vector_meta, auth, Role::Pusher)?;
+    check_vector_accounts(vector_meta_account, &vector_meta, pages, &vector_accounts, program_id)?;
+    check_provisioned(&vector_meta)?;
+
+    if data.len() % (vector_meta.element_size) as usize != 0{
+        msg!("Data length not multiple of element size");
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    let num_elements = data.len() as u64 / vector_meta.element_size;
+    if index.checked_add(num_elements).ok_or(VectorError::Overflow)? > vector_meta.length{
+        msg!("Index Out of Bounds");
+        return Err(VectorError::IndexOutofBounds.into());
+    }
+
+    let mut vector_storage = PagedStorage::new(pages, &vector_accounts, vector_meta.element_size, vector_meta.max_elements_per_account, vector_meta.data_header_len())?;
+    vector_storage.write_range(index, data)?;
+
+    Ok(())
+}
+
 pub fn pop_slice(
     accounts: &[AccountInfo],
     num_elements: u64,
+    pages: &[u32],
+    program_id: &Pubkey,
 ) -> Result<Vec<Vec<u8>>, ProgramError> {
 
     let account_info_iter = &mut accounts.iter().peekable();
 
+    let auth = next_account_info(account_info_iter)?;
     let vector_meta_account = next_account_info(account_info_iter)?;
 
     let mut vector_accounts = Vec::new();
@@ -305,31 +742,22 @@ pub fn pop_slice(
         vector_accounts.push(next_account_info(account_info_iter)?);
     }
 
+    check_meta_account(vector_meta_account, program_id)?;
     let mut vector_meta = VectorMeta::try_from_slice(&vector_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&vector_meta, &mut vector_accounts, program_id)?;
+    check_role(&vector_meta, auth, Role::Popper)?;
+    check_vector_accounts(vector_meta_account, &vector_meta, pages, &vector_accounts, program_id)?;
+    check_provisioned(&vector_meta)?;
 
     if vector_meta.length < num_elements{
         msg!("Not enough elements to pop");
         return Err(VectorError::PopFromEmpty.into());
     }
 
-    let mut ret = Vec::with_capacity(num_elements as usize);
-
     let new_length = vector_meta.length - num_elements;
-    let start = new_length + 1;
-
-    let mut vector_accounts_index = (start / vector_meta.max_elements_per_account) as usize;
-    let mut vector_data = vector_accounts[vector_accounts_index].data.borrow_mut();
-    let mut vector_data_index = ((start % vector_meta.max_elements_per_account) * vector_meta.element_size) as usize;
-
-    for _x in 0..num_elements{
-        ret.push(vector_data[vector_data_index..(vector_data_index + vector_meta.element_size as usize)].to_vec());
-        vector_data_index += vector_meta.element_size as usize;
-        if vector_data_index as u64 >= vector_meta.max_bytes_per_account{
-            vector_accounts_index += 1;
-            vector_data = vector_accounts[vector_accounts_index].data.borrow_mut();
-            vector_data_index = 0;
-        }
-    }
+
+    let vector_storage = PagedStorage::new(pages, &vector_accounts, vector_meta.element_size, vector_meta.max_elements_per_account, vector_meta.data_header_len())?;
+    let ret = vector_storage.read_range(new_length, num_elements)?;
 
     vector_meta.length = new_length;
     vector_meta.serialize(&mut *vector_meta_account.data.borrow_mut())?;
@@ -339,12 +767,16 @@ pub fn pop_slice(
 
 pub fn pop(
     accounts: &[AccountInfo],
+    pages: &[u32],
+    program_id: &Pubkey,
 ) -> Result<Vec<u8>, ProgramError> {
 
-    Ok((pop_slice(
+    pop_slice(
         accounts,
-        1
-    )?).pop().ok_or(ProgramError::InvalidArgument)?)
+        1,
+        pages,
+        program_id,
+    )?.pop().ok_or(ProgramError::InvalidArgument)
 
 }
 
@@ -352,6 +784,8 @@ pub fn slice(
     accounts: &[AccountInfo],
     start: u64,
     end: u64,
+    pages: &[u32],
+    program_id: &Pubkey,
 ) -> Result<Vec<Vec<u8>>, ProgramError> {
 
     let account_info_iter = &mut accounts.iter().peekable();
@@ -363,53 +797,46 @@ pub fn slice(
         vector_accounts.push(next_account_info(account_info_iter)?);
     }
 
+    check_meta_account(vector_meta_account, program_id)?;
     let vector_meta = VectorMeta::try_from_slice(&vector_meta_account.data.borrow())?;
+    check_vector_accounts(vector_meta_account, &vector_meta, pages, &vector_accounts, program_id)?;
+    check_provisioned(&vector_meta)?;
 
-    if start >= vector_meta.length || end >= vector_meta.length || start > end {
+    if start > end || end > vector_meta.length {
         msg!("Index Out of Bounds");
         return Err(VectorError::IndexOutofBounds.into());
     }
 
-    let num_elements = end - start;
-
-    let mut ret = Vec::with_capacity(num_elements as usize);
-
-    let mut vector_accounts_index = (start / vector_meta.max_elements_per_account) as usize;
-    let mut vector_data = vector_accounts[vector_accounts_index].data.borrow_mut();
-    let mut vector_data_index = ((start % vector_meta.max_elements_per_account) * vector_meta.element_size) as usize;
-
-    for _x in 0..num_elements{
-        ret.push(vector_data[vector_data_index..(vector_data_index + vector_meta.element_size as usize)].to_vec());
-        vector_data_index += vector_meta.element_size as usize;
-        if vector_data_index as u64 >= vector_meta.max_bytes_per_account{
-            vector_accounts_index += 1;
-            vector_data = vector_accounts[vector_accounts_index].data.borrow_mut();
-            vector_data_index = 0;
-        }
-    }
-
-    Ok(ret)
+    let vector_storage = PagedStorage::new(pages, &vector_accounts, vector_meta.element_size, vector_meta.max_elements_per_account, vector_meta.data_header_len())?;
+    vector_storage.read_range(start, end - start)
 }
 
 pub fn get(
     accounts: &[AccountInfo],
     index: u64,
+    pages: &[u32],
+    program_id: &Pubkey,
 ) -> Result<Vec<u8>, ProgramError> {
-    Ok((slice(
+    slice(
         accounts,
         index,
-        index + 1,
-    )?).pop().ok_or(ProgramError::InvalidArgument)?)
+        index.checked_add(1).ok_or(VectorError::IndexOutofBounds)?,
+        pages,
+        program_id,
+    )?.pop().ok_or(ProgramError::InvalidArgument)
 }
 
 pub fn remove_slice(
     accounts: &[AccountInfo],
     start: u64,
     end: u64,
+    pages: &[u32],
+    program_id: &Pubkey,
 ) -> Result<Vec<Vec<u8>>, ProgramError> {
 
     let account_info_iter = &mut accounts.iter().peekable();
 
+    let auth = next_account_info(account_info_iter)?;
     let vector_meta_account = next_account_info(account_info_iter)?;
 
     let mut vector_accounts = Vec::new();
@@ -417,54 +844,24 @@ pub fn remove_slice(
         vector_accounts.push(next_account_info(account_info_iter)?);
     }
 
+    check_meta_account(vector_meta_account, program_id)?;
     let mut vector_meta = VectorMeta::try_from_slice(&vector_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&vector_meta, &mut vector_accounts, program_id)?;
+    check_role(&vector_meta, auth, Role::Popper)?;
+    check_vector_accounts(vector_meta_account, &vector_meta, pages, &vector_accounts, program_id)?;
+    check_provisioned(&vector_meta)?;
 
-    if start >= vector_meta.length || end >= vector_meta.length || start > end {
+    if start > end || end > vector_meta.length {
         msg!("Index Out of Bounds");
         return Err(VectorError::IndexOutofBounds.into());
     }
 
-    let mut vector_account_refs = Vec::with_capacity(vector_accounts.len());
-    for i in 0..vector_accounts.len(){
-        vector_account_refs.push(vector_accounts[i].data.borrow_mut());
-    }
-
     let num_elements = end - start;
+    let new_length = vector_meta.length - num_elements;
 
-    let mut ret = Vec::with_capacity(num_elements as usize);
-
-    let mut vector_accounts_index = (start / vector_meta.max_elements_per_account) as usize;
-    let mut vector_data_index = ((start % vector_meta.max_elements_per_account) * vector_meta.element_size) as usize;
-    for _x in 0..num_elements{
-        ret.push(vector_account_refs[vector_accounts_index][vector_data_index..(vector_data_index + vector_meta.element_size as usize)].to_vec());
-        vector_data_index += vector_meta.element_size as usize;
-        if vector_data_index as u64 >= vector_meta.max_bytes_per_account{
-            vector_accounts_index += 1;
-            vector_data_index = 0;
-        }
-    }
-
-    let new_length = vector_meta.length - num_elements;
-
-    let mut vector_accounts_index_a = (start / vector_meta.max_elements_per_account) as usize;
-    let mut vector_data_index_a = ((start % vector_meta.max_elements_per_account) * vector_meta.element_size) as usize;
-    let mut vector_accounts_index_b = (end / vector_meta.max_elements_per_account) as usize;
-    let mut vector_data_index_b = ((end % vector_meta.max_elements_per_account) * vector_meta.element_size) as usize;
-    for _x in 0..(new_length - start) * vector_meta.element_size{
-        vector_account_refs[vector_accounts_index_a][vector_data_index_a] = vector_account_refs[vector_accounts_index_b][vector_data_index_b];
-
-        vector_data_index_a += 1;
-        if vector_data_index_a as u64 >= vector_meta.max_bytes_per_account{
-            vector_accounts_index_a += 1;
-            vector_data_index_a = 0;
-        }
-
-        vector_data_index_b += 1;
-        if vector_data_index_b as u64 >= vector_meta.max_bytes_per_account{
-            vector_accounts_index_b += 1;
-            vector_data_index_b = 0;
-        }
-    }
+    let mut vector_storage = PagedStorage::new(pages, &vector_accounts, vector_meta.element_size, vector_meta.max_elements_per_account, vector_meta.data_header_len())?;
+    let ret = vector_storage.read_range(start, num_elements)?;
+    vector_storage.copy_range(end, start, vector_meta.length - end)?;
 
     vector_meta.length = new_length;
     vector_meta.serialize(&mut *vector_meta_account.data.borrow_mut())?;
@@ -475,41 +872,1010 @@ pub fn remove_slice(
 pub fn remove(
     accounts: &[AccountInfo],
     index: u64,
+    pages: &[u32],
+    program_id: &Pubkey,
 ) -> Result<Vec<u8>, ProgramError> {
-    Ok((remove_slice(
+    remove_slice(
         accounts,
         index,
-        index + 1,
-    )?).pop().ok_or(ProgramError::InvalidArgument)?)
+        index.checked_add(1).ok_or(VectorError::IndexOutofBounds)?,
+        pages,
+        program_id,
+    )?.pop().ok_or(ProgramError::InvalidArgument)
+}
+
+/// Removes the element at `index` and moves the last element into its place, so only two elements
+/// are touched. The order of the elements is not kept.
+pub fn swap_remove(
+    accounts: &[AccountInfo],
+    index: u64,
+    pages: &[u32],
+    program_id: &Pubkey,
+) -> Result<Vec<u8>, ProgramError> {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+
+    let auth = next_account_info(account_info_iter)?;
+    let vector_meta_account = next_account_info(account_info_iter)?;
+
+    let mut vector_accounts = Vec::new();
+    while account_info_iter.peek().is_some(){
+        vector_accounts.push(next_account_info(account_info_iter)?);
+    }
+
+    check_meta_account(vector_meta_account, program_id)?;
+    let mut vector_meta = VectorMeta::try_from_slice(&vector_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&vector_meta, &mut vector_accounts, program_id)?;
+    check_role(&vector_meta, auth, Role::Popper)?;
+    check_vector_accounts(vector_meta_account, &vector_meta, pages, &vector_accounts, program_id)?;
+    check_provisioned(&vector_meta)?;
+
+    if index >= vector_meta.length {
+        msg!("Index Out of Bounds");
+        return Err(VectorError::IndexOutofBounds.into());
+    }
+
+    let last = vector_meta.length - 1;
+
+    let mut vector_storage = PagedStorage::new(pages, &vector_accounts, vector_meta.element_size, vector_meta.max_elements_per_account, vector_meta.data_header_len())?;
+    let ret = vector_storage.read(index)?;
+    vector_storage.copy_range(last, index, 1)?;
+
+    vector_meta.length = last;
+    vector_meta.serialize(&mut *vector_meta_account.data.borrow_mut())?;
+
+    Ok(ret)
+}
+
+/// Grows the data accounts passed towards their full size by up to `MAX_PERMITTED_DATA_INCREASE`
+/// bytes, the most a program may allocate per account in one instruction, with the signer paying
+/// the rent. Large vectors need several `Extend` instructions after `Initialize` before they can be
+/// used. Data accounts that do not exist yet are created.
+pub fn extend(
+    accounts: &[AccountInfo],
+    pages: &[u32],
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let vector_meta_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let mut vector_accounts = Vec::new();
+    while account_info_iter.peek().is_some(){
+        vector_accounts.push(next_account_info(account_info_iter)?);
+    }
+
+    check_meta_account(vector_meta_account, program_id)?;
+    let mut vector_meta = VectorMeta::try_from_slice(&vector_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&vector_meta, &mut vector_accounts, program_id)?;
+    check_role(&vector_meta, auth, Role::Admin)?;
+    check_vector_accounts(vector_meta_account, &vector_meta, pages, &vector_accounts, program_id)?;
+
+    for (&page, &vector_account) in pages.iter().zip(vector_accounts.iter()){
+        let target = vector_meta.data_header_len() + vector_meta.account_bytes(page as u64);
+        vector_meta.provisioned_bytes += provision_vector_account(auth, system_program, vector_meta_account, &vector_meta, vector_account, page, target, program_id)?;
+    }
+
+    vector_meta.serialize(&mut *vector_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Grows the vector to `max_length` elements in place. The data accounts passed are created or
+/// grown by up to `MAX_PERMITTED_DATA_INCREASE` bytes, `Extend` provisions the rest before the
+/// vector can be used again. The meta keeps the address derived from the max length it was created
+/// with.
+pub fn resize(
+    accounts: &[AccountInfo],
+    max_length: u64,
+    pages: &[u32],
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let vector_meta_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+    let mut vector_accounts = Vec::new();
+    while account_info_iter.peek().is_some(){
+        vector_accounts.push(next_account_info(account_info_iter)?);
+    }
+
+    check_meta_account(vector_meta_account, program_id)?;
+    let mut vector_meta = VectorMeta::try_from_slice(&vector_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&vector_meta, &mut vector_accounts, program_id)?;
+
+    // only the authority itself may resize the vector
+    check_authority(&vector_meta, auth)?;
+    check_provisioned(&vector_meta)?;
+
+    if max_length < vector_meta.max_length{
+        msg!("Resize cannot shrink the vector");
+        return Err(VectorError::InvalidParameters.into());
+    }
+    check_parameters(max_length, vector_meta.element_size)?;
+
+    vector_meta.max_length = max_length;
+    check_num_accounts(&vector_meta)?;
+    check_vector_accounts(vector_meta_account, &vector_meta, pages, &vector_accounts, program_id)?;
+
+    // a lazy vector creates its new data accounts when a push first needs them
+    if !vector_meta.lazy{
+        for (&page, &vector_account) in pages.iter().zip(vector_accounts.iter()){
+            let target = vector_meta.data_header_len() + vector_meta.account_bytes(page as u64);
+            vector_meta.provisioned_bytes += provision_vector_account(auth, system_program, vector_meta_account, &vector_meta, vector_account, page, target, program_id)?;
+        }
+    }
+
+    vector_meta.serialize(&mut *vector_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Shrinks the vector to `max_length` elements, which must still hold its elements; shrinking to the
+/// current length fits it to its contents, an empty vector keeps room for one element. Data accounts past the new capacity are closed, the
+/// last one kept is truncated, and the freed rent goes to the authority. Takes the data accounts
+/// from the last one kept on.
+pub fn shrink(
+    accounts: &[AccountInfo],
+    max_length: u64,
+    pages: &[u32],
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let vector_meta_account = next_account_info(account_info_iter)?;
+    let mut vector_accounts = Vec::new();
+    while account_info_iter.peek().is_some(){
+        vector_accounts.push(next_account_info(account_info_iter)?);
+    }
+
+    check_meta_account(vector_meta_account, program_id)?;
+    let mut vector_meta = VectorMeta::try_from_slice(&vector_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&vector_meta, &mut vector_accounts, program_id)?;
+
+    // only the authority itself may shrink the vector
+    check_authority(&vector_meta, auth)?;
+    check_vector_accounts(vector_meta_account, &vector_meta, pages, &vector_accounts, program_id)?;
+
+    // the paging arithmetic needs a capacity of at least one element
+    let max_length = max(max_length, 1);
+    if max_length > vector_meta.max_length || max_length < vector_meta.length{
+        msg!("Shrink cannot grow the vector or drop elements");
+        return Err(VectorError::InvalidParameters.into());
+    }
+    check_parameters(max_length, vector_meta.element_size)?;
+
+    let old_num_accounts = vector_meta.num_accounts();
+    vector_meta.max_length = max_length;
+    let num_accounts = vector_meta.num_accounts();
+    if !(num_accounts - 1..old_num_accounts).all(|page| pages.contains(&(page as u32))){
+        msg!("Shrink takes the vector accounts from {} to {}", num_accounts - 1, old_num_accounts - 1);
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+
+    let rent = Rent::get()?;
+    for (&page, &vector_account) in pages.iter().zip(vector_accounts.iter()){
+        let provisioned = (vector_account.data_len() as u64).saturating_sub(vector_meta.data_header_len());
+        if page as u64 >= num_accounts{
+            // uncreated data accounts have nothing to close
+            if vector_account.owner == program_id{
+                close_account(vector_account, auth)?;
+            }
+            vector_meta.provisioned_bytes = vector_meta.provisioned_bytes.saturating_sub(provisioned);
+            continue;
+        }
+        let size = vector_meta.data_header_len() + vector_meta.account_bytes(page as u64);
+        if (vector_account.data_len() as u64) > size{
+            vector_account.realloc(size as usize, false)?;
+
+            let refund = vector_account.lamports().saturating_sub(rent.minimum_balance(size as usize));
+            let auth_lamports = auth.lamports().checked_add(refund).ok_or(VectorError::Overflow)?;
+            **vector_account.try_borrow_mut_lamports()? -= refund;
+            **auth.try_borrow_mut_lamports()? = auth_lamports;
+            vector_meta.provisioned_bytes = vector_meta.provisioned_bytes.saturating_sub(provisioned - vector_meta.account_bytes(page as u64));
+        }
+    }
+
+    vector_meta.serialize(&mut *vector_meta_account.data.borrow_mut())?;
+
+    Ok(())
 }
 
 pub fn delete(
     accounts: &[AccountInfo],
+    pages: &[u32],
+    program_id: &Pubkey,
 ) -> ProgramResult{
 
     let account_info_iter = &mut accounts.iter().peekable();
     let auth = next_account_info(account_info_iter)?;
+    let destination = next_account_info(account_info_iter)?;
     let vector_meta_account = next_account_info(account_info_iter)?;
     let mut vector_accounts = Vec::new();
     while account_info_iter.peek().is_some(){
         vector_accounts.push(next_account_info(account_info_iter)?);
     }
 
-    let mut auth_lamports = auth.lamports.borrow_mut();
-    let mut vector_meta_lamports = vector_meta_account.lamports.borrow_mut();
+    check_meta_account(vector_meta_account, program_id)?;
+    let vector_meta = VectorMeta::try_from_slice(&vector_meta_account.data.borrow())?;
+    check_cpi_only_accounts(&vector_meta, &mut vector_accounts, program_id)?;
+    check_vector_accounts(vector_meta_account, &vector_meta, pages, &vector_accounts, program_id)?;
+    // the data accounts cannot be closed without the meta, vectors with more of them than fit in
+    // a transaction are shrunk first
+    if pages.len() as u64 != vector_meta.num_accounts(){
+        msg!("Delete takes every vector account");
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+
+    // only the authority itself may close the vector
+    check_authority(&vector_meta, auth)?;
 
-    **auth_lamports = auth_lamports.
-        checked_add(**vector_meta_lamports)
-        .ok_or(VectorError::Overflow)?;
-    **vector_meta_lamports = 0;
+    if destination.key == vector_meta_account.key || vector_accounts.iter().any(|vector_account| vector_account.key == destination.key){
+        msg!("Destination cannot be one of the vector accounts");
+        return Err(ProgramError::InvalidArgument);
+    }
 
-    for i in 0..vector_accounts.len(){
-        let mut account_lamports = vector_accounts[i].lamports.borrow_mut();
-        **auth_lamports = auth_lamports
-                .checked_add(**account_lamports)
-                .ok_or(VectorError::Overflow)?;
-        **account_lamports = 0;
+    close_account(vector_meta_account, destination)?;
+    for vector_account in vector_accounts.iter(){
+        // uncreated data accounts have nothing to close
+        if vector_account.owner == program_id{
+            close_account(vector_account, destination)?;
+        }
     }
 
     Ok(())
 }
+
+pub fn set_delegate(
+    accounts: &[AccountInfo],
+    delegate: Pubkey,
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let vector_meta_account = next_account_info(account_info_iter)?;
+
+    check_meta_account(vector_meta_account, program_id)?;
+    let mut vector_meta = VectorMeta::try_from_slice(&vector_meta_account.data.borrow())?;
+    check_cpi_only(&vector_meta, account_info_iter.next(), program_id)?;
+
+    // only the authority itself may change the delegate
+    check_authority(&vector_meta, auth)?;
+
+    vector_meta.delegate = delegate;
+    vector_meta.serialize(&mut *vector_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Empties the vector. The data accounts keep their contents until elements are pushed over them.
+pub fn clear(
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let vector_meta_account = next_account_info(account_info_iter)?;
+
+    check_meta_account(vector_meta_account, program_id)?;
+    let mut vector_meta = VectorMeta::try_from_slice(&vector_meta_account.data.borrow())?;
+    check_cpi_only(&vector_meta, account_info_iter.next(), program_id)?;
+
+    // only the authority itself may clear the vector
+    check_authority(&vector_meta, auth)?;
+
+    vector_meta.length = 0;
+    vector_meta.serialize(&mut *vector_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Reads the meta `Migrate` rewrites and checks that `auth` may rewrite it. A version 0 meta comes
+/// back with `auth` as its authority and fully provisioned.
+fn read_meta_to_migrate(
+    auth: &AccountInfo,
+    vector_meta_account: &AccountInfo,
+    program_id: &Pubkey,
+) -> Result<(VectorMeta, u8), ProgramError> {
+    if vector_meta_account.owner != program_id{
+        msg!("Invalid vector meta account");
+        return Err(VectorError::InvalidMetaAccount.into());
+    }
+    let (mut vector_meta, version) = VectorMeta::from_any_version(&vector_meta_account.data.borrow())?;
+    if version == LAYOUT_VERSION{
+        msg!("Vector meta is already at layout version {}", LAYOUT_VERSION);
+        return Ok((vector_meta, version));
+    }
+
+    if version == 0{
+        if !auth.is_signer{
+            msg!("Missing authority signature");
+            return Err(VectorError::Unauthorized.into());
+        }
+        // a version 0 meta is told apart by its length alone, so it has to be at the PDA the first
+        // release derived from its authority and sizes, which also makes the signer that authority
+        let (vector_meta_key, _) = Pubkey::find_program_address(
+            &[auth.key.as_ref(), &vector_meta.max_length.to_le_bytes(), &vector_meta.element_size.to_le_bytes()],
+            program_id,
+        );
+        if *vector_meta_account.key != vector_meta_key{
+            msg!("Vector meta account is not the version 0 meta of {}", auth.key);
+            return Err(VectorError::InvalidMetaAccount.into());
+        }
+        if vector_meta.max_bytes_per_account == 0{
+            msg!("Vector meta was never initialized");
+            return Err(VectorError::InvalidMetaAccount.into());
+        }
+        vector_meta.authority = *auth.key;
+        // the first release created every data account at its full size in Initialize
+        vector_meta.provisioned_bytes = vector_meta.max_bytes();
+        msg!("Set the authority to {}, the data accounts keep layout version 0", auth.key);
+    } else {
+        check_authority(&vector_meta, auth)?;
+    }
+
+    Ok((vector_meta, version))
+}
+
+/// Rewrites a meta written in an earlier layout version in the current one, growing the meta
+/// account with `authority` paying the rent, and returns the version it was written in. Fields
+/// that version did not have start zeroed. Version 0 metas predate authorities, so the signer
+/// becomes the authority and has to be the one the `[authority, max_length, element_size]` meta
+/// PDA was derived from; their data accounts keep their layout and the `[meta key, index as u8]`
+/// PDAs.
+pub fn migrate(
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+) -> Result<u8, ProgramError> {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let vector_meta_account = next_account_info(account_info_iter)?;
+    let system_program = next_account_info(account_info_iter)?;
+
+    let (vector_meta, version) = read_meta_to_migrate(auth, vector_meta_account, program_id)?;
+    if version == LAYOUT_VERSION{
+        return Ok(version);
+    }
+
+    msg!("Grew the vector meta from {} to {} bytes", vector_meta_account.data_len(), VECTOR_META_LEN);
+    grow_account(auth, system_program, vector_meta_account, VECTOR_META_LEN, &Rent::get()?)?;
+    vector_meta.serialize(&mut *vector_meta_account.data.borrow_mut())?;
+
+    Ok(version)
+}
+
+/// Proposes `new_authority` as the authority of the vector. It takes over, along with the rent refunds,
+/// once it signs `AcceptAuthority`. Proposing the default pubkey withdraws the proposal.
+pub fn set_authority(
+    accounts: &[AccountInfo],
+    new_authority: Pubkey,
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let vector_meta_account = next_account_info(account_info_iter)?;
+
+    check_meta_account(vector_meta_account, program_id)?;
+    let mut vector_meta = VectorMeta::try_from_slice(&vector_meta_account.data.borrow())?;
+    check_cpi_only(&vector_meta, account_info_iter.next(), program_id)?;
+
+    // only the authority itself may hand the vector over
+    check_authority(&vector_meta, auth)?;
+
+    vector_meta.pending_authority = new_authority;
+    vector_meta.serialize(&mut *vector_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Makes the proposed authority, which must sign, the authority of the vector. The delegate of the
+/// previous authority is cleared.
+pub fn accept_authority(
+    accounts: &[AccountInfo],
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let new_auth = next_account_info(account_info_iter)?;
+    let vector_meta_account = next_account_info(account_info_iter)?;
+
+    check_meta_account(vector_meta_account, program_id)?;
+    let mut vector_meta = VectorMeta::try_from_slice(&vector_meta_account.data.borrow())?;
+    check_cpi_only(&vector_meta, account_info_iter.next(), program_id)?;
+
+    if vector_meta.pending_authority == Pubkey::default() || !new_auth.is_signer || *new_auth.key != vector_meta.pending_authority{
+        msg!("Missing signature of the proposed authority");
+        return Err(VectorError::Unauthorized.into());
+    }
+
+    vector_meta.authority = vector_meta.pending_authority;
+    vector_meta.pending_authority = Pubkey::default();
+    vector_meta.delegate = Pubkey::default();
+    vector_meta.serialize(&mut *vector_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Adds `member` to the allowlist of `role`.
+pub fn grant_role(
+    accounts: &[AccountInfo],
+    role: Role,
+    member: Pubkey,
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let vector_meta_account = next_account_info(account_info_iter)?;
+
+    check_meta_account(vector_meta_account, program_id)?;
+    let mut vector_meta = VectorMeta::try_from_slice(&vector_meta_account.data.borrow())?;
+    check_cpi_only(&vector_meta, account_info_iter.next(), program_id)?;
+    check_role_manager(&vector_meta, auth, role)?;
+
+    if member == Pubkey::default(){
+        msg!("Cannot grant a role to the default pubkey");
+        return Err(VectorError::InvalidParameters.into());
+    }
+    if !vector_meta.roles.grant(role, member){
+        msg!("The {:?} allowlist is full", role);
+        return Err(VectorError::RoleListFull.into());
+    }
+    vector_meta.serialize(&mut *vector_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Removes `member` from the allowlist of `role`, if it is on it.
+pub fn revoke_role(
+    accounts: &[AccountInfo],
+    role: Role,
+    member: Pubkey,
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let vector_meta_account = next_account_info(account_info_iter)?;
+
+    check_meta_account(vector_meta_account, program_id)?;
+    let mut vector_meta = VectorMeta::try_from_slice(&vector_meta_account.data.borrow())?;
+    check_cpi_only(&vector_meta, account_info_iter.next(), program_id)?;
+    check_role_manager(&vector_meta, auth, role)?;
+
+    vector_meta.roles.revoke(role, &member);
+    vector_meta.serialize(&mut *vector_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Sets whether only other programs may change the vector, e.g. the program whose PDA is the
+/// authority. Reads stay open to everyone. Once set, clearing it takes a call from another program
+/// as well.
+pub fn set_cpi_only(
+    accounts: &[AccountInfo],
+    cpi_only: bool,
+    program_id: &Pubkey,
+) -> ProgramResult {
+
+    let account_info_iter = &mut accounts.iter().peekable();
+    let auth = next_account_info(account_info_iter)?;
+    let vector_meta_account = next_account_info(account_info_iter)?;
+
+    check_meta_account(vector_meta_account, program_id)?;
+    let mut vector_meta = VectorMeta::try_from_slice(&vector_meta_account.data.borrow())?;
+    check_cpi_only(&vector_meta, account_info_iter.next(), program_id)?;
+
+    // only the authority itself may restrict the callers
+    check_authority(&vector_meta, auth)?;
+
+    vector_meta.cpi_only = cpi_only;
+    vector_meta.serialize(&mut *vector_meta_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+/// Address and bump of the meta account of the vector `authority` creates with these parameters.
+/// `id` tells apart the vectors of one authority that share their sizes.
+pub fn find_meta_address(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    max_length: u64,
+    element_size: u64,
+    id: u64,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[authority.as_ref(), &max_length.to_le_bytes(), &element_size.to_le_bytes(), &id.to_le_bytes()], program_id)
+}
+
+/// Page indices and addresses of the data accounts at `pages` of the vector with meta account `meta`,
+/// as the builders take them. The seed of each one is its page index as a little-endian u32.
+pub fn find_data_account_addresses(
+    program_id: &Pubkey,
+    meta: &Pubkey,
+    pages: &[u32],
+) -> Vec<(u32, Pubkey)> {
+    pages.iter().map(|&page| (page, Pubkey::find_program_address(&[meta.as_ref(), &page.to_le_bytes()], program_id).0)).collect()
+}
+
+/// Number of data accounts backing `max_length` elements of `element_size` bytes, in a lazy
+/// vector if `lazy`.
+pub fn num_data_accounts(
+    max_length: u64,
+    element_size: u64,
+    lazy: bool,
+) -> u64 {
+    let account_size = if lazy { LAZY_ACCOUNT_SIZE } else { MAX_ACCOUNT_SIZE };
+    let max_bytes_per_account = ((account_size - DATA_HEADER_LEN) / element_size) * element_size;
+    (max_length * element_size + max_bytes_per_account - 1) / max_bytes_per_account
+}
+
+/// Creates the vector with its meta and the data accounts at `pages`, paid for by `authority`; `Extend`
+/// creates the others. A `lazy` vector creates its data accounts as pushes need them instead, with the
+/// pusher paying.
+pub fn build_initialize(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    max_length: u64,
+    element_size: u64,
+    lazy: bool,
+    id: u64,
+    pages: &[u32],
+) -> SolInstruction {
+    let (meta, _) = find_meta_address(program_id, authority, max_length, element_size, id);
+    let data_accounts = find_data_account_addresses(program_id, &meta, pages);
+
+    let mut account_metas = vec![
+        AccountMeta::new(*authority, true),
+        AccountMeta::new(meta, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new_readonly(sysvar::rent::id(), false),
+    ];
+    account_metas.extend(data_accounts.iter().map(|(_, key)| AccountMeta::new(*key, false)));
+
+    let instruction = Instruction::Initialize(InitializeParams{
+        max_length,
+        element_size,
+        lazy,
+        id,
+    });
+    SolInstruction::new_with_bytes(*program_id, &instruction.pack_with_pages(pages), account_metas)
+}
+
+/// Page indices of `data_accounts`, which the instruction data carries after the instruction.
+fn page_indices(
+    data_accounts: &[(u32, Pubkey)],
+) -> Vec<u32> {
+    data_accounts.iter().map(|(page, _)| *page).collect()
+}
+
+fn mutating_account_metas(
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+) -> Vec<AccountMeta> {
+    let mut account_metas = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    account_metas.extend(data_accounts.iter().map(|(_, key)| AccountMeta::new(*key, false)));
+    account_metas
+}
+
+/// Like `mutating_account_metas` for pushes to a lazy vector, which may create data accounts with the
+/// authority paying.
+fn lazy_push_account_metas(
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+) -> Vec<AccountMeta> {
+    let mut account_metas = vec![
+        AccountMeta::new(*authority, true),
+        AccountMeta::new(*meta, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    account_metas.extend(data_accounts.iter().map(|(_, key)| AccountMeta::new(*key, false)));
+    account_metas
+}
+
+fn read_only_account_metas(
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+) -> Vec<AccountMeta> {
+    let mut account_metas = vec![AccountMeta::new_readonly(*meta, false)];
+    account_metas.extend(data_accounts.iter().map(|(_, key)| AccountMeta::new_readonly(*key, false)));
+    account_metas
+}
+
+/// Pushes the elements in `data` onto the end.
+pub fn build_push(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    data: &[u8],
+) -> SolInstruction {
+    let instruction_data = Instruction::Push(PushParams{ data: data.to_vec() }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, mutating_account_metas(authority, meta, data_accounts))
+}
+
+/// `build_push` for a lazy vector, creating data accounts as needed with `authority` paying.
+pub fn build_push_lazy(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    data: &[u8],
+) -> SolInstruction {
+    let instruction_data = Instruction::Push(PushParams{ data: data.to_vec() }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, lazy_push_account_metas(authority, meta, data_accounts))
+}
+
+/// Inserts the elements packed in `data` at `index`.
+pub fn build_insert(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    index: u64,
+    data: &[u8],
+) -> SolInstruction {
+    let instruction_data = Instruction::Insert(InsertParams{ index, data: data.to_vec() }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, mutating_account_metas(authority, meta, data_accounts))
+}
+
+/// `build_insert` for a lazy vector, creating data accounts as needed with `authority` paying.
+pub fn build_insert_lazy(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    index: u64,
+    data: &[u8],
+) -> SolInstruction {
+    let instruction_data = Instruction::Insert(InsertParams{ index, data: data.to_vec() }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, lazy_push_account_metas(authority, meta, data_accounts))
+}
+
+/// Pops `num_elements` elements from the end.
+pub fn build_pop(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    num_elements: u64,
+) -> SolInstruction {
+    let instruction_data = Instruction::Pop(PopParams{ num_elements }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, mutating_account_metas(authority, meta, data_accounts))
+}
+
+/// Reads the elements `start..end`. Read-only, the authority does not sign.
+pub fn build_get(
+    program_id: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    start: u64,
+    end: u64,
+) -> SolInstruction {
+    let instruction_data = Instruction::Get(GetParams{ start, end }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, read_only_account_metas(meta, data_accounts))
+}
+
+/// Removes the elements `start..end`.
+pub fn build_remove(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    start: u64,
+    end: u64,
+) -> SolInstruction {
+    let instruction_data = Instruction::Remove(GetParams{ start, end }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, mutating_account_metas(authority, meta, data_accounts))
+}
+
+/// Removes the element at `index`, moving the last element into its place.
+pub fn build_swap_remove(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    index: u64,
+) -> SolInstruction {
+    let instruction_data = Instruction::SwapRemove(SwapRemoveParams{ index }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, mutating_account_metas(authority, meta, data_accounts))
+}
+
+/// Overwrites the elements from `index` on with the elements packed in `data`.
+pub fn build_set(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    index: u64,
+    data: &[u8],
+) -> SolInstruction {
+    let instruction_data = Instruction::Set(SetParams{ index, data: data.to_vec() }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, mutating_account_metas(authority, meta, data_accounts))
+}
+
+/// Closes the vector and moves its lamports to `destination`. `data_accounts` lists every data account.
+pub fn build_delete(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    destination: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+) -> SolInstruction {
+    let mut account_metas = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*destination, false),
+        AccountMeta::new(*meta, false),
+    ];
+    account_metas.extend(data_accounts.iter().map(|(_, key)| AccountMeta::new(*key, false)));
+    SolInstruction::new_with_bytes(*program_id, &Instruction::Delete.pack_with_pages(&page_indices(data_accounts)), account_metas)
+}
+
+pub fn build_set_delegate(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    delegate: &Pubkey,
+) -> SolInstruction {
+    let instruction_data = Instruction::SetDelegate(SetDelegateParams{ delegate: *delegate }).pack();
+    let account_metas = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, account_metas)
+}
+
+/// Grows `data_accounts` by up to `MAX_PERMITTED_DATA_INCREASE` bytes each, creating the ones that
+/// do not exist yet, paid by `authority`.
+pub fn build_extend(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+) -> SolInstruction {
+    let mut account_metas = vec![
+        AccountMeta::new(*authority, true),
+        AccountMeta::new(*meta, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    account_metas.extend(data_accounts.iter().map(|(_, key)| AccountMeta::new(*key, false)));
+    SolInstruction::new_with_bytes(*program_id, &Instruction::Extend.pack_with_pages(&page_indices(data_accounts)), account_metas)
+}
+
+/// Grows the vector to `max_length` elements. `data_accounts` lists data accounts of the grown
+/// vector to create or grow, see `find_data_account_addresses`.
+pub fn build_resize(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    max_length: u64,
+) -> SolInstruction {
+    let mut account_metas = vec![
+        AccountMeta::new(*authority, true),
+        AccountMeta::new(*meta, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    account_metas.extend(data_accounts.iter().map(|(_, key)| AccountMeta::new(*key, false)));
+    let instruction_data = Instruction::Resize(ResizeParams{ max_length }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, account_metas)
+}
+
+/// Shrinks the vector to `max_length` elements and refunds the freed rent to `authority`.
+/// `data_accounts` lists the data accounts from the last one kept on, the ones past the new
+/// capacity are closed.
+pub fn build_shrink(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    data_accounts: &[(u32, Pubkey)],
+    max_length: u64,
+) -> SolInstruction {
+    let mut account_metas = vec![
+        AccountMeta::new(*authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    account_metas.extend(data_accounts.iter().map(|(_, key)| AccountMeta::new(*key, false)));
+    let instruction_data = Instruction::Shrink(ResizeParams{ max_length }).pack_with_pages(&page_indices(data_accounts));
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, account_metas)
+}
+
+/// Empties the vector.
+pub fn build_clear(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+) -> SolInstruction {
+    let account_metas = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    SolInstruction::new_with_bytes(*program_id, &Instruction::Clear.pack(), account_metas)
+}
+
+/// Rewrites the meta in the current layout, `authority` paying for the larger meta account.
+pub fn build_migrate(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+) -> SolInstruction {
+    let account_metas = vec![
+        AccountMeta::new(*authority, true),
+        AccountMeta::new(*meta, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    SolInstruction::new_with_bytes(*program_id, &Instruction::Migrate.pack(), account_metas)
+}
+
+/// Proposes `new_authority` as the authority of the vector, see `build_accept_authority`.
+pub fn build_set_authority(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    new_authority: &Pubkey,
+) -> SolInstruction {
+    let instruction_data = Instruction::SetAuthority(SetAuthorityParams{ new_authority: *new_authority }).pack();
+    let account_metas = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, account_metas)
+}
+
+/// Makes `new_authority`, proposed by `build_set_authority`, the authority of the vector.
+pub fn build_accept_authority(
+    program_id: &Pubkey,
+    new_authority: &Pubkey,
+    meta: &Pubkey,
+) -> SolInstruction {
+    let account_metas = vec![
+        AccountMeta::new_readonly(*new_authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    SolInstruction::new_with_bytes(*program_id, &Instruction::AcceptAuthority.pack(), account_metas)
+}
+
+/// Adds `member` to the allowlist of `role`. `authority` is the authority, or an admin for the
+/// pusher and popper roles.
+pub fn build_grant_role(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    role: Role,
+    member: &Pubkey,
+) -> SolInstruction {
+    let instruction_data = Instruction::GrantRole(RoleParams{ role, member: *member }).pack();
+    let account_metas = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, account_metas)
+}
+
+/// Removes `member` from the allowlist of `role`, see `build_grant_role`.
+pub fn build_revoke_role(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    role: Role,
+    member: &Pubkey,
+) -> SolInstruction {
+    let instruction_data = Instruction::RevokeRole(RoleParams{ role, member: *member }).pack();
+    let account_metas = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, account_metas)
+}
+
+/// Sets whether only other programs may change the vector.
+pub fn build_set_cpi_only(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    meta: &Pubkey,
+    cpi_only: bool,
+) -> SolInstruction {
+    let instruction_data = Instruction::SetCpiOnly(SetCpiOnlyParams{ cpi_only }).pack();
+    let account_metas = vec![
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*meta, false),
+    ];
+    SolInstruction::new_with_bytes(*program_id, &instruction_data, account_metas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{VectorMetaV0, VECTOR_META_LENS};
+
+    const MAX_LENGTH: u64 = 3;
+    const ELEMENT_SIZE: u64 = 4;
+
+    /// A version 0 meta as the first release initialized it, with a single data account.
+    fn meta_v0() -> Vec<u8> {
+        VectorMetaV0{
+            max_length: MAX_LENGTH,
+            element_size: ELEMENT_SIZE,
+            length: 0,
+            max_elements_per_account: MAX_LENGTH,
+            max_bytes_per_account: MAX_LENGTH * ELEMENT_SIZE,
+        }.try_to_vec().unwrap()
+    }
+
+    fn meta_v0_key(program_id: &Pubkey, authority: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[authority.as_ref(), &MAX_LENGTH.to_le_bytes(), &ELEMENT_SIZE.to_le_bytes()], program_id).0
+    }
+
+    /// Runs the checks of `Migrate` with `signer` signing for the meta at `meta_key`.
+    fn read(program_id: &Pubkey, signer: &Pubkey, is_signer: bool, meta_key: &Pubkey, owner: &Pubkey, meta: &mut [u8]) -> Result<(VectorMeta, u8), ProgramError> {
+        let (mut auth_lamports, mut meta_lamports) = (0, 0);
+        let mut auth_data = vec![];
+        let auth = AccountInfo::new(signer, is_signer, true, &mut auth_lamports, &mut auth_data, &system_program::ID, false, 0);
+        let vector_meta_account = AccountInfo::new(meta_key, false, true, &mut meta_lamports, meta, owner, false, 0);
+        read_meta_to_migrate(&auth, &vector_meta_account, program_id)
+    }
+
+    #[test]
+    fn sets_authority_of_version_0_meta() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let meta_key = meta_v0_key(&program_id, &authority);
+
+        let (vector_meta, version) = read(&program_id, &authority, true, &meta_key, &program_id, &mut meta_v0()).unwrap();
+        assert_eq!(version, 0);
+        assert_eq!(vector_meta.authority, authority);
+        assert_eq!(vector_meta.provisioned_bytes, MAX_LENGTH * ELEMENT_SIZE);
+        assert!(vector_meta.legacy);
+    }
+
+    #[test]
+    fn rejects_wrong_signer() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let meta_key = meta_v0_key(&program_id, &authority);
+
+        let res = read(&program_id, &Pubkey::new_unique(), true, &meta_key, &program_id, &mut meta_v0());
+        assert_eq!(res.unwrap_err(), VectorError::InvalidMetaAccount.into());
+        let res = read(&program_id, &authority, false, &meta_key, &program_id, &mut meta_v0());
+        assert_eq!(res.unwrap_err(), VectorError::Unauthorized.into());
+
+        // a later layout already names its authority
+        let (mut vector_meta, _) = VectorMeta::from_any_version(&meta_v0()).unwrap();
+        vector_meta.version = 1;
+        vector_meta.authority = authority;
+        vector_meta.legacy = false;
+        let mut meta_v1 = vector_meta.try_to_vec().unwrap();
+        meta_v1.truncate(VECTOR_META_LENS[1] as usize);
+        let res = read(&program_id, &Pubkey::new_unique(), true, &meta_key, &program_id, &mut meta_v1.clone());
+        assert_eq!(res.unwrap_err(), VectorError::Unauthorized.into());
+        let (vector_meta, version) = read(&program_id, &authority, true, &meta_key, &program_id, &mut meta_v1).unwrap();
+        assert_eq!(version, 1);
+        assert_eq!(vector_meta.authority, authority);
+    }
+
+    #[test]
+    fn rejects_non_meta_account() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let meta_key = meta_v0_key(&program_id, &authority);
+
+        // any program owned account of the version 0 length, e.g. a data account
+        let res = read(&program_id, &authority, true, &Pubkey::new_unique(), &program_id, &mut meta_v0());
+        assert_eq!(res.unwrap_err(), VectorError::InvalidMetaAccount.into());
+        let res = read(&program_id, &authority, true, &meta_key, &Pubkey::new_unique(), &mut meta_v0());
+        assert_eq!(res.unwrap_err(), VectorError::InvalidMetaAccount.into());
+        let res = read(&program_id, &authority, true, &meta_key, &program_id, &mut [0; 16]);
+        assert!(res.is_err());
+    }
+}